@@ -0,0 +1,266 @@
+use std::{cmp, ops::Range};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime, Utc};
+use dely::{
+    domain::core::CoreEvent,
+    infrastructure::{
+        projection::{ProjectedEvent, Projection, ProjectionError},
+        sqlite::{self, CastAvailability, Reports},
+    },
+    Badges,
+};
+use meilisearch_sdk::indexes::Index;
+use serde::Serialize;
+use tracing::error;
+
+/// 女の子のドキュメントで絞り込みに使うバッジのフィールド
+pub const FILTERABLE_ATTRIBUTES: [&str; 1] = ["badges"];
+
+/// 一覧に表示する女の子のバッジ
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Badge {
+    /// 登録から間もない
+    #[serde(rename = "新人")]
+    Newcomer,
+    /// 本日の確定したシフトがまだ終わっていない
+    #[serde(rename = "本日出勤")]
+    WorkingToday,
+    /// 本日の空き時間が少ない
+    #[serde(rename = "残りわずか")]
+    FewSlotsLeft,
+}
+
+/// 女の子のドキュメントに部分更新で書き込むバッジのフィールド
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BadgeDocument {
+    id: u64,
+    badges: Vec<Badge>,
+}
+
+/// `now`を含む1日（`offset`のタイムゾーンで区切る）
+fn today(offset: FixedOffset, now: DateTime<Utc>) -> Range<DateTime<Utc>> {
+    let start = now - (now.with_timezone(&offset).time() - NaiveTime::default());
+    start..start + Duration::days(1)
+}
+
+/// 確定したシフトのうち、`now`以降で予約が入っていない時間
+fn remaining(availability: &CastAvailability, now: DateTime<Utc>) -> Duration {
+    availability
+        .shifts
+        .iter()
+        .map(|shift| {
+            let start = cmp::max(shift.start, now);
+            if start >= shift.end {
+                return Duration::zero();
+            }
+            // 予約は開始日時順のため、予約同士が重なっていても二重に差し引かない
+            let mut free = shift.end - start;
+            let mut cursor = start;
+            for reservation in &availability.reservations {
+                let (start, end) = (
+                    cmp::max(reservation.start, cursor),
+                    cmp::min(reservation.end, shift.end),
+                );
+                if start < end {
+                    free -= end - start;
+                    cursor = end;
+                }
+            }
+            free
+        })
+        .fold(Duration::zero(), |total, free| total + free)
+}
+
+/// 女の子のバッジを判定する
+///
+/// 退職した女の子にはバッジを付けない。空き時間がない場合は「残りわずか」を付けない。
+pub fn badges(availability: &CastAvailability, config: &Badges, now: DateTime<Utc>) -> Vec<Badge> {
+    let mut badges = Vec::new();
+    if availability.leaved {
+        return badges;
+    }
+    if availability
+        .joined_at
+        .is_some_and(|joined_at| now < joined_at + Duration::days(config.newcomer_days as i64))
+    {
+        badges.push(Badge::Newcomer);
+    }
+    if availability.shifts.iter().any(|shift| shift.end > now) {
+        badges.push(Badge::WorkingToday);
+        let remaining = remaining(availability, now);
+        if remaining > Duration::zero()
+            && remaining <= Duration::minutes(config.few_slots_minutes as i64)
+        {
+            badges.push(Badge::FewSlotsLeft);
+        }
+    }
+    badges
+}
+
+/// 本日のシフトと予約を読み取りモデルから取得し、女の子ごとのバッジを判定する
+pub fn documents(
+    sqlite_path: &str,
+    config: &Badges,
+    offset: FixedOffset,
+    now: DateTime<Utc>,
+) -> Result<Vec<BadgeDocument>, ProjectionError> {
+    let connection = sqlite::open(sqlite_path)?;
+    Ok(Reports::new(&connection)
+        .cast_availability(today(offset, now))?
+        .iter()
+        .map(|availability| BadgeDocument {
+            id: availability.prostitute_id,
+            badges: badges(availability, config, now),
+        })
+        .collect())
+}
+
+/// バッジを判定し直して女の子のドキュメントを部分更新する
+async fn update(
+    index: &Index,
+    sqlite_path: &str,
+    config: &Badges,
+    offset: FixedOffset,
+) -> Result<(), ProjectionError> {
+    let (path, config) = (sqlite_path.to_owned(), config.clone());
+    let documents =
+        tokio::task::spawn_blocking(move || documents(&path, &config, offset, Utc::now()))
+            .await??;
+    if !documents.is_empty() {
+        index.add_or_update(&documents, Some("id")).await?;
+    }
+    Ok(())
+}
+
+/// 時間の経過（日付の変更、新人の期間の終了、空き時間の減少）によるバッジの変化を一定間隔で反映する
+pub async fn refresh(index: Index, sqlite_path: String, config: Badges, offset: FixedOffset) {
+    let period = std::time::Duration::from_secs(cmp::max(config.refresh_interval_secs, 1));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = update(&index, &sqlite_path, &config, offset).await {
+            error!("バッジの更新エラー: {}", e);
+        }
+    }
+}
+
+/// 女の子のバッジの投影
+///
+/// 女の子、予約、スケジュールのイベントを受け取った場合に、書き込みの確定時にSQLiteの読み取りモデルから
+/// バッジを判定し直す。SQLiteの投影より後に登録する。
+pub struct BadgeProjection {
+    index: Index,
+    sqlite_path: String,
+    config: Badges,
+    offset: FixedOffset,
+    dirty: bool,
+}
+
+impl BadgeProjection {
+    pub fn new(index: Index, sqlite_path: &str, config: &Badges, offset: FixedOffset) -> Self {
+        Self {
+            index,
+            sqlite_path: sqlite_path.to_owned(),
+            config: config.clone(),
+            offset,
+            dirty: false,
+        }
+    }
+}
+
+#[async_trait]
+impl Projection for BadgeProjection {
+    fn name(&self) -> &str {
+        "badge"
+    }
+
+    async fn project(&mut self, event: &ProjectedEvent) -> Result<(), ProjectionError> {
+        if matches!(
+            event.event,
+            CoreEvent::ProstituteEvent(_)
+                | CoreEvent::ReservationEvent(_)
+                | CoreEvent::ScheduleEvent(_)
+        ) {
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ProjectionError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        update(&self.index, &self.sqlite_path, &self.config, self.offset).await?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 4, 20, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_today() {
+        // 日本時間の2023-04-21 01:00
+        let today = today(FixedOffset::east_opt(9 * 3600).unwrap(), time(16, 0));
+        assert_eq!(today.start, time(15, 0));
+        assert_eq!(today.end, time(15, 0) + Duration::days(1));
+    }
+
+    #[test]
+    fn test_badges() {
+        let config = Badges::default();
+        let mut availability = CastAvailability {
+            prostitute_id: 1,
+            leaved: false,
+            joined_at: Some(time(0, 0) - Duration::days(29)),
+            shifts: vec![time(9, 0)..time(17, 0)],
+            reservations: vec![time(10, 0)..time(11, 0)],
+        };
+        assert_eq!(
+            badges(&availability, &config, time(12, 0)),
+            vec![Badge::Newcomer, Badge::WorkingToday]
+        );
+        // 残り3時間のうち、重なった予約を合わせた1時間を除く2時間が空いている
+        availability.reservations.push(time(15, 0)..time(16, 0));
+        availability.reservations.push(time(15, 30)..time(16, 0));
+        assert_eq!(
+            badges(&availability, &config, time(14, 0)),
+            vec![Badge::Newcomer, Badge::WorkingToday, Badge::FewSlotsLeft]
+        );
+        // 空き時間がない場合
+        availability.reservations.push(time(16, 0)..time(17, 0));
+        assert_eq!(
+            badges(&availability, &config, time(15, 0)),
+            vec![Badge::Newcomer, Badge::WorkingToday]
+        );
+        // シフトと新人の期間が終わった場合
+        assert_eq!(
+            badges(&availability, &config, time(15, 0) + Duration::days(1)),
+            vec![]
+        );
+        availability.leaved = true;
+        assert_eq!(badges(&availability, &config, time(12, 0)), vec![]);
+    }
+
+    #[test]
+    fn test_badge_document() {
+        let document = BadgeDocument {
+            id: 1,
+            badges: vec![Badge::Newcomer, Badge::FewSlotsLeft],
+        };
+        assert_eq!(
+            serde_json::to_value(document).unwrap(),
+            json!({"id": 1, "badges": ["新人", "残りわずか"]})
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    error::Error,
+    io::ErrorKind,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use dely::{CheckpointStoreKind, DelyConfig};
+use eventstore::{
+    AppendToStreamOptions, EventData, Position, ReadStreamOptions, StreamMetadata, StreamPosition,
+};
+use meilisearch_sdk::errors::{ErrorCode, MeilisearchError};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::info;
+use uuid::Uuid;
+
+pub type CheckpointError = Box<dyn Error + Send + Sync>;
+
+static VSERSION_UID: &str = "eventstore_version";
+
+/// チェックポイント（処理済みの`$all`の位置）
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// 最後に処理したイベントのID（チェックポイント通知で進めた場合は`None`）
+    pub event_id: Option<Uuid>,
+    pub position: Position,
+}
+
+/// チェックポイントストア
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    /// チェックポイントを読み込む（未保存の場合は`None`）
+    async fn load(&self) -> Result<Option<Checkpoint>, CheckpointError>;
+    /// チェックポイントを保存する
+    async fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError>;
+    /// ストア自身が書き込むストリームであれば`true`を返す
+    fn owns(&self, _stream_id: &str) -> bool {
+        false
+    }
+}
+
+/// 設定に従ってチェックポイントストアを生成する
+///
+/// Meilisearch以外のストアにチェックポイントがない場合は、従来のMeilisearchのチェックポイントから再開する。
+pub fn from_config(
+    config: &DelyConfig,
+    eventstore: &eventstore::Client,
+    meilisearch: &meilisearch_sdk::Client,
+) -> Box<dyn CheckpointStore> {
+    let legacy = || Box::new(MeiliSearchCheckpointStore::new(meilisearch.clone()));
+    match config.sync.checkpoint_store {
+        CheckpointStoreKind::File => Box::new(FallbackCheckpointStore::new(
+            Box::new(FileCheckpointStore::new(&config.sync.checkpoint_path)),
+            legacy(),
+        )),
+        CheckpointStoreKind::MeiliSearch => legacy(),
+        CheckpointStoreKind::EventStore => Box::new(FallbackCheckpointStore::new(
+            Box::new(EventStoreCheckpointStore::new(
+                eventstore.clone(),
+                config.sync.checkpoint_stream.clone(),
+            )),
+            legacy(),
+        )),
+    }
+}
+
+/// チェックポイントがない場合に別のストアから引き継ぐチェックポイントストア
+///
+/// 引き継いだチェックポイントは次回の保存で新しいストアに書き込まれる。
+pub struct FallbackCheckpointStore {
+    store: Box<dyn CheckpointStore>,
+    fallback: Box<dyn CheckpointStore>,
+}
+
+impl FallbackCheckpointStore {
+    pub fn new(store: Box<dyn CheckpointStore>, fallback: Box<dyn CheckpointStore>) -> Self {
+        Self { store, fallback }
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for FallbackCheckpointStore {
+    async fn load(&self) -> Result<Option<Checkpoint>, CheckpointError> {
+        match self.store.load().await? {
+            Some(checkpoint) => Ok(Some(checkpoint)),
+            None => {
+                let checkpoint = self.fallback.load().await?;
+                if let Some(checkpoint) = checkpoint {
+                    info!(
+                        "従来のチェックポイントから再開します: {:?}",
+                        checkpoint.position
+                    );
+                }
+                Ok(checkpoint)
+            }
+        }
+    }
+
+    async fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.store.save(checkpoint).await
+    }
+
+    fn owns(&self, stream_id: &str) -> bool {
+        self.store.owns(stream_id)
+    }
+}
+
+/// ローカルファイルのチェックポイントストア
+pub struct FileCheckpointStore {
+    path: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpointStore {
+    async fn load(&self) -> Result<Option<Checkpoint>, CheckpointError> {
+        match fs::read(&self.path).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        // 一時ファイルに書き込んでから置き換えることで、書き込み途中のファイルを残さない
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(checkpoint)?).await?;
+        fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EventstoreVersion {
+    id: u64,
+    event_id: Option<Uuid>,
+    position: Position,
+}
+
+/// Meilisearchのドキュメントに保存するチェックポイントストア
+pub struct MeiliSearchCheckpointStore {
+    client: meilisearch_sdk::Client,
+}
+
+impl MeiliSearchCheckpointStore {
+    pub fn new(client: meilisearch_sdk::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for MeiliSearchCheckpointStore {
+    async fn load(&self) -> Result<Option<Checkpoint>, CheckpointError> {
+        match self
+            .client
+            .index(VSERSION_UID)
+            .get_document::<EventstoreVersion>("1")
+            .await
+        {
+            Ok(version) => Ok(Some(Checkpoint {
+                event_id: version.event_id,
+                position: version.position,
+            })),
+            Err(meilisearch_sdk::errors::Error::Meilisearch(MeilisearchError {
+                error_code: ErrorCode::IndexNotFound | ErrorCode::DocumentNotFound,
+                ..
+            })) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.client
+            .index(VSERSION_UID)
+            .add_documents(
+                &[EventstoreVersion {
+                    id: 1,
+                    event_id: checkpoint.event_id,
+                    position: checkpoint.position,
+                }],
+                Some("id"),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// EventStoreのストリームに保存するチェックポイントストア
+pub struct EventStoreCheckpointStore {
+    client: eventstore::Client,
+    stream: String,
+    initialized: bool,
+}
+
+impl EventStoreCheckpointStore {
+    pub fn new(client: eventstore::Client, stream: String) -> Self {
+        Self {
+            client,
+            stream,
+            initialized: false,
+        }
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for EventStoreCheckpointStore {
+    async fn load(&self) -> Result<Option<Checkpoint>, CheckpointError> {
+        let mut stream = self
+            .client
+            .read_stream(
+                self.stream.as_str(),
+                &ReadStreamOptions::default()
+                    .position(StreamPosition::End)
+                    .backwards()
+                    .max_count(1),
+            )
+            .await?;
+        match stream.next().await {
+            Ok(Some(e)) => Ok(Some(e.get_original_event().as_json()?)),
+            Ok(None) | Err(eventstore::Error::ResourceNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        if !self.initialized {
+            // 最新のチェックポイント以外は不要なので保持数を制限する
+            self.client
+                .set_stream_metadata(
+                    self.stream.as_str(),
+                    &AppendToStreamOptions::default(),
+                    StreamMetadata::builder().max_count(1).build(),
+                )
+                .await?;
+            self.initialized = true;
+        }
+        self.client
+            .append_to_stream(
+                self.stream.as_str(),
+                &AppendToStreamOptions::default(),
+                EventData::json("CheckpointStored", checkpoint)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    fn owns(&self, stream_id: &str) -> bool {
+        stream_id == self.stream
+    }
+}
+
+/// 設定された頻度でチェックポイントを保存する
+pub struct Checkpointer {
+    store: Box<dyn CheckpointStore>,
+    every: u32,
+    interval: Duration,
+    pending: Option<Checkpoint>,
+    count: u32,
+    saved_at: Instant,
+}
+
+impl Checkpointer {
+    pub fn new(store: Box<dyn CheckpointStore>, config: &DelyConfig) -> Self {
+        Self {
+            store,
+            every: config.sync.checkpoint_every.max(1),
+            interval: Duration::from_millis(config.sync.checkpoint_interval_ms),
+            pending: None,
+            count: 0,
+            saved_at: Instant::now(),
+        }
+    }
+
+    pub async fn load(&self) -> Result<Option<Checkpoint>, CheckpointError> {
+        self.store.load().await
+    }
+
+    /// 処理済みのイベントを記録し、必要であればチェックポイントを保存する
+    pub async fn record(
+        &mut self,
+        stream_id: &str,
+        checkpoint: Checkpoint,
+    ) -> Result<(), CheckpointError> {
+        self.pending = Some(checkpoint);
+        // ストア自身の書き込みを数えると保存が保存を呼び続けるため除外する
+        if !self.store.owns(stream_id) {
+            self.count += 1;
+        }
+        if self.count > 0 && (self.count >= self.every || self.saved_at.elapsed() >= self.interval)
+        {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// チェックポイント通知を記録する（投影対象のイベントがない間も位置を進める）
+    pub async fn reached(&mut self, position: Position) -> Result<(), CheckpointError> {
+        self.pending = Some(Checkpoint {
+            event_id: None,
+            position,
+        });
+        if self.saved_at.elapsed() >= self.interval {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// チェックポイントを直ちに保存する
+    pub async fn commit(&mut self, checkpoint: Checkpoint) -> Result<(), CheckpointError> {
+        self.pending = Some(checkpoint);
+        self.flush().await
+    }
+
+    /// 未保存のチェックポイントを保存する
+    pub async fn flush(&mut self) -> Result<(), CheckpointError> {
+        if let Some(checkpoint) = self.pending {
+            self.store.save(&checkpoint).await?;
+            self.pending = None;
+            self.count = 0;
+            self.saved_at = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eventstore::Position;
+    use uuid::Uuid;
+
+    use super::{Checkpoint, CheckpointStore, FallbackCheckpointStore, FileCheckpointStore};
+
+    #[tokio::test]
+    async fn test_file_checkpoint_store() {
+        let path = std::env::temp_dir().join(format!("dely_sync-{}.json", Uuid::new_v4()));
+        let mut store = FileCheckpointStore::new(&path);
+        assert_eq!(store.load().await.unwrap(), None);
+
+        let checkpoint = Checkpoint {
+            event_id: Some(Uuid::new_v4()),
+            position: Position {
+                commit: 42,
+                prepare: 42,
+            },
+        };
+        store.save(&checkpoint).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), Some(checkpoint));
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fallback_checkpoint_store() {
+        let path = std::env::temp_dir().join(format!("dely_sync-{}.json", Uuid::new_v4()));
+        let legacy_path = std::env::temp_dir().join(format!("dely_sync-{}.json", Uuid::new_v4()));
+        let legacy = Checkpoint {
+            event_id: Some(Uuid::new_v4()),
+            position: Position {
+                commit: 42,
+                prepare: 42,
+            },
+        };
+        FileCheckpointStore::new(&legacy_path)
+            .save(&legacy)
+            .await
+            .unwrap();
+        let mut store = FallbackCheckpointStore::new(
+            Box::new(FileCheckpointStore::new(&path)),
+            Box::new(FileCheckpointStore::new(&legacy_path)),
+        );
+        // 新しいストアにない場合は従来のストアから引き継ぐ
+        assert_eq!(store.load().await.unwrap(), Some(legacy));
+
+        let checkpoint = Checkpoint {
+            event_id: None,
+            position: Position {
+                commit: 84,
+                prepare: 84,
+            },
+        };
+        store.save(&checkpoint).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), Some(checkpoint));
+        assert_eq!(
+            FileCheckpointStore::new(&legacy_path).load().await.unwrap(),
+            Some(legacy)
+        );
+        tokio::fs::remove_file(&path).await.unwrap();
+        tokio::fs::remove_file(&legacy_path).await.unwrap();
+    }
+}
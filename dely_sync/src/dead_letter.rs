@@ -0,0 +1,155 @@
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+use eventstore::{Position, RecordedEvent};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use uuid::Uuid;
+
+/// デッドレター（投影できなかったイベント）
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub event_id: Uuid,
+    pub stream_id: String,
+    pub revision: u64,
+    pub event_type: String,
+    pub position: Position,
+    /// 失敗理由
+    pub reason: String,
+    /// 試行回数
+    pub attempts: u32,
+    /// 最後に失敗した日時
+    pub failed_at: DateTime<Utc>,
+}
+
+impl DeadLetter {
+    pub fn new(event: &RecordedEvent) -> Self {
+        Self {
+            event_id: event.id,
+            stream_id: event.stream_id.clone(),
+            revision: event.revision,
+            event_type: event.event_type.clone(),
+            position: event.position,
+            reason: String::new(),
+            attempts: 0,
+            failed_at: Utc::now(),
+        }
+    }
+
+    /// 失敗を記録する
+    pub fn failed(self, reason: String, attempts: u32) -> Self {
+        Self {
+            reason,
+            attempts: self.attempts + attempts,
+            failed_at: Utc::now(),
+            ..self
+        }
+    }
+}
+
+/// デッドレターストア（JSON Lines形式のローカルファイル）
+pub struct DeadLetterStore {
+    path: PathBuf,
+}
+
+impl DeadLetterStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// 全てのデッドレターを古い順に取得する
+    pub async fn list(&self) -> io::Result<Vec<DeadLetter>> {
+        match fs::read_to_string(&self.path).await {
+            Ok(text) => text
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| serde_json::from_str(l).map_err(io::Error::from))
+                .collect(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// デッドレターを追加する（同じイベントが既にあれば置き換える）
+    pub async fn push(&self, dead_letter: DeadLetter) -> io::Result<()> {
+        let mut dead_letters = self.list().await?;
+        dead_letters.retain(|d| d.event_id != dead_letter.event_id);
+        dead_letters.push(dead_letter);
+        self.write(&dead_letters).await
+    }
+
+    /// デッドレターを削除する
+    pub async fn remove(&self, event_id: Uuid) -> io::Result<bool> {
+        let mut dead_letters = self.list().await?;
+        let len = dead_letters.len();
+        dead_letters.retain(|d| d.event_id != event_id);
+        if dead_letters.len() == len {
+            return Ok(false);
+        }
+        self.write(&dead_letters).await?;
+        Ok(true)
+    }
+
+    async fn write(&self, dead_letters: &[DeadLetter]) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let mut text = String::new();
+        for d in dead_letters {
+            text += &serde_json::to_string(d)?;
+            text.push('\n');
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, text).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use eventstore::Position;
+    use uuid::Uuid;
+
+    use super::{DeadLetter, DeadLetterStore};
+
+    #[tokio::test]
+    async fn test_dead_letter_store() {
+        let path = std::env::temp_dir().join(format!("dely_sync-{}.jsonl", Uuid::new_v4()));
+        let store = DeadLetterStore::new(&path);
+        assert!(store.list().await.unwrap().is_empty());
+
+        let dead_letter = DeadLetter {
+            event_id: Uuid::new_v4(),
+            stream_id: "prostitute-100".to_owned(),
+            revision: 3,
+            event_type: "ProstituteImageAdded".to_owned(),
+            position: Position {
+                commit: 10,
+                prepare: 10,
+            },
+            reason: String::new(),
+            attempts: 0,
+            failed_at: Utc::now(),
+        };
+        store
+            .push(dead_letter.clone().failed("エラー".to_owned(), 6))
+            .await
+            .unwrap();
+        store
+            .push(dead_letter.clone().failed("エラー".to_owned(), 6))
+            .await
+            .unwrap();
+        let list = store.list().await.unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].attempts, 6);
+
+        assert!(store.remove(dead_letter.event_id).await.unwrap());
+        assert!(!store.remove(dead_letter.event_id).await.unwrap());
+        assert!(store.list().await.unwrap().is_empty());
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -1,26 +1,69 @@
-use std::{error::Error, ops::Range};
+mod badge;
+mod checkpoint;
+mod dead_letter;
+mod metrics;
+mod profile;
+mod ranking;
+mod rebuild;
+
+use std::{cmp, error::Error, io, ops::Range, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
 use dely::{
     domain::{
         core::{
             CoreEvent, ExtraService, ExtraServiceEvent, Media, MediaEvent, Prostitute,
-            ProstituteEvent, ProstituteId, Schedule, ScheduleEvent, ScheduleId, Shift, ShiftId,
-            ShiftStatus,
+            ProstituteEvent, ProstituteId, Reservation, Schedule, ScheduleEvent, ScheduleId, Shift,
+            ShiftId, ShiftStatus, Tag, TagEvent,
         },
         Aggregation, Entity,
     },
+    infrastructure::{
+        projection::{
+            is_after, JsonFileCheckpointStore, JsonFileProjection, ProjectedEvent, Projector,
+        },
+        sqlite::{self, SqliteProjection},
+        EventConvertError,
+    },
     DelyConfig,
 };
-use eventstore::{ClientSettings, Position, StreamPosition, SubscribeToAllOptions};
-use meilisearch_sdk::{task_info::TaskInfo, tasks::Task};
+use eventstore::{
+    ClientSettings, Position, ReadAllOptions, ReadStreamOptions, StreamPosition,
+    SubscribeToAllOptions, SubscriptionEvent, SubscriptionFilter,
+};
+use meilisearch_sdk::{indexes::Index, settings::Settings, task_info::TaskInfo, tasks::Task};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 use tracing::{error, info, log::warn, Level};
 use uuid::Uuid;
 
-static VSERSION_UID: &str = "eventstore_version";
+use crate::badge::BadgeProjection;
+use crate::checkpoint::{Checkpoint, Checkpointer};
+use crate::dead_letter::{DeadLetter, DeadLetterStore};
+use crate::metrics::Metrics;
+use crate::profile::{AgeField, FigureFields};
+use crate::ranking::RankingProjection;
+
+/// 投影先のインデックス名
+const INDEXES: [&str; 6] = [
+    ExtraService::ENTITY_NAME,
+    Media::ENTITY_NAME,
+    Prostitute::ENTITY_NAME,
+    Schedule::ENTITY_NAME,
+    Shift::ENTITY_NAME,
+    Tag::ENTITY_NAME,
+];
+
+/// 投影対象のエンティティ名
+const PROJECTED_ENTITIES: [&str; 6] = [
+    ExtraService::ENTITY_NAME,
+    Media::ENTITY_NAME,
+    Prostitute::ENTITY_NAME,
+    Reservation::ENTITY_NAME,
+    Schedule::ENTITY_NAME,
+    Tag::ENTITY_NAME,
+];
 
 #[tokio::main]
 async fn main() {
@@ -29,7 +72,18 @@ async fn main() {
             tracing_subscriber::fmt()
                 .with_max_level(Level::from(&config.logger.level))
                 .init();
-            if let Err(error) = subscribe(&config).await {
+            let args = std::env::args().skip(1).collect::<Vec<_>>();
+            let result = match args.first().map(String::as_str) {
+                None | Some("run") => subscribe(&config).await,
+                Some("dead-letters") => list_dead_letters(&config).await,
+                Some("replay-dead-letters") => replay_dead_letters(&config, args.get(1)).await,
+                Some("rebuild") => match args.get(1).map(|v| v.parse::<u32>()) {
+                    Some(Ok(version)) => rebuild::rebuild(&config, version).await,
+                    _ => Err("使い方: dely_sync rebuild <バージョン>".into()),
+                },
+                Some(command) => Err(format!("不明なコマンド: {}", command).into()),
+            };
+            if let Err(error) = result {
                 error!("アプリケーションエラー: {}", error);
             }
         }
@@ -40,65 +94,437 @@ async fn main() {
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct EventstoreVersion {
-    id: u64,
-    event_id: Uuid,
-    position: Position,
-}
-
 async fn subscribe(config: &DelyConfig) -> Result<(), Box<dyn Error>> {
-    let settings = config.eventstore.url.parse::<ClientSettings>()?;
-    let mut client = Client {
-        eventstore: eventstore::Client::new(settings)?,
-        meilisearch: meilisearch_sdk::Client::new(
-            &config.meilisearch.url,
-            &config.meilisearch.api_key,
-        ),
-        task_info: None,
+    let mut client = Client::new(config)?;
+    apply_settings(&client).await?;
+    let retry = RetryPolicy::from(config);
+    let dead_letters = DeadLetterStore::new(&config.sync.dead_letter_path);
+    let metrics = Arc::new(Metrics::default());
+    tokio::spawn(metrics::serve(
+        config.sync.metrics_addr.clone(),
+        metrics.clone(),
+    ));
+    let mut checkpointer = Checkpointer::new(
+        checkpoint::from_config(config, &client.eventstore, &client.meilisearch),
+        config,
+    );
+    let checkpoint = checkpointer.load().await.map_err(|e| e as Box<dyn Error>)?;
+    if checkpoint.is_none() {
+        info!("チェックポイントがないため最初から投影します");
+    }
+    let mut projector = projector(config)?;
+    projector.load().await.map_err(|e| e as Box<dyn Error>)?;
+    projector
+        .catch_up(&client.eventstore)
+        .await
+        .map_err(|e| e as Box<dyn Error>)?;
+
+    // 停止中に溜まったイベントはまとめて投影してから購読に切り替える
+    metrics.set_catching_up(true);
+    let position = catch_up(
+        &mut client,
+        &retry,
+        &dead_letters,
+        Some(&mut checkpointer),
+        &metrics,
+        checkpoint.map(|c| c.position),
+        config.sync.catch_up_batch_size,
+    )
+    .await?;
+    metrics.set_catching_up(false);
+    info!("追い上げが完了したため購読に切り替えます");
+
+    // 検索用インデックスと他の投影のうち、遅れている方の位置から購読する
+    let start = match projector.position() {
+        Some(Some(p)) => {
+            position.map(|position| if is_after(&position, &p) { p } else { position })
+        }
+        Some(None) => None,
+        None => position,
+    };
+    let start = match start {
+        Some(position) => StreamPosition::Position(position),
+        None => StreamPosition::Start,
     };
-    let version = client
-        .meilisearch
-        .index(VSERSION_UID)
-        .get_document::<EventstoreVersion>("1")
-        .await?;
+    tokio::spawn(profile::refresh(
+        client.index(Prostitute::ENTITY_NAME),
+        config.sync.offset(),
+    ));
+    if let Some(path) = &config.sync.sqlite_path {
+        tokio::spawn(badge::refresh(
+            client.index(Prostitute::ENTITY_NAME),
+            path.clone(),
+            config.badges.clone(),
+            config.sync.offset(),
+        ));
+    }
     let mut sub = client
         .eventstore
         .subscribe_to_all(
-            &SubscribeToAllOptions::default().position(StreamPosition::Position(version.position)),
+            &SubscribeToAllOptions::default()
+                .position(start)
+                .filter(projected_filter()),
         )
         .await;
     loop {
-        match sub.next().await {
-            Ok(resolved) => {
+        let result = match sub.next_subscription_event().await? {
+            SubscriptionEvent::EventAppeared(resolved) => {
                 let event = resolved.get_original_event();
-                let event_id = event.id;
-                let position = event.position;
-                if let Ok(core_event) = CoreEvent::try_from(resolved) {
-                    info!("イベントを受信: {:?}", core_event);
-                    if let Err(e) = client.execute(core_event).await {
-                        error!("イベント実行エラー: {}", e);
-                        continue;
+                let stream_id = event.stream_id.clone();
+                let checkpoint = Checkpoint {
+                    event_id: Some(event.id),
+                    position: event.position,
+                };
+                // 検索用インデックスへ投影済みのイベントは他の投影にだけ配る
+                let indexed = position.is_some_and(|p| !is_after(&event.position, &p));
+                metrics.observe(event.created);
+                let dead_letter = DeadLetter::new(event);
+                let event = ProjectedEvent::try_from(resolved);
+                if !indexed {
+                    let core_event = event
+                        .as_ref()
+                        .map(|e| e.event.clone())
+                        .map_err(|_| EventConvertError);
+                    project(&mut client, &retry, &dead_letters, dead_letter, core_event).await?;
+                }
+                if let Ok(event) = &event {
+                    projector.project(event).await;
+                    if let Err(e) = projector.flush().await {
+                        error!("投影の書き込みエラー: {}", e);
                     }
                 }
-                if let Err(e) = client
-                    .meilisearch
-                    .index(VSERSION_UID)
-                    .add_documents(
-                        &[EventstoreVersion {
-                            id: 1,
-                            event_id,
-                            position,
-                        }],
-                        Some("id"),
-                    )
-                    .await
-                {
-                    error!("バージョン情報保存失敗: {}", e);
-                    todo!("バージョン情報をローカルに保存する等必要")
+                checkpointer.record(&stream_id, checkpoint).await
+            }
+            // 投影対象のイベントがない間もサーバーからの通知で位置を進める
+            SubscriptionEvent::Checkpoint(position) => checkpointer.reached(position).await,
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            // 保存できなかったチェックポイントは次のイベントで再度保存を試みる
+            error!("チェックポイント保存失敗: {}", e);
+        }
+    }
+}
+
+/// 検索用インデックス以外の投影を設定に従って生成する
+fn projector(config: &DelyConfig) -> Result<Projector, Box<dyn Error>> {
+    let mut projector = Projector::new(JsonFileCheckpointStore::new(
+        &config.sync.projection_checkpoint_path,
+    ));
+    if let Some(path) = &config.sync.export_path {
+        projector = projector.with(JsonFileProjection::new("export", path));
+    }
+    if let Some(path) = &config.sync.sqlite_path {
+        projector = projector.with(SqliteProjection::new(sqlite::open(path)?));
+        let client = Client::new(config)?;
+        projector = projector.with(RankingProjection::new(
+            client.index(Prostitute::ENTITY_NAME),
+            path,
+            config.sync.ranking_days,
+            config.sync.ranking_currency,
+        ));
+        projector = projector.with(BadgeProjection::new(
+            client.index(Prostitute::ENTITY_NAME),
+            path,
+            &config.badges,
+            config.sync.offset(),
+        ));
+    }
+    Ok(projector)
+}
+
+/// インデックスの設定（並べ替えや絞り込みに使うフィールド）
+fn settings(uid: &str) -> Option<Settings> {
+    match uid {
+        uid if uid == Prostitute::ENTITY_NAME => Some(
+            Settings::new()
+                .with_sortable_attributes(
+                    ranking::SORTABLE_ATTRIBUTES
+                        .into_iter()
+                        .chain(profile::SORTABLE_ATTRIBUTES),
+                )
+                .with_filterable_attributes(
+                    badge::FILTERABLE_ATTRIBUTES
+                        .into_iter()
+                        .chain(profile::FILTERABLE_ATTRIBUTES),
+                ),
+        ),
+        uid if uid == Tag::ENTITY_NAME => {
+            Some(Settings::new().with_filterable_attributes(["category"]))
+        }
+        _ => None,
+    }
+}
+
+/// 書き込み先のインデックスに設定を反映する（設定の変更後は再インデックスが完了するまで待機する）
+async fn apply_settings(client: &Client) -> Result<(), meilisearch_sdk::errors::Error> {
+    for uid in INDEXES {
+        if let Some(settings) = settings(uid) {
+            let task_info = client.index(uid).set_settings(&settings).await?;
+            wait_for_task(&client.meilisearch, &task_info).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 投影対象のストリームに絞り込むサーバー側のフィルター
+fn projected_filter() -> SubscriptionFilter {
+    PROJECTED_ENTITIES
+        .iter()
+        .fold(SubscriptionFilter::on_stream_name(), |filter, name| {
+            filter.add_prefix(format!("{}-", name))
+        })
+}
+
+/// `$all`を指定位置の次から末尾まで投影し、最後に読み込んだ位置を返す
+///
+/// 同じインデックスへの連続した部分更新は`batch_size`件までまとめて送信する。
+/// チェックポイントは送信済みのイベントまでしか記録しないため、中断しても未送信の更新は失われない。
+/// まとめた部分更新の送信に失敗した場合は、含まれるイベントをデッドレターに記録して続行する。
+async fn catch_up(
+    client: &mut Client,
+    retry: &RetryPolicy,
+    dead_letters: &DeadLetterStore,
+    mut checkpointer: Option<&mut Checkpointer>,
+    metrics: &Metrics,
+    from: Option<Position>,
+    batch_size: usize,
+) -> Result<Option<Position>, Box<dyn Error>> {
+    let options = ReadAllOptions::default().position(match from {
+        Some(position) => StreamPosition::Position(position),
+        None => StreamPosition::Start,
+    });
+    let mut stream = client.eventstore.read_all(&options).await?;
+    let mut last = None;
+    let mut batched = Vec::new();
+    client.batching = true;
+    while let Some(resolved) = stream.next().await? {
+        let event = resolved.get_original_event();
+        // 開始位置のイベントは投影済みのため除外する
+        if Some(event.position) == from {
+            continue;
+        }
+        let stream_id = event.stream_id.clone();
+        let checkpoint = Checkpoint {
+            event_id: Some(event.id),
+            position: event.position,
+        };
+        last = Some(checkpoint);
+        // `$all`の読み込みはサーバー側で絞り込めないため、投影対象外のストリームは位置だけ進める
+        if !is_projected(&stream_id) {
+            if let (Some(checkpointer), 0) = (checkpointer.as_deref_mut(), client.pending()) {
+                if let Err(e) = checkpointer.reached(event.position).await {
+                    error!("チェックポイント保存失敗: {}", e);
                 }
             }
+            continue;
+        }
+        metrics.observe(event.created);
+        let dead_letter = DeadLetter::new(event);
+        let event = CoreEvent::try_from(resolved);
+        let flushes = client.flushes;
+        project(client, retry, dead_letters, dead_letter.clone(), event).await?;
+        // 別のインデックスへの更新などで送信済みになった部分更新のイベントはデッドレターの対象から外す
+        if client.flushes != flushes {
+            batched.clear();
+        }
+        if client.pending() > 0 {
+            batched.push(dead_letter);
+        }
+        if client.pending() >= batch_size.max(1) {
+            flush(client, retry, dead_letters, &mut batched).await?;
+        }
+        if client.pending() == 0 {
+            batched.clear();
+            if let Some(checkpointer) = checkpointer.as_deref_mut() {
+                if let Err(e) = checkpointer.record(&stream_id, checkpoint).await {
+                    error!("チェックポイント保存失敗: {}", e);
+                }
+            }
+        }
+    }
+    flush(client, retry, dead_letters, &mut batched).await?;
+    client.batching = false;
+    client.wait_for_completion().await?;
+    if let (Some(checkpointer), Some(checkpoint)) = (checkpointer, last) {
+        checkpointer
+            .commit(checkpoint)
+            .await
+            .map_err(|e| e as Box<dyn Error>)?;
+    }
+    Ok(last.map(|c| c.position).or(from))
+}
+
+/// イベントを投影し、失敗した場合はデッドレターに記録する
+async fn project(
+    client: &mut Client,
+    retry: &RetryPolicy,
+    dead_letters: &DeadLetterStore,
+    dead_letter: DeadLetter,
+    event: Result<CoreEvent, EventConvertError>,
+) -> io::Result<()> {
+    let stream_id = dead_letter.stream_id.clone();
+    match event {
+        Ok(core_event) => {
+            info!("イベントを受信: {:?}", core_event);
+            if let Err((e, attempts)) = retry.execute(client, core_event).await {
+                error!("イベント実行エラー: {}", e);
+                dead_letters
+                    .push(dead_letter.failed(e.to_string(), attempts))
+                    .await?;
+            }
+        }
+        Err(e) if is_projected(&stream_id) => {
+            error!("イベント変換エラー: {}", e);
+            dead_letters
+                .push(dead_letter.failed(e.to_string(), 1))
+                .await?;
+        }
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+/// まとめた部分更新をリトライしながら送信し、失敗した場合は含まれるイベントをデッドレターに記録する
+async fn flush(
+    client: &mut Client,
+    retry: &RetryPolicy,
+    dead_letters: &DeadLetterStore,
+    batched: &mut Vec<DeadLetter>,
+) -> io::Result<()> {
+    if let Err((e, attempts)) = retry.execute(client, Flush).await {
+        error!("まとめた部分更新の送信エラー: {}", e);
+        // 再実行はイベント単位で行うため、送信できなかった部分更新は破棄する
+        client.batch = None;
+        for dead_letter in batched.iter() {
+            dead_letters
+                .push(dead_letter.clone().failed(e.to_string(), attempts))
+                .await?;
+        }
+    }
+    batched.clear();
+    Ok(())
+}
+
+fn is_projected(stream_id: &str) -> bool {
+    stream_id
+        .split('-')
+        .next()
+        .is_some_and(|name| PROJECTED_ENTITIES.contains(&name))
+}
+
+/// デッドレターを一覧表示する
+async fn list_dead_letters(config: &DelyConfig) -> Result<(), Box<dyn Error>> {
+    let dead_letters = DeadLetterStore::new(&config.sync.dead_letter_path);
+    for d in dead_letters.list().await? {
+        println!(
+            "{}\t{}@{}\t{}\t{}回\t{}\t{}",
+            d.event_id, d.stream_id, d.revision, d.event_type, d.attempts, d.failed_at, d.reason
+        );
+    }
+    Ok(())
+}
+
+/// デッドレターを再実行する（イベントIDを省略した場合は全て）
+///
+/// 元の順序とは無関係に再実行されるため、後続のイベントで更新された値を古い値で上書きする可能性がある。
+async fn replay_dead_letters(
+    config: &DelyConfig,
+    event_id: Option<&String>,
+) -> Result<(), Box<dyn Error>> {
+    let event_id = event_id.map(|id| id.parse::<Uuid>()).transpose()?;
+    let mut client = Client::new(config)?;
+    let retry = RetryPolicy::from(config);
+    let dead_letters = DeadLetterStore::new(&config.sync.dead_letter_path);
+    for dead_letter in dead_letters.list().await? {
+        if event_id.is_some_and(|id| id != dead_letter.event_id) {
+            continue;
+        }
+        let mut stream = client
+            .eventstore
+            .read_stream(
+                dead_letter.stream_id.as_str(),
+                &ReadStreamOptions::default()
+                    .position(StreamPosition::Position(dead_letter.revision))
+                    .max_count(1),
+            )
+            .await?;
+        let resolved = match stream.next().await {
+            Ok(Some(resolved)) => resolved,
+            Ok(None)
+            | Err(eventstore::Error::ResourceNotFound)
+            | Err(eventstore::Error::ResourceDeleted) => {
+                warn!("イベントが見つかりません: {}", dead_letter.event_id);
+                continue;
+            }
             Err(e) => return Err(Box::new(e)),
+        };
+        let result = match CoreEvent::try_from(resolved) {
+            Ok(core_event) => retry
+                .execute(&mut client, core_event)
+                .await
+                .map_err(|(e, attempts)| (e.to_string(), attempts)),
+            Err(e) => Err((e.to_string(), 1)),
+        };
+        match result {
+            Ok(()) => {
+                dead_letters.remove(dead_letter.event_id).await?;
+                info!("デッドレターを再実行しました: {}", dead_letter.event_id);
+            }
+            Err((reason, attempts)) => {
+                error!("デッドレター再実行エラー: {}", reason);
+                dead_letters
+                    .push(dead_letter.failed(reason, attempts))
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// リトライポリシー
+struct RetryPolicy {
+    max_retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl From<&DelyConfig> for RetryPolicy {
+    fn from(config: &DelyConfig) -> Self {
+        Self {
+            max_retries: config.sync.max_retries,
+            backoff: Duration::from_millis(config.sync.retry_backoff_ms),
+            max_backoff: Duration::from_millis(config.sync.max_retry_backoff_ms),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 指数バックオフでリトライしながらイベントを実行し、失敗した場合はエラーと試行回数を返す
+    async fn execute<C, E>(&self, client: &mut C, event: E) -> Result<(), (C::Error, u32)>
+    where
+        C: Execute<E> + Send,
+        E: Clone + Send + 'static,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match client.execute(event.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempts > self.max_retries => return Err((e, attempts)),
+                Err(e) => {
+                    let backoff = cmp::min(
+                        self.backoff
+                            .saturating_mul(2u32.saturating_pow(attempts - 1)),
+                        self.max_backoff,
+                    );
+                    warn!(
+                        "イベント実行エラー（{}回目）: {}、{:?}後に再試行します",
+                        attempts, e, backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+            }
         }
     }
 }
@@ -113,23 +539,155 @@ struct Client {
     eventstore: eventstore::Client,
     meilisearch: meilisearch_sdk::Client,
     task_info: Option<TaskInfo>,
+    /// インデックス名の接尾辞（再構築中のインデックスに書き込む場合に使用する）
+    suffix: String,
+    /// 部分更新をまとめて送信するかどうか
+    batching: bool,
+    batch: Option<Batch>,
+    /// 送信が完了したまとめた部分更新の数
+    flushes: u64,
+    /// 年齢を判定するタイムゾーン
+    offset: FixedOffset,
+}
+
+/// まとめて送信する部分更新
+struct Batch {
+    uid: String,
+    documents: Vec<Value>,
+}
+
+impl Batch {
+    /// ドキュメントを追加する（同じIDのドキュメントがあればフィールドを上書きして統合する）
+    fn push(&mut self, document: Value) {
+        let existing = self
+            .documents
+            .iter_mut()
+            .find(|d| d.get("id").is_some() && d.get("id") == document.get("id"));
+        match (existing, document) {
+            (Some(Value::Object(existing)), Value::Object(document)) => existing.extend(document),
+            (_, document) => self.documents.push(document),
+        }
+    }
 }
 
 impl Client {
-    async fn wait_for_completion(&self) -> Result<Option<Task>, meilisearch_sdk::errors::Error> {
-        if let Some(task_info) = &self.task_info {
-            loop {
-                match self.meilisearch.wait_for_task(task_info, None, None).await {
-                    Ok(task) => match task {
-                        Task::Succeeded { .. } | Task::Failed { .. } => return Ok(Some(task)),
-                        _ => continue,
-                    },
-                    Err(meilisearch_sdk::errors::Error::Timeout) => continue,
-                    Err(e) => return Err(e),
-                }
-            }
+    fn new(config: &DelyConfig) -> Result<Self, Box<dyn Error>> {
+        let settings = config.eventstore.url.parse::<ClientSettings>()?;
+        Ok(Self {
+            eventstore: eventstore::Client::new(settings)?,
+            meilisearch: meilisearch_sdk::Client::new(
+                &config.meilisearch.url,
+                &config.meilisearch.api_key,
+            ),
+            task_info: None,
+            suffix: String::new(),
+            batching: false,
+            batch: None,
+            flushes: 0,
+            offset: config.sync.offset(),
+        })
+    }
+
+    /// 書き込み先のインデックス名に接尾辞を付ける
+    fn with_suffix(self, suffix: &str) -> Self {
+        Self {
+            suffix: suffix.to_owned(),
+            ..self
         }
-        Ok(None)
+    }
+
+    fn index(&self, uid: &str) -> Index {
+        self.meilisearch.index(format!("{}{}", uid, self.suffix))
+    }
+
+    /// 年齢を判定する今日の日付
+    fn today(&self) -> NaiveDate {
+        profile::today(self.offset)
+    }
+
+    /// 部分更新を送信する（まとめて送信する場合は同じインデックスへの連続した更新をまとめる）
+    async fn update(
+        &mut self,
+        uid: &str,
+        document: Value,
+    ) -> Result<(), meilisearch_sdk::errors::Error> {
+        if !self.batching {
+            self.task_info = Some(
+                self.index(uid)
+                    .add_or_update(&[document], Some("id"))
+                    .await?,
+            );
+            return Ok(());
+        }
+        if self.batch.as_ref().is_some_and(|b| b.uid != uid) {
+            self.flush().await?;
+        }
+        self.batch
+            .get_or_insert_with(|| Batch {
+                uid: uid.to_owned(),
+                documents: Vec::new(),
+            })
+            .push(document);
+        Ok(())
+    }
+
+    /// まとめた部分更新を送信する
+    ///
+    /// 失敗した場合に再送できるよう、タスクが完了するまでまとめた部分更新を残す
+    async fn flush(&mut self) -> Result<(), meilisearch_sdk::errors::Error> {
+        if let Some(batch) = &self.batch {
+            let index = self.index(&batch.uid);
+            let task_info = index.add_or_update(&batch.documents, Some("id")).await?;
+            wait_for_task(&self.meilisearch, &task_info).await?;
+            self.batch = None;
+            self.flushes += 1;
+        }
+        Ok(())
+    }
+
+    /// 未送信のドキュメント数
+    fn pending(&self) -> usize {
+        self.batch.as_ref().map_or(0, |b| b.documents.len())
+    }
+
+    /// 最後に送信したタスクの完了を待機する（失敗は一度だけ報告する）
+    async fn wait_for_completion(
+        &mut self,
+    ) -> Result<Option<Task>, meilisearch_sdk::errors::Error> {
+        match self.task_info.take() {
+            Some(task_info) => wait_for_task(&self.meilisearch, &task_info).await.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// タスクが完了するまで待機する（失敗した場合はタスクのエラーを返す）
+async fn wait_for_task(
+    meilisearch: &meilisearch_sdk::Client,
+    task_info: &TaskInfo,
+) -> Result<Task, meilisearch_sdk::errors::Error> {
+    loop {
+        match meilisearch.wait_for_task(task_info, None, None).await {
+            Ok(task) => match task {
+                Task::Succeeded { .. } => return Ok(task),
+                Task::Failed { content } => return Err(content.error.into()),
+                _ => continue,
+            },
+            Err(meilisearch_sdk::errors::Error::Timeout) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// まとめた部分更新の送信
+#[derive(Clone)]
+struct Flush;
+
+#[async_trait]
+impl Execute<Flush> for Client {
+    type Error = meilisearch_sdk::errors::Error;
+    async fn execute(&mut self, _: Flush) -> Result<(), Self::Error> {
+        self.flush().await
     }
 }
 
@@ -137,12 +695,22 @@ impl Client {
 impl Execute<CoreEvent> for Client {
     type Error = meilisearch_sdk::errors::Error;
     async fn execute(&mut self, event: CoreEvent) -> Result<(), Self::Error> {
-        Ok(match event {
+        match event {
             CoreEvent::ExtraServiceEvent(event) => self.execute(event).await?,
             CoreEvent::MediaEvent(event) => self.execute(event).await?,
             CoreEvent::ProstituteEvent(event) => self.execute(event).await?,
+            // レジ締めと予約は検索用インデックスに投影しない
+            CoreEvent::CashClosingEvent(_)
+            | CoreEvent::CouponEvent(_)
+            | CoreEvent::PointLedgerEvent(_)
+            | CoreEvent::ReceiptBookEvent(_)
+            | CoreEvent::ReservationEvent(_) => (),
             CoreEvent::ScheduleEvent(event) => self.execute(event).await?,
-        })
+            CoreEvent::TagEvent(event) => self.execute(event).await?,
+        };
+        // 送信したタスクの失敗をリトライとデッドレターの対象にする（まとめた部分更新は送信時に待機する）
+        self.wait_for_completion().await?;
+        Ok(())
     }
 }
 
@@ -150,7 +718,7 @@ impl Execute<CoreEvent> for Client {
 impl Execute<ExtraServiceEvent> for Client {
     type Error = meilisearch_sdk::errors::Error;
     async fn execute(&mut self, event: ExtraServiceEvent) -> Result<(), Self::Error> {
-        let index = self.meilisearch.index(ExtraService::ENTITY_NAME);
+        let index = self.index(ExtraService::ENTITY_NAME);
         let task = match event {
             ExtraServiceEvent::ExtraServiceCreated {
                 id,
@@ -158,6 +726,7 @@ impl Execute<ExtraServiceEvent> for Client {
                 description,
                 price,
             } => {
+                self.flush().await?;
                 if let Ok(entity) = ExtraService::create(id, name, description, price) {
                     index.add_documents(&[entity], Some("id")).await?
                 } else {
@@ -167,10 +736,16 @@ impl Execute<ExtraServiceEvent> for Client {
             }
             ExtraServiceEvent::ExtraServiceNameChanged { .. }
             | ExtraServiceEvent::ExtraServiceDescriptionChanged { .. }
-            | ExtraServiceEvent::ExtraServicePriceChanged { .. } => {
-                index.add_or_update(&[event], Some("id")).await?
+            | ExtraServiceEvent::ExtraServicePriceChanged { .. }
+            | ExtraServiceEvent::ExtraServiceTaxChanged { .. } => {
+                return self
+                    .update(ExtraService::ENTITY_NAME, document(&event)?)
+                    .await;
+            }
+            ExtraServiceEvent::ExtraServiceDeleted { id } => {
+                self.flush().await?;
+                index.delete_document(id).await?
             }
-            ExtraServiceEvent::ExtraServiceDeleted { id } => index.delete_document(id).await?,
         };
         self.task_info = Some(task);
         Ok(())
@@ -181,7 +756,8 @@ impl Execute<ExtraServiceEvent> for Client {
 impl Execute<MediaEvent> for Client {
     type Error = meilisearch_sdk::errors::Error;
     async fn execute(&mut self, event: MediaEvent) -> Result<(), Self::Error> {
-        let index = self.meilisearch.index(Media::ENTITY_NAME);
+        let index = self.index(Media::ENTITY_NAME);
+        self.flush().await?;
         let task = match event {
             MediaEvent::MediaCreated { id, data, .. } => {
                 if let Ok(entity) = Media::create(id, data) {
@@ -202,8 +778,7 @@ impl Execute<MediaEvent> for Client {
 impl Execute<ProstituteEvent> for Client {
     type Error = meilisearch_sdk::errors::Error;
     async fn execute(&mut self, event: ProstituteEvent) -> Result<(), Self::Error> {
-        let uid = Prostitute::ENTITY_NAME;
-        let index = self.meilisearch.index(uid);
+        let index = self.index(Prostitute::ENTITY_NAME);
         let task = match event {
             ProstituteEvent::ProstituteJoined {
                 id,
@@ -218,6 +793,9 @@ impl Execute<ProstituteEvent> for Client {
                 images,
                 video,
             } => {
+                self.flush().await?;
+                let figure_fields = FigureFields::from(&figure);
+                let age_field = AgeField::new(birthday.as_ref(), self.today());
                 if let Ok(entity) = Prostitute::join(
                     id,
                     name,
@@ -231,52 +809,114 @@ impl Execute<ProstituteEvent> for Client {
                     images,
                     video,
                 ) {
-                    index.add_documents(&[entity], Some("id")).await?
+                    let mut document = serde_json::to_value(&entity)?;
+                    profile::extend(&mut document, figure_fields)?;
+                    profile::extend(&mut document, age_field)?;
+                    index.add_documents(&[document], Some("id")).await?
                 } else {
                     warn!("不正なエンティティの登録をスキップしました");
                     return Ok(());
                 }
             }
             ProstituteEvent::ProstituteRejoined { id } => {
-                index
-                    .add_or_update(&[json!({"id": id, "leaved": false})], Some("id"))
-                    .await?
+                return self
+                    .update(Prostitute::ENTITY_NAME, json!({"id": id, "leaved": false}))
+                    .await;
             }
             ProstituteEvent::ProstituteLeaved { id } => {
-                index
-                    .add_or_update(&[json!({"id": id, "leaved": true})], Some("id"))
-                    .await?
+                return self
+                    .update(Prostitute::ENTITY_NAME, json!({"id": id, "leaved": true}))
+                    .await;
             }
-            ProstituteEvent::ProstituteExtraServiceNameChanged { .. }
+            ProstituteEvent::ProstituteNameChanged { .. }
             | ProstituteEvent::ProstituteCatchphraseChanged { .. }
             | ProstituteEvent::ProstituteProfileChanged { .. }
             | ProstituteEvent::ProstituteMessageChanged { .. }
-            | ProstituteEvent::ProstituteFigureChanged { .. }
             | ProstituteEvent::ProstituteBloodTypeChanged { .. }
-            | ProstituteEvent::ProstituteBirthdayChanged { .. }
             | ProstituteEvent::ProstituteQuestionsChanged { .. }
             | ProstituteEvent::ProstituteImagesChanged { .. }
             | ProstituteEvent::ProstituteVideoChanged { .. } => {
-                index.add_or_update(&[event], Some("id")).await?
+                return self
+                    .update(Prostitute::ENTITY_NAME, document(&event)?)
+                    .await;
+            }
+            // 絞り込みと並べ替えに使う身長、カップサイズ、年齢も合わせて更新する
+            ProstituteEvent::ProstituteFigureChanged { ref figure, .. } => {
+                let mut document = document(&event)?;
+                profile::extend(&mut document, FigureFields::from(figure))?;
+                return self.update(Prostitute::ENTITY_NAME, document).await;
+            }
+            ProstituteEvent::ProstituteBirthdayChanged { ref birthday, .. } => {
+                let mut document = document(&event)?;
+                profile::extend(
+                    &mut document,
+                    AgeField::new(birthday.as_ref(), self.today()),
+                )?;
+                return self.update(Prostitute::ENTITY_NAME, document).await;
             }
             ProstituteEvent::ProstituteQuestionAdded { id, .. }
             | ProstituteEvent::ProstituteQuestionDeleted { id, .. }
             | ProstituteEvent::ProstituteQuestionSwapped { id, .. }
             | ProstituteEvent::ProstituteImageAdded { id, .. }
             | ProstituteEvent::ProstituteImageDeleted { id, .. }
-            | ProstituteEvent::ProstituteImageSwapped { id, .. } => {
+            | ProstituteEvent::ProstituteImageSwapped { id, .. }
+            | ProstituteEvent::ProstituteTagAdded { id, .. }
+            | ProstituteEvent::ProstituteTagRemoved { id, .. } => {
+                self.flush().await?;
                 self.wait_for_completion().await?;
                 let mut entity = index.get_document::<Prostitute>(&id.to_string()).await?;
                 entity.apply(event);
                 index.add_or_update(&[entity], Some("id")).await?
             }
-            ProstituteEvent::ProstituteDeleted { id } => index.delete_document(id).await?,
+            ProstituteEvent::ProstituteDeleted { id } => {
+                self.flush().await?;
+                index.delete_document(id).await?
+            }
         };
         self.task_info = Some(task);
         Ok(())
     }
 }
 
+#[async_trait]
+impl Execute<TagEvent> for Client {
+    type Error = meilisearch_sdk::errors::Error;
+    async fn execute(&mut self, event: TagEvent) -> Result<(), Self::Error> {
+        let index = self.index(Tag::ENTITY_NAME);
+        let task = match event {
+            TagEvent::TagCreated { id, category, name } => {
+                self.flush().await?;
+                if let Ok(entity) = Tag::create(id, category, name) {
+                    index.add_documents(&[entity], Some("id")).await?
+                } else {
+                    warn!("不正なエンティティの登録をスキップしました");
+                    return Ok(());
+                }
+            }
+            TagEvent::TagRenamed { .. } => {
+                return self.update(Tag::ENTITY_NAME, document(&event)?).await;
+            }
+            TagEvent::TagDeleted { id } => {
+                self.flush().await?;
+                self.wait_for_completion().await?;
+                let prostitutes = self.index(Prostitute::ENTITY_NAME);
+                profile::remove_deleted_tag(&prostitutes, id).await?;
+                index.delete_document(id).await?
+            }
+        };
+        self.task_info = Some(task);
+        Ok(())
+    }
+}
+
+/// イベントの内容（外部タグを除いた値）を部分更新のドキュメントにする
+fn document<E: Serialize>(event: &E) -> Result<Value, serde_json::Error> {
+    match serde_json::to_value(event)? {
+        Value::Object(map) if map.len() == 1 => Ok(map.into_iter().next().unwrap().1),
+        value => Ok(value),
+    }
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct MeiliSchedule {
     id: ScheduleId,
@@ -286,8 +926,11 @@ pub struct MeiliSchedule {
 #[derive(Default, Serialize, Deserialize)]
 pub struct MeiliShift {
     id: ShiftId,
+    #[serde(skip_serializing_if = "Option::is_none")]
     schedule_id: Option<ScheduleId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     time: Option<Range<DateTime<Utc>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     status: Option<ShiftStatus>,
 }
 
@@ -295,8 +938,15 @@ pub struct MeiliShift {
 impl Execute<ScheduleEvent> for Client {
     type Error = meilisearch_sdk::errors::Error;
     async fn execute(&mut self, event: ScheduleEvent) -> Result<(), Self::Error> {
-        let index_schedule = self.meilisearch.index(Schedule::ENTITY_NAME);
-        let index_shift = self.meilisearch.index(Shift::ENTITY_NAME);
+        let index_schedule = self.index(Schedule::ENTITY_NAME);
+        let index_shift = self.index(Shift::ENTITY_NAME);
+        if !matches!(
+            event,
+            ScheduleEvent::ScheduleShiftTimeChanged { .. }
+                | ScheduleEvent::ScheduleShiftStatusChanged { .. }
+        ) {
+            self.flush().await?;
+        }
         let task = match event {
             ScheduleEvent::ScheduleCreated { id, prostitute_id } => {
                 index_schedule
@@ -318,28 +968,24 @@ impl Execute<ScheduleEvent> for Client {
                     .await?
             }
             ScheduleEvent::ScheduleShiftTimeChanged { shift_id, time } => {
-                index_shift
-                    .add_or_update(
-                        &[MeiliShift {
-                            id: shift_id,
-                            time: Some(time),
-                            ..Default::default()
-                        }],
-                        Some("id"),
-                    )
-                    .await?
+                let shift = MeiliShift {
+                    id: shift_id,
+                    time: Some(time),
+                    ..Default::default()
+                };
+                return self
+                    .update(Shift::ENTITY_NAME, serde_json::to_value(shift)?)
+                    .await;
             }
             ScheduleEvent::ScheduleShiftStatusChanged { shift_id, status } => {
-                index_shift
-                    .add_or_update(
-                        &[MeiliShift {
-                            id: shift_id,
-                            status: Some(status),
-                            ..Default::default()
-                        }],
-                        Some("id"),
-                    )
-                    .await?
+                let shift = MeiliShift {
+                    id: shift_id,
+                    status: Some(status),
+                    ..Default::default()
+                };
+                return self
+                    .update(Shift::ENTITY_NAME, serde_json::to_value(shift)?)
+                    .await;
             }
             ScheduleEvent::ScheduleShiftsDeleted { shift_ids } => {
                 index_shift.delete_documents(&shift_ids).await?
@@ -349,3 +995,38 @@ impl Execute<ScheduleEvent> for Client {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use dely::domain::core::ExtraServiceEvent;
+    use serde_json::json;
+
+    use super::{document, Batch};
+
+    #[test]
+    fn test_batch_push() {
+        let mut batch = Batch {
+            uid: "prostitute".to_owned(),
+            documents: Vec::new(),
+        };
+        batch.push(json!({"id": 1, "name": "あい"}));
+        batch.push(json!({"id": 2, "leaved": true}));
+        batch.push(json!({"id": 1, "name": "あいこ", "leaved": false}));
+        assert_eq!(
+            batch.documents,
+            vec![
+                json!({"id": 1, "name": "あいこ", "leaved": false}),
+                json!({"id": 2, "leaved": true}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_document() {
+        let event = ExtraServiceEvent::ExtraServiceNameChanged {
+            id: 1.into(),
+            name: "延長".to_owned(),
+        };
+        assert_eq!(document(&event).unwrap(), json!({"id": 1, "name": "延長"}));
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use axum::{extract::State, routing::get, Router};
+use chrono::{DateTime, Utc};
+use tracing::{error, info};
+
+/// 投影のメトリクス
+#[derive(Default)]
+pub struct Metrics {
+    /// 追い上げ中かどうか
+    catching_up: AtomicBool,
+    /// 最後に投影したイベントの書き込みから投影までの遅延（ミリ秒）
+    lag_ms: AtomicI64,
+    /// 投影したイベントの数
+    events: AtomicU64,
+}
+
+impl Metrics {
+    pub fn set_catching_up(&self, catching_up: bool) {
+        self.catching_up.store(catching_up, Ordering::Relaxed);
+    }
+
+    /// イベントの投影を記録する
+    pub fn observe(&self, created: DateTime<Utc>) {
+        let lag = (Utc::now() - created).num_milliseconds().max(0);
+        self.lag_ms.store(lag, Ordering::Relaxed);
+        self.events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Prometheusのテキスト形式で出力する
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(
+            text,
+            "# HELP dely_sync_lag_seconds イベントの書き込みから投影までの遅延\n\
+             # TYPE dely_sync_lag_seconds gauge\n\
+             dely_sync_lag_seconds {}",
+            self.lag_ms.load(Ordering::Relaxed) as f64 / 1000.0
+        );
+        let _ = writeln!(
+            text,
+            "# HELP dely_sync_catching_up 追い上げ中であれば1\n\
+             # TYPE dely_sync_catching_up gauge\n\
+             dely_sync_catching_up {}",
+            u8::from(self.catching_up.load(Ordering::Relaxed))
+        );
+        let _ = writeln!(
+            text,
+            "# HELP dely_sync_events_total 投影したイベントの数\n\
+             # TYPE dely_sync_events_total counter\n\
+             dely_sync_events_total {}",
+            self.events.load(Ordering::Relaxed)
+        );
+        text
+    }
+}
+
+/// メトリクスをHTTPで公開する
+pub async fn serve(addr: String, metrics: Arc<Metrics>) {
+    let addr = match addr.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!("メトリクスのアドレスが不正です: {}", e);
+            return;
+        }
+    };
+    let app = Router::new()
+        .route("/metrics", get(render))
+        .with_state(metrics);
+    info!("メトリクスを公開します: http://{}/metrics", addr);
+    if let Err(e) = axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+    {
+        error!("メトリクスサーバーエラー: {}", e);
+    }
+}
+
+async fn render(State(metrics): State<Arc<Metrics>>) -> String {
+    metrics.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::Metrics;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.set_catching_up(true);
+        metrics.observe(Utc::now() - Duration::seconds(90));
+        let text = metrics.render();
+        let lag = text
+            .lines()
+            .find_map(|l| l.strip_prefix("dely_sync_lag_seconds "))
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+        assert!((90.0..91.0).contains(&lag));
+        assert!(text.contains("dely_sync_catching_up 1\n"));
+        assert!(text.contains("dely_sync_events_total 1\n"));
+    }
+}
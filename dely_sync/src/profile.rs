@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use chrono::{FixedOffset, NaiveDate, Utc};
+use dely::domain::core::{Birthday, CupSize, Figure, Prostitute, ProstituteId, TagEvent, TagId};
+use meilisearch_sdk::{documents::DocumentsQuery, errors::Error, indexes::Index};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::error;
+
+/// 女の子のドキュメントで絞り込みに使うプロフィールのフィールド
+pub const FILTERABLE_ATTRIBUTES: [&str; 5] = ["tags", "age", "height", "cup_size", "cup_size_rank"];
+
+/// 女の子のドキュメントで並べ替えに使うプロフィールのフィールド（カップサイズは順位で並べ替える）
+pub const SORTABLE_ATTRIBUTES: [&str; 3] = ["age", "height", "cup_size_rank"];
+
+/// 年齢を判定し直す間隔（誕生日は日単位のため1時間ごとで足りる）
+const AGE_REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// 年齢を判定し直す際に1回で取得するドキュメント数
+const AGE_REFRESH_LIMIT: usize = 1000;
+
+/// 削除されたタグを外す際に1回で検索するドキュメント数
+const TAG_REMOVAL_LIMIT: usize = 1000;
+
+/// 体型から求める身長とカップサイズのフィールド
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FigureFields {
+    height: Option<u16>,
+    cup_size: Option<CupSize>,
+    /// カップサイズの順位（AAAを0とした並べ替え用の値）
+    cup_size_rank: Option<u8>,
+}
+
+impl From<&Figure> for FigureFields {
+    fn from(figure: &Figure) -> Self {
+        let cup_size = figure.cup_size();
+        Self {
+            height: figure.height,
+            cup_size,
+            cup_size_rank: cup_size.map(|cup_size| cup_size as u8),
+        }
+    }
+}
+
+/// 誕生日から求める年齢のフィールド
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AgeField {
+    age: Option<i32>,
+}
+
+impl AgeField {
+    pub fn new(birthday: Option<&Birthday>, today: NaiveDate) -> Self {
+        Self {
+            age: birthday.map(|birthday| birthday.age_on(today)),
+        }
+    }
+}
+
+/// ドキュメントにフィールドを追加する
+pub fn extend<T: Serialize>(document: &mut Value, fields: T) -> Result<(), serde_json::Error> {
+    if let (Value::Object(document), Value::Object(fields)) =
+        (document, serde_json::to_value(fields)?)
+    {
+        document.extend(fields);
+    }
+    Ok(())
+}
+
+/// `offset`のタイムゾーンでの今日の日付
+pub fn today(offset: FixedOffset) -> NaiveDate {
+    Utc::now().with_timezone(&offset).date_naive()
+}
+
+#[derive(Deserialize)]
+struct BirthdayDocument {
+    id: ProstituteId,
+    birthday: Option<Birthday>,
+}
+
+#[derive(Serialize)]
+struct AgeDocument {
+    id: ProstituteId,
+    #[serde(flatten)]
+    age: AgeField,
+}
+
+/// 誕生日が登録されている女の子の年齢を判定し直す
+async fn update_ages(index: &Index, today: NaiveDate) -> Result<(), Error> {
+    let mut offset = 0;
+    loop {
+        let page = DocumentsQuery::new(index)
+            .with_offset(offset)
+            .with_limit(AGE_REFRESH_LIMIT)
+            .with_fields(["id", "birthday"])
+            .execute::<BirthdayDocument>()
+            .await?;
+        let documents = page
+            .results
+            .iter()
+            .filter(|document| document.birthday.is_some())
+            .map(|document| AgeDocument {
+                id: document.id,
+                age: AgeField::new(document.birthday.as_ref(), today),
+            })
+            .collect::<Vec<_>>();
+        if !documents.is_empty() {
+            index.add_or_update(&documents, Some("id")).await?;
+        }
+        offset += page.results.len();
+        if page.results.is_empty() || offset >= page.total as usize {
+            return Ok(());
+        }
+    }
+}
+
+/// 削除されたタグが付いた女の子のドキュメントからタグを外す
+pub async fn remove_deleted_tag(index: &Index, id: TagId) -> Result<(), Error> {
+    let filter = format!("tags = {}", id);
+    let mut entities = Vec::new();
+    loop {
+        let results = index
+            .search()
+            .with_filter(&filter)
+            .with_offset(entities.len())
+            .with_limit(TAG_REMOVAL_LIMIT)
+            .execute::<Prostitute>()
+            .await?;
+        let found = results.hits.len();
+        entities.extend(results.hits.into_iter().map(|hit| hit.result));
+        if found < TAG_REMOVAL_LIMIT {
+            break;
+        }
+    }
+    if entities.is_empty() {
+        return Ok(());
+    }
+    let event = TagEvent::TagDeleted { id };
+    for entity in entities.iter_mut() {
+        entity.apply_tag_event(&event);
+    }
+    index.add_or_update(&entities, Some("id")).await?;
+    Ok(())
+}
+
+/// 誕生日を迎えた女の子の年齢を一定間隔で反映する
+pub async fn refresh(index: Index, offset: FixedOffset) {
+    let mut interval = tokio::time::interval(AGE_REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = update_ages(&index, today(offset)).await {
+            error!("年齢の更新エラー: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dely::domain::core::{Bust, VitalStatistics};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_figure_fields() {
+        let figure = Figure {
+            vital_statistics: Some(VitalStatistics {
+                bust: Bust {
+                    top: 88,
+                    under: Some(65),
+                },
+                waist: 58,
+                hip: 86,
+            }),
+            cup_size: None,
+            height: Some(158),
+            weight: None,
+        };
+        assert_eq!(
+            serde_json::to_value(FigureFields::from(&figure)).unwrap(),
+            json!({"height": 158, "cup_size": "F", "cup_size_rank": 7})
+        );
+        assert_eq!(
+            serde_json::to_value(FigureFields::from(&Figure::default())).unwrap(),
+            json!({"height": null, "cup_size": null, "cup_size_rank": null})
+        );
+    }
+
+    #[test]
+    fn test_extend() {
+        let birthday = Birthday::from(NaiveDate::from_ymd_opt(2000, 4, 20).unwrap());
+        let today = NaiveDate::from_ymd_opt(2023, 4, 20).unwrap();
+        let mut document = json!({"id": 1, "birthday": "2000-04-20"});
+        extend(&mut document, AgeField::new(Some(&birthday), today)).unwrap();
+        assert_eq!(
+            document,
+            json!({"id": 1, "birthday": "2000-04-20", "age": 23})
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use dely::{
+    domain::core::{CoreEvent, Currency},
+    infrastructure::{
+        projection::{ProjectedEvent, Projection, ProjectionError},
+        sqlite::{self, CastStatistics, Reports},
+    },
+};
+use meilisearch_sdk::indexes::Index;
+use serde::Serialize;
+
+/// 女の子のドキュメントで並べ替えに使うランキング用のフィールド
+pub const SORTABLE_ATTRIBUTES: [&str; 4] = [
+    "reservation_count",
+    "designation_count",
+    "repeat_ratio",
+    "revenue",
+];
+
+/// 女の子のドキュメントに部分更新で書き込むランキング用のフィールド
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RankingDocument {
+    id: u64,
+    /// 予約数
+    reservation_count: u64,
+    /// 指名数（写真指名と本指名）
+    designation_count: u64,
+    /// リピート率
+    repeat_ratio: f64,
+    /// 売上
+    revenue: i64,
+}
+
+impl From<&CastStatistics> for RankingDocument {
+    fn from(value: &CastStatistics) -> Self {
+        Self {
+            id: value.prostitute_id,
+            reservation_count: value.reservations,
+            designation_count: value.designations,
+            repeat_ratio: value.repeat_ratio,
+            revenue: value.revenue,
+        }
+    }
+}
+
+/// 直近`days`日間に開始した予約の統計を読み取りモデルから集計する（売上は`currency`のものだけを比べる）
+pub fn documents(
+    sqlite_path: &str,
+    days: u32,
+    currency: Currency,
+) -> Result<Vec<RankingDocument>, ProjectionError> {
+    let now = Utc::now();
+    let connection = sqlite::open(sqlite_path)?;
+    Ok(Reports::new(&connection)
+        .cast_statistics(now - Duration::days(days as i64)..now)?
+        .iter()
+        .filter(|statistics| statistics.currency == currency)
+        .map(RankingDocument::from)
+        .collect())
+}
+
+/// 女の子のランキングの投影
+///
+/// 予約と女の子のイベントを受け取った場合に、書き込みの確定時にSQLiteの読み取りモデルから直近の統計を集計し直し、
+/// 女の子のドキュメントを部分更新する。集計期間は現在日時から遡るため、SQLiteの投影より後に登録する。
+pub struct RankingProjection {
+    index: Index,
+    sqlite_path: String,
+    days: u32,
+    currency: Currency,
+    dirty: bool,
+}
+
+impl RankingProjection {
+    pub fn new(index: Index, sqlite_path: &str, days: u32, currency: Currency) -> Self {
+        Self {
+            index,
+            sqlite_path: sqlite_path.to_owned(),
+            days,
+            currency,
+            dirty: false,
+        }
+    }
+}
+
+#[async_trait]
+impl Projection for RankingProjection {
+    fn name(&self) -> &str {
+        "ranking"
+    }
+
+    async fn project(&mut self, event: &ProjectedEvent) -> Result<(), ProjectionError> {
+        if matches!(
+            event.event,
+            CoreEvent::ReservationEvent(_) | CoreEvent::ProstituteEvent(_)
+        ) {
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ProjectionError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let (path, days, currency) = (self.sqlite_path.clone(), self.days, self.currency);
+        let documents =
+            tokio::task::spawn_blocking(move || documents(&path, days, currency)).await??;
+        if !documents.is_empty() {
+            self.index.add_or_update(&documents, Some("id")).await?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_ranking_document() {
+        let statistics = CastStatistics {
+            prostitute_id: 1,
+            name: "あい".to_owned(),
+            reservations: 4,
+            designations: 3,
+            customers: 2,
+            repeat_customers: 1,
+            repeat_ratio: 0.5,
+            revenue: 60000,
+            currency: Currency::JPY,
+        };
+        assert_eq!(
+            serde_json::to_value(RankingDocument::from(&statistics)).unwrap(),
+            json!({
+                "id": 1,
+                "reservation_count": 4,
+                "designation_count": 3,
+                "repeat_ratio": 0.5,
+                "revenue": 60000
+            })
+        );
+    }
+}
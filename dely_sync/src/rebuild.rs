@@ -0,0 +1,138 @@
+use std::error::Error;
+
+use chrono::Utc;
+use dely::{
+    domain::{core::Prostitute, Entity},
+    DelyConfig,
+};
+use meilisearch_sdk::{
+    client::SwapIndexes,
+    errors::{Error as MeilisearchError, ErrorCode},
+    task_info::TaskInfo,
+};
+use tracing::info;
+
+use crate::{
+    apply_settings, badge, catch_up, dead_letter::DeadLetterStore, metrics::Metrics, ranking,
+    wait_for_task, Client, RetryPolicy, INDEXES,
+};
+
+/// 読み取りモデルをイベントログから再構築する
+///
+/// `{インデックス名}_v{バージョン}`のインデックスに最初から投影し、追いついた時点で現在のインデックスと
+/// アトミックに入れ替える。入れ替えまでの間は現在のインデックスで検索を継続できる。
+pub async fn rebuild(config: &DelyConfig, version: u32) -> Result<(), Box<dyn Error>> {
+    let suffix = format!("_v{}", version);
+    let mut client = Client::new(config)?.with_suffix(&suffix);
+    let retry = RetryPolicy::from(config);
+    let dead_letters = DeadLetterStore::new(&config.sync.dead_letter_path);
+
+    for uid in INDEXES {
+        let versioned = format!("{}{}", uid, suffix);
+        // 前回の再構築が途中で終了していた場合に備えて作り直す
+        if index_exists(&client.meilisearch, &versioned).await? {
+            let task_info = client.meilisearch.delete_index(&versioned).await?;
+            wait_unless(&client.meilisearch, &task_info, ErrorCode::IndexNotFound).await?;
+        }
+        let task_info = client
+            .meilisearch
+            .create_index(&versioned, Some("id"))
+            .await?;
+        wait_for_task(&client.meilisearch, &task_info).await?;
+        // 入れ替え先が存在しないと入れ替えに失敗するため、現在のインデックスも用意する
+        if !index_exists(&client.meilisearch, uid).await? {
+            let task_info = client.meilisearch.create_index(uid, Some("id")).await?;
+            wait_unless(
+                &client.meilisearch,
+                &task_info,
+                ErrorCode::IndexAlreadyExists,
+            )
+            .await?;
+        }
+    }
+    apply_settings(&client).await?;
+
+    info!("インデックス{}を再構築します", suffix);
+    let batch_size = config.sync.catch_up_batch_size;
+    let position = catch_up(
+        &mut client,
+        &retry,
+        &dead_letters,
+        None,
+        &Metrics::default(),
+        None,
+        batch_size,
+    )
+    .await?;
+
+    // 入れ替え後も並べ替えと絞り込みが変わらないよう、ランキングとバッジも書き込んでから入れ替える
+    if let Some(path) = &config.sync.sqlite_path {
+        let index = client.index(Prostitute::ENTITY_NAME);
+        let documents =
+            ranking::documents(path, config.sync.ranking_days, config.sync.ranking_currency)
+                .map_err(|e| e as Box<dyn Error>)?;
+        if !documents.is_empty() {
+            let task_info = index.add_or_update(&documents, Some("id")).await?;
+            wait_for_task(&client.meilisearch, &task_info).await?;
+        }
+        let documents = badge::documents(path, &config.badges, config.sync.offset(), Utc::now())
+            .map_err(|e| e as Box<dyn Error>)?;
+        if !documents.is_empty() {
+            let task_info = index.add_or_update(&documents, Some("id")).await?;
+            wait_for_task(&client.meilisearch, &task_info).await?;
+        }
+    }
+
+    let swaps = INDEXES
+        .iter()
+        .map(|uid| SwapIndexes {
+            indexes: (uid.to_string(), format!("{}{}", uid, suffix)),
+        })
+        .collect::<Vec<_>>();
+    let task_info = client.meilisearch.swap_indexes(&swaps).await?;
+    wait_for_task(&client.meilisearch, &task_info).await?;
+    info!("インデックスを入れ替えました");
+
+    // 入れ替えまでの間に購読側が旧インデックスへ投影したイベントを新しいインデックスへ反映する
+    let mut client = Client::new(config)?;
+    if position.is_some() {
+        catch_up(
+            &mut client,
+            &retry,
+            &dead_letters,
+            None,
+            &Metrics::default(),
+            position,
+            batch_size,
+        )
+        .await?;
+    }
+    info!("入れ替え前のインデックスは接尾辞{}で残っています", suffix);
+    Ok(())
+}
+
+/// インデックスが存在するか
+async fn index_exists(
+    meilisearch: &meilisearch_sdk::Client,
+    uid: &str,
+) -> Result<bool, MeilisearchError> {
+    match meilisearch.get_index(uid).await {
+        Ok(_) => Ok(true),
+        Err(MeilisearchError::Meilisearch(e)) if e.error_code == ErrorCode::IndexNotFound => {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// タスクの完了を待機する（確認後に他から作成・削除された場合の指定したエラーコードの失敗は無視する）
+async fn wait_unless(
+    meilisearch: &meilisearch_sdk::Client,
+    task_info: &TaskInfo,
+    code: ErrorCode,
+) -> Result<(), MeilisearchError> {
+    match wait_for_task(meilisearch, task_info).await {
+        Err(MeilisearchError::Meilisearch(e)) if e.error_code == code => Ok(()),
+        result => result.map(|_| ()),
+    }
+}
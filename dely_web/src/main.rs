@@ -1,10 +1,160 @@
-use axum::{routing::get, Router};
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path, Query, State},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
 use axum_server::tls_rustls::RustlsConfig;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, TimeZone, Utc};
+use dely::{
+    domain::{
+        core::{
+            Billing, CashClosing, CashClosingId, CashClosingRepository, Coupon, CouponApplication,
+            CouponId, CouponRepository, Currency, CustomerId, ExtraService, Money, PointError,
+            PointLedger, PointLedgerId, PointLedgerRepository, PointLot, PointPolicy, Prostitute,
+            ProstituteId, Receipt, ReceiptBook, ReceiptBookId, ReceiptBookRepository, ReceiptError,
+            ReceiptKind, ReservationCustomer, ReservationDetail, ReservationDetailId,
+            ReservationError, ReservationId, ReservationRepository, Schedule, ScheduleRepository,
+            ServiceCatalog, ServiceId, Tag, TaxCategory, TaxInclusion, TaxTable,
+        },
+        ActorId, Aggregation, DataAccessError, EventEnvelope, Id, Metadata, Role,
+    },
+    infrastructure::{
+        self,
+        core::{
+            EventStoreCashClosingRepository, EventStoreCouponRepository,
+            EventStorePointLedgerRepository, EventStoreReceiptBookRepository,
+            EventStoreReservationRepository, EventStoreScheduleRepository,
+        },
+        csv::to_csv,
+        pdf,
+        sqlite::{self, ReportDimension, ReportPeriod, Reports},
+        text, EventConvertError, HistoryEntry,
+    },
+    DelyConfig, Invoice, Payroll,
+};
+use eventstore::{Client, ResolvedEvent};
+use serde::{de::IntoDeserializer, Deserialize, Serialize};
+use tracing::{error, Level};
+use uuid::Uuid;
+
+/// 同時に更新された場合に読み込み直して保存を試みる回数
+const SAVE_ATTEMPTS: usize = 3;
+
+/// 操作者IDのヘッダー
+const ACTOR_ID_HEADER: &str = "x-actor-id";
+
+/// 操作者の役割のヘッダー（`Manager`、`Staff`など）
+const ACTOR_ROLE_HEADER: &str = "x-actor-role";
+
+/// 相関IDのヘッダー
+const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+
+/// リクエストの操作者と相関IDから生成するイベントメタデータ
+///
+/// 操作者IDと役割がない場合は`401 Unauthorized`、不正な値の場合は`400 Bad Request`を返す。
+/// システムの役割は受け付けない。相関IDを省略した場合は新しい相関IDを生成する。
+struct RequestMetadata(Metadata);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestMetadata {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name| {
+            parts
+                .headers
+                .get(name)
+                .map(|value| value.to_str().map_err(|_| StatusCode::BAD_REQUEST))
+                .transpose()
+        };
+        let actor_id = header(ACTOR_ID_HEADER)?
+            .ok_or(StatusCode::UNAUTHORIZED)?
+            .parse::<u64>()
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let role = header(ACTOR_ROLE_HEADER)?.ok_or(StatusCode::UNAUTHORIZED)?;
+        let role = Role::deserialize(role.into_deserializer())
+            .map_err(|_: serde::de::value::Error| StatusCode::BAD_REQUEST)?;
+        if role == Role::System {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        let metadata = Metadata::new(ActorId::from(actor_id), role);
+        Ok(Self(match header(CORRELATION_ID_HEADER)? {
+            Some(id) => {
+                metadata.correlated_with(Uuid::parse_str(id).map_err(|_| StatusCode::BAD_REQUEST)?)
+            }
+            None => metadata,
+        }))
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    client: Client,
+    sqlite_path: Option<String>,
+    payroll: Payroll,
+    invoice: Invoice,
+    tax: TaxTable,
+    billing: Billing,
+    points: PointPolicy,
+    services: ServiceCatalog,
+}
+
+impl FromRef<AppState> for Client {
+    fn from_ref(state: &AppState) -> Self {
+        state.client.clone()
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    // build our application with a single route
-    let app = Router::new().route("/", get(|| async { "Hello, World!" }));
+    let config = DelyConfig::load().unwrap();
+    tracing_subscriber::fmt()
+        .with_max_level(Level::from(&config.logger.level))
+        .init();
+    let client = Client::new(config.eventstore.url.parse().unwrap()).unwrap();
+
+    // build our application
+    let app = Router::new()
+        .route("/", get(|| async { "Hello, World!" }))
+        .route("/prostitutes/:id/history", get(history::<Prostitute>))
+        .route("/extra_services/:id/history", get(history::<ExtraService>))
+        .route("/schedules/:id/history", get(history::<Schedule>))
+        .route("/cash_closings/:id", get(cash_closing))
+        .route("/cash_closings/:id/history", get(history::<CashClosing>))
+        .route("/prostitutes/:id/payout", get(payout))
+        .route("/reports/sales", get(sales))
+        .route("/reservations/:id/details", post(add_reservation_detail))
+        .route("/reservations/:id/extensions", post(extend_reservation))
+        .route("/reservations/:id/coupons", post(redeem_coupon))
+        .route("/reservations/:id/complete", post(complete_reservation))
+        .route("/reservations/:id/points", post(redeem_points))
+        .route("/reservations/:id/receipts", post(issue_receipt))
+        .route(
+            "/receipt_books/:id/receipts/:number",
+            get(receipt).post(reissue_receipt),
+        )
+        .route("/receipt_books/:id/history", get(history::<ReceiptBook>))
+        .route("/coupons/quote", get(quote_coupon))
+        .route("/coupons/:id/history", get(history::<Coupon>))
+        .route("/reports/coupons", get(coupon_redemptions))
+        .route("/reports/designations", get(designation_counts))
+        .route("/reports/rankings", get(cast_statistics))
+        .route("/customers/:id/points", get(points))
+        .route("/point_ledgers/:id/history", get(history::<PointLedger>))
+        .route("/tags/:id/history", get(history::<Tag>))
+        .with_state(AppState {
+            client,
+            sqlite_path: config.sync.sqlite_path.clone(),
+            payroll: config.payroll.clone(),
+            invoice: config.invoice.clone(),
+            tax: config.tax.clone(),
+            billing: config.billing.clone(),
+            points: config.points.clone(),
+            services: config.services.clone(),
+        });
 
     let config = RustlsConfig::from_pem_file("localhost.pem", "localhost.key")
         .await
@@ -16,3 +166,1094 @@ async fn main() {
         .await
         .unwrap();
 }
+
+/// 集約の変更履歴を返す
+async fn history<A>(
+    State(client): State<Client>,
+    Path(id): Path<u64>,
+) -> Result<Json<Vec<HistoryEntry<A::Event>>>, StatusCode>
+where
+    A: Aggregation + Send,
+    A::Id: Id<Inner = u64> + Send,
+    A::Event: Send,
+    EventEnvelope<A::Event>: TryFrom<ResolvedEvent, Error = EventConvertError>,
+{
+    match infrastructure::history::<A>(&client, A::Id::from(id)).await {
+        Ok(Some(entries)) => Ok(Json(entries)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("履歴取得エラー: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// 売上レポートの出力形式
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// 売上レポートの条件
+#[derive(Debug, Deserialize)]
+struct SalesQuery {
+    /// 集計開始日時（この日時のタイムゾーンで集計期間を区切る）
+    from: DateTime<FixedOffset>,
+    /// 集計終了日時（この日時を含まない）
+    to: DateTime<FixedOffset>,
+    period: ReportPeriod,
+    #[serde(default = "total")]
+    by: ReportDimension,
+    #[serde(default)]
+    format: ReportFormat,
+}
+
+fn total() -> ReportDimension {
+    ReportDimension::Total
+}
+
+/// 期間内の売上を集計期間と内訳ごとに返す
+async fn sales(
+    State(state): State<AppState>,
+    Query(query): Query<SalesQuery>,
+) -> Result<Response, StatusCode> {
+    let path = state.sqlite_path.ok_or(StatusCode::NOT_FOUND)?;
+    let rows = tokio::task::spawn_blocking(move || {
+        let connection = sqlite::open(path)?;
+        Reports::new(&connection).sales(
+            query.from.with_timezone(&Utc)..query.to.with_timezone(&Utc),
+            *query.from.offset(),
+            query.period,
+            query.by,
+        )
+    })
+    .await
+    .map_err(|e| {
+        error!("売上レポート取得エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .map_err(|e| {
+        error!("売上レポート取得エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(match query.format {
+        ReportFormat::Json => Json(rows).into_response(),
+        ReportFormat::Csv => (
+            [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+            to_csv(&rows),
+        )
+            .into_response(),
+    })
+}
+
+/// 支払明細の出力形式
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PayoutFormat {
+    #[default]
+    Json,
+    Csv,
+    Pdf,
+}
+
+/// 支払明細の条件
+#[derive(Debug, Deserialize)]
+struct PayoutQuery {
+    /// 対象期間の開始日時（この日時のタイムゾーンでPDFの日時を表示する）
+    from: DateTime<FixedOffset>,
+    /// 対象期間の終了日時（この日時を含まない）
+    to: DateTime<FixedOffset>,
+    #[serde(default)]
+    format: PayoutFormat,
+}
+
+/// 女の子の期間内の支払明細を返す
+async fn payout(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Query(query): Query<PayoutQuery>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("支払明細作成エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let prostitute_id = ProstituteId::from(id);
+    let period = query.from.with_timezone(&Utc)..query.to.with_timezone(&Utc);
+    let path = state.sqlite_path.ok_or(StatusCode::NOT_FOUND)?;
+    let range = period.clone();
+    let (name, reservation_ids, schedule_ids) = tokio::task::spawn_blocking(move || {
+        let connection = sqlite::open(path)?;
+        let reports = Reports::new(&connection);
+        Ok::<_, rusqlite::Error>((
+            reports.cast_member_name(prostitute_id)?,
+            reports.reservation_ids(prostitute_id, range)?,
+            reports.schedule_ids(prostitute_id)?,
+        ))
+    })
+    .await
+    .map_err(|e| internal_error(&e))?
+    .map_err(|e| internal_error(&e))?;
+    let name = name.ok_or(StatusCode::NOT_FOUND)?;
+
+    let repository = EventStoreReservationRepository::new(state.client.clone());
+    let mut reservations = Vec::new();
+    for id in reservation_ids {
+        if let Some(reservation) = repository
+            .find_by_id(id)
+            .await
+            .map_err(|e| internal_error(&e))?
+        {
+            reservations.push(reservation);
+        }
+    }
+    let repository = EventStoreScheduleRepository::new(state.client);
+    let mut shifts = Vec::new();
+    for id in schedule_ids {
+        if let Some(schedule) = repository
+            .find_by_id(id)
+            .await
+            .map_err(|e| internal_error(&e))?
+        {
+            shifts.extend_from_slice(schedule.shifts());
+        }
+    }
+
+    let statement = state
+        .payroll
+        .rules
+        .statement(prostitute_id, period, &reservations, &shifts, Utc::now())
+        .map_err(|e| internal_error(&e))?;
+    Ok(match query.format {
+        PayoutFormat::Json => Json(statement).into_response(),
+        PayoutFormat::Csv => (
+            [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+            to_csv(&statement.lines),
+        )
+            .into_response(),
+        PayoutFormat::Pdf => {
+            let font = match &state.payroll.font_path {
+                Some(path) => Some(
+                    tokio::fs::read(path)
+                        .await
+                        .map_err(|e| internal_error(&e))?,
+                ),
+                None => None,
+            };
+            let pdf =
+                pdf::payout_statement(&statement, &name, *query.from.offset(), font.as_deref())
+                    .map_err(|e| internal_error(&e))?;
+            ([(header::CONTENT_TYPE, "application/pdf")], pdf).into_response()
+        }
+    })
+}
+
+/// レジ締めの条件
+#[derive(Debug, Deserialize)]
+struct CashClosingQuery {
+    /// 営業日を区切るタイムゾーン（`+09:00`形式、省略時は日本時間）
+    offset: Option<String>,
+}
+
+/// レジ締めレポート
+#[derive(Debug, Serialize)]
+struct CashClosingReport {
+    /// 営業日
+    business_day: NaiveDate,
+    /// レジ締め開始時（または最後に更新した時点）の予定額
+    expected: Option<Money>,
+    /// 数えた現金
+    counted: Option<Money>,
+    /// 過不足（正数は過剰、負数は不足）
+    difference: Option<Money>,
+    /// 締められているか
+    locked: bool,
+    /// 読み取りモデルから再計算した現在の予約詳細の合計（通貨ごと）
+    reservations_total: Option<Vec<Money>>,
+}
+
+/// 営業日のレジ締めの状況を返す
+async fn cash_closing(
+    State(state): State<AppState>,
+    Path(business_day): Path<NaiveDate>,
+    Query(query): Query<CashClosingQuery>,
+) -> Result<Json<CashClosingReport>, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("レジ締め取得エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let offset = parse_offset(query.offset)?;
+    let closing = EventStoreCashClosingRepository::new(state.client)
+        .find_by_id(CashClosingId::from(business_day))
+        .await
+        .map_err(|e| internal_error(&e))?;
+
+    let reservations_total = match state.sqlite_path {
+        Some(path) => {
+            let start = offset
+                .from_local_datetime(&business_day.and_hms_opt(0, 0, 0).unwrap())
+                .unwrap()
+                .with_timezone(&Utc);
+            tokio::task::spawn_blocking(move || {
+                let connection = sqlite::open(path)?;
+                Reports::new(&connection).sales(
+                    start..start + Duration::days(1),
+                    offset,
+                    ReportPeriod::Daily,
+                    ReportDimension::Total,
+                )
+            })
+            .await
+            .map_err(|e| internal_error(&e))?
+            .map_err(|e| internal_error(&e))?
+            .iter()
+            .map(|row| Money::new(row.amount, row.currency))
+            .collect::<Vec<_>>()
+            .into()
+        }
+        None => None,
+    };
+    if closing.is_none() && reservations_total.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(CashClosingReport {
+        business_day,
+        expected: closing.as_ref().map(|c| c.expected().clone()),
+        counted: closing.as_ref().and_then(|c| c.counted().cloned()),
+        difference: closing
+            .as_ref()
+            .map(|c| c.difference())
+            .transpose()
+            .map_err(|e| internal_error(&e))?
+            .flatten(),
+        locked: closing.as_ref().is_some_and(|c| c.is_locked()),
+        reservations_total,
+    }))
+}
+
+/// `+09:00`形式のタイムゾーンを解析する（省略時は日本時間）
+fn parse_offset(offset: Option<String>) -> Result<FixedOffset, StatusCode> {
+    match offset {
+        Some(offset) => offset
+            .parse::<FixedOffset>()
+            .map_err(|_| StatusCode::BAD_REQUEST),
+        None => Ok(FixedOffset::east_opt(9 * 3600).unwrap()),
+    }
+}
+
+/// 領収書の出力形式
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ReceiptFormat {
+    #[default]
+    Json,
+    Text,
+    Pdf,
+}
+
+/// 領収書の発行条件
+#[derive(Debug, Deserialize)]
+struct ReceiptQuery {
+    /// 書類の種類
+    #[serde(default)]
+    kind: ReceiptKind,
+    /// 宛名（省略時は「上様」）
+    addressee: Option<String>,
+    /// 日付を表示するタイムゾーン（`+09:00`形式、省略時は日本時間）
+    offset: Option<String>,
+    #[serde(default)]
+    format: ReceiptFormat,
+}
+
+/// 追加する予約詳細
+#[derive(Debug, Deserialize)]
+struct ReservationDetailRequest {
+    id: u64,
+    name: String,
+    #[serde(default = "default_quantity")]
+    quantity: u32,
+    /// 単価
+    amount: i64,
+    #[serde(default)]
+    currency: Currency,
+    #[serde(default)]
+    tax_category: TaxCategory,
+    #[serde(default)]
+    tax_inclusion: TaxInclusion,
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+/// 予約に予約詳細を追加する
+///
+/// 税率は予約の日付で設定の税率表から取得する。追加できない場合は422と理由を返す。
+/// 読み込んだ後に予約が更新された場合は`409 Conflict`を返す。
+async fn add_reservation_detail(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    RequestMetadata(metadata): RequestMetadata,
+    Json(request): Json<ReservationDetailRequest>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("予約詳細追加エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let mut repository = EventStoreReservationRepository::new(state.client);
+    let mut reservation = repository
+        .find_by_id(ReservationId::from(id))
+        .await
+        .map_err(|e| internal_error(&e))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let detail = match ReservationDetail::create(
+        ReservationDetailId::from(request.id),
+        request.name,
+        request.quantity,
+        Money::new(request.amount, request.currency),
+    ) {
+        Ok(detail) => detail.with_tax(request.tax_category, 0, request.tax_inclusion),
+        Err(e) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()),
+    };
+    if let Err(e) = reservation.add_detail(detail, &state.tax) {
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response());
+    }
+    repository
+        .save(&mut reservation, &metadata)
+        .await
+        .map_err(|e| match e {
+            DataAccessError::ConflictError(_) => StatusCode::CONFLICT,
+            e => internal_error(&e),
+        })?;
+    Ok(Json(&reservation.details()[reservation.details().len() - 1]).into_response())
+}
+
+/// 予約の延長
+#[derive(Debug, Deserialize)]
+struct ExtensionRequest {
+    /// 延長料金の予約詳細のID
+    detail_id: u64,
+    /// 延長する時間（分）
+    minutes: i64,
+    /// 延長するサービスのID
+    service_id: u64,
+}
+
+/// 予約を延長し、延長料金の予約詳細を返す
+///
+/// 延長料金は設定のサービスの延長価格から設定の課金方法で計算し、税率は予約の日付で設定の税率表から取得する。サービスが見つからない場合や延長できない場合は422と理由を返す。
+async fn extend_reservation(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    RequestMetadata(metadata): RequestMetadata,
+    Json(request): Json<ExtensionRequest>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("予約延長エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let service = match state
+        .services
+        .find_by_id(ServiceId::from(request.service_id))
+    {
+        Ok(Some(service)) => service,
+        Ok(None) => {
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, "Service not found").into_response())
+        }
+        Err(e) => return Err(internal_error(&e)),
+    };
+    if service.extension_price().is_none() {
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ReservationError::NoExtensionPrice.to_string(),
+        )
+            .into_response());
+    }
+    let mut repository = EventStoreReservationRepository::new(state.client);
+    let mut reservation = repository
+        .find_by_id(ReservationId::from(id))
+        .await
+        .map_err(|e| internal_error(&e))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let detail = match reservation.extend_time(
+        Duration::minutes(request.minutes),
+        ReservationDetailId::from(request.detail_id),
+        &service,
+        &state.billing,
+        &state.tax,
+    ) {
+        Ok(detail) => detail.clone(),
+        Err(e) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()),
+    };
+    repository
+        .save(&mut reservation, &metadata)
+        .await
+        .map_err(|e| match e {
+            DataAccessError::ConflictError(_) => StatusCode::CONFLICT,
+            e => internal_error(&e),
+        })?;
+    Ok(Json(detail).into_response())
+}
+
+/// 領収書の出力条件
+#[derive(Debug, Deserialize)]
+struct ReceiptOutputQuery {
+    /// 日付を表示するタイムゾーン（`+09:00`形式、省略時は日本時間）
+    offset: Option<String>,
+    #[serde(default)]
+    format: ReceiptFormat,
+}
+
+/// 予約の領収書を発行する
+///
+/// 発行年の領収書控えに通し番号で記録する。同じ予約に発行済みの場合は`409 Conflict`を返すため、再発行する。
+async fn issue_receipt(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    RequestMetadata(metadata): RequestMetadata,
+    Query(query): Query<ReceiptQuery>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("領収書発行エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let offset = parse_offset(query.offset)?;
+    let reservation = EventStoreReservationRepository::new(state.client.clone())
+        .find_by_id(ReservationId::from(id))
+        .await
+        .map_err(|e| internal_error(&e))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let now = Utc::now();
+    let book_id = ReceiptBookId::from(now.with_timezone(&offset).year() as u64);
+    let addressee = query.addressee.unwrap_or_else(|| "上様".to_owned());
+    let mut repository = EventStoreReceiptBookRepository::new(state.client);
+    // 同時に発行された場合は領収書控えを読み込み直して次の通し番号で発行する
+    let mut issued = None;
+    for _ in 0..SAVE_ATTEMPTS {
+        let mut book = repository
+            .find_by_id(book_id)
+            .await
+            .map_err(|e| internal_error(&e))?
+            .unwrap_or_else(|| ReceiptBook::open(book_id));
+        let receipt = book
+            .issue(
+                query.kind,
+                &reservation,
+                state.invoice.issuer.clone(),
+                addressee.clone(),
+                now,
+            )
+            .map_err(|e| match e {
+                ReceiptError::AlreadyIssued => StatusCode::CONFLICT,
+                ReceiptError::NoDetails => StatusCode::UNPROCESSABLE_ENTITY,
+                e => internal_error(&e),
+            })?
+            .clone();
+        match repository.save(&mut book, &metadata).await {
+            Ok(_) => {
+                issued = Some(receipt);
+                break;
+            }
+            Err(DataAccessError::ConflictError(_)) => continue,
+            Err(e) => return Err(internal_error(&e)),
+        }
+    }
+    let receipt = issued.ok_or(StatusCode::CONFLICT)?;
+    receipt_response(&receipt, query.format, offset, &state.invoice).await
+}
+
+/// 発行済みの領収書を再発行する（再発行の記録を残す）
+async fn reissue_receipt(
+    State(state): State<AppState>,
+    Path((id, number)): Path<(u64, u64)>,
+    RequestMetadata(metadata): RequestMetadata,
+    Query(query): Query<ReceiptOutputQuery>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("領収書再発行エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let offset = parse_offset(query.offset)?;
+    let mut repository = EventStoreReceiptBookRepository::new(state.client);
+    let mut book = repository
+        .find_by_id(ReceiptBookId::from(id))
+        .await
+        .map_err(|e| internal_error(&e))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let receipt = book
+        .reissue(number, Utc::now())
+        .map_err(|_| StatusCode::NOT_FOUND)?
+        .clone();
+    repository
+        .save(&mut book, &metadata)
+        .await
+        .map_err(|e| internal_error(&e))?;
+    receipt_response(&receipt, query.format, offset, &state.invoice).await
+}
+
+/// 発行済みの領収書の控えを返す（発行の記録は残さない）
+async fn receipt(
+    State(state): State<AppState>,
+    Path((id, number)): Path<(u64, u64)>,
+    Query(query): Query<ReceiptOutputQuery>,
+) -> Result<Response, StatusCode> {
+    let offset = parse_offset(query.offset)?;
+    let receipt = EventStoreReceiptBookRepository::new(state.client)
+        .find_by_id(ReceiptBookId::from(id))
+        .await
+        .map_err(|e| {
+            error!("領収書取得エラー: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .and_then(|book| book.receipt(number).cloned())
+        .ok_or(StatusCode::NOT_FOUND)?;
+    receipt_response(&receipt, query.format, offset, &state.invoice).await
+}
+
+async fn receipt_response(
+    receipt: &Receipt,
+    format: ReceiptFormat,
+    offset: FixedOffset,
+    invoice: &Invoice,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("領収書出力エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    Ok(match format {
+        ReceiptFormat::Json => Json(receipt).into_response(),
+        ReceiptFormat::Text => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            text::receipt(receipt, offset),
+        )
+            .into_response(),
+        ReceiptFormat::Pdf => {
+            let font = match &invoice.font_path {
+                Some(path) => Some(
+                    tokio::fs::read(path)
+                        .await
+                        .map_err(|e| internal_error(&e))?,
+                ),
+                None => None,
+            };
+            let pdf =
+                pdf::receipt(receipt, offset, font.as_deref()).map_err(|e| internal_error(&e))?;
+            ([(header::CONTENT_TYPE, "application/pdf")], pdf).into_response()
+        }
+    })
+}
+
+/// クーポンの割引額の見積もり条件
+#[derive(Debug, Deserialize)]
+struct CouponQuoteQuery {
+    /// クーポンコード（大文字・小文字は区別しない）
+    code: String,
+    /// 割引前の小計
+    amount: i64,
+    #[serde(default)]
+    currency: Currency,
+    customer_id: Option<u64>,
+    /// 初回利用のお客様か
+    #[serde(default)]
+    first_visit: bool,
+    birthday: Option<NaiveDate>,
+    /// 利用日時（省略時は現在日時）
+    time: Option<DateTime<FixedOffset>>,
+}
+
+/// クーポンの割引額の見積もり
+#[derive(Debug, Serialize)]
+struct CouponQuote {
+    coupon_id: CouponId,
+    code: String,
+    name: String,
+    discount: Money,
+}
+
+/// クーポンコードを検証して割引額を返す（利用できない場合は422と理由を返す）
+async fn quote_coupon(
+    State(state): State<AppState>,
+    Query(query): Query<CouponQuoteQuery>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("クーポン取得エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let path = state.sqlite_path.ok_or(StatusCode::NOT_FOUND)?;
+    let code = Coupon::normalize(&query.code);
+    let id = tokio::task::spawn_blocking(move || {
+        let connection = sqlite::open(path)?;
+        Reports::new(&connection).coupon_id(&code)
+    })
+    .await
+    .map_err(|e| internal_error(&e))?
+    .map_err(|e| internal_error(&e))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    let coupon = EventStoreCouponRepository::new(state.client)
+        .find_by_id(id)
+        .await
+        .map_err(|e| internal_error(&e))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let application = CouponApplication {
+        customer_id: query.customer_id.map(CustomerId::from),
+        first_visit: query.first_visit,
+        birthday: query.birthday,
+        subtotal: Money::new(query.amount, query.currency),
+        time: query.time.unwrap_or_else(|| Utc::now().fixed_offset()),
+    };
+    Ok(match coupon.quote(&application) {
+        Ok(discount) => Json(CouponQuote {
+            coupon_id: id,
+            code: coupon.code().to_owned(),
+            name: coupon.name().to_owned(),
+            discount,
+        })
+        .into_response(),
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response(),
+    })
+}
+
+/// 予約でのクーポンの利用
+#[derive(Debug, Deserialize)]
+struct CouponRedemptionRequest {
+    /// クーポンコード（大文字・小文字は区別しない）
+    code: String,
+    /// 割引の予約詳細のID
+    detail_id: u64,
+    /// 初回利用のお客様か
+    #[serde(default)]
+    first_visit: bool,
+    birthday: Option<NaiveDate>,
+}
+
+/// 予約でクーポンを利用し、割引の予約詳細を追加して返す（利用できない場合は422と理由を返す）
+///
+/// 同時に利用された場合はクーポンを読み込み直して利用回数の上限を検証し直す。
+async fn redeem_coupon(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    RequestMetadata(metadata): RequestMetadata,
+    Json(request): Json<CouponRedemptionRequest>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("クーポン利用エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let path = state.sqlite_path.clone().ok_or(StatusCode::NOT_FOUND)?;
+    let code = Coupon::normalize(&request.code);
+    let coupon_id = tokio::task::spawn_blocking(move || {
+        let connection = sqlite::open(path)?;
+        Reports::new(&connection).coupon_id(&code)
+    })
+    .await
+    .map_err(|e| internal_error(&e))?
+    .map_err(|e| internal_error(&e))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    let mut reservations = EventStoreReservationRepository::new(state.client.clone());
+    let reservation = reservations
+        .find_by_id(ReservationId::from(id))
+        .await
+        .map_err(|e| internal_error(&e))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // 割引前の合計とお客様は予約から取得する
+    let application = CouponApplication {
+        customer_id: None,
+        first_visit: request.first_visit,
+        birthday: request.birthday,
+        subtotal: Money::default(),
+        time: Utc::now().fixed_offset(),
+    };
+    let mut coupons = EventStoreCouponRepository::new(state.client);
+    let mut redeemed = None;
+    for _ in 0..SAVE_ATTEMPTS {
+        let mut coupon = coupons
+            .find_by_id(coupon_id)
+            .await
+            .map_err(|e| internal_error(&e))?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let detail = match coupon.redeem(
+            &reservation,
+            ReservationDetailId::from(request.detail_id),
+            &application,
+        ) {
+            Ok(detail) => detail,
+            Err(e) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()),
+        };
+        let mut updated = reservation.clone();
+        if let Err(e) = updated.add_detail(detail, &state.tax) {
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response());
+        }
+        match coupons.save(&mut coupon, &metadata).await {
+            Ok(_) => {
+                redeemed = Some(updated);
+                break;
+            }
+            Err(DataAccessError::ConflictError(_)) => continue,
+            Err(e) => return Err(internal_error(&e)),
+        }
+    }
+    let mut reservation = redeemed.ok_or(StatusCode::CONFLICT)?;
+    let detail = reservation.details()[reservation.details().len() - 1].clone();
+    // 読み込んだ後に予約が更新されていたなどで保存できない場合はクーポンの利用を取り消す
+    let saved = match reservations.save(&mut reservation, &metadata).await {
+        Ok(_) => Ok(()),
+        Err(DataAccessError::ConflictError(_)) => Err(StatusCode::CONFLICT),
+        Err(e) => Err(internal_error(&e)),
+    };
+    if let Err(status) = saved {
+        cancel_coupon_redemption(&mut coupons, coupon_id, ReservationId::from(id), &metadata)
+            .await?;
+        return Err(status);
+    }
+    Ok(Json(detail).into_response())
+}
+
+/// 予約を保存できなかった場合にクーポンの利用を取り消す
+async fn cancel_coupon_redemption(
+    coupons: &mut EventStoreCouponRepository,
+    id: CouponId,
+    reservation_id: ReservationId,
+    metadata: &Metadata,
+) -> Result<(), StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("クーポン利用取り消しエラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    for _ in 0..SAVE_ATTEMPTS {
+        let mut coupon = coupons
+            .find_by_id(id)
+            .await
+            .map_err(|e| internal_error(&e))?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        if let Err(e) = coupon.cancel_redemption(reservation_id) {
+            return Err(internal_error(&e));
+        }
+        match coupons.save(&mut coupon, metadata).await {
+            Ok(_) => return Ok(()),
+            Err(DataAccessError::ConflictError(_)) => continue,
+            Err(e) => return Err(internal_error(&e)),
+        }
+    }
+    Err(internal_error(&"クーポンの利用を取り消せませんでした"))
+}
+
+/// クーポン利用レポートの条件
+#[derive(Debug, Deserialize)]
+struct CouponReportQuery {
+    from: DateTime<FixedOffset>,
+    /// 集計終了日時（この日時を含まない）
+    to: DateTime<FixedOffset>,
+    #[serde(default)]
+    format: ReportFormat,
+}
+
+/// 期間内のクーポンの利用回数と割引額をクーポンごとに返す
+async fn coupon_redemptions(
+    State(state): State<AppState>,
+    Query(query): Query<CouponReportQuery>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("クーポン利用レポート取得エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let path = state.sqlite_path.ok_or(StatusCode::NOT_FOUND)?;
+    let rows = tokio::task::spawn_blocking(move || {
+        let connection = sqlite::open(path)?;
+        Reports::new(&connection)
+            .coupon_redemptions(query.from.with_timezone(&Utc)..query.to.with_timezone(&Utc))
+    })
+    .await
+    .map_err(|e| internal_error(&e))?
+    .map_err(|e| internal_error(&e))?;
+    Ok(match query.format {
+        ReportFormat::Json => Json(rows).into_response(),
+        ReportFormat::Csv => (
+            [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+            to_csv(&rows),
+        )
+            .into_response(),
+    })
+}
+
+/// ポイント残高
+#[derive(Debug, Serialize)]
+struct PointBalance {
+    /// 記録済みのイベントに基づく残高
+    balance: u64,
+    /// 失効の記録に関わらず、現在使える残高
+    available: u64,
+    /// 付与ごとの残高
+    lots: Vec<PointLot>,
+}
+
+/// お客様のポイント残高を返す
+async fn points(
+    State(client): State<Client>,
+    Path(customer_id): Path<u64>,
+) -> Result<Json<PointBalance>, StatusCode> {
+    let ledger = EventStorePointLedgerRepository::new(client)
+        .find_by_id(PointLedgerId::from(CustomerId::from(customer_id)))
+        .await
+        .map_err(|e| {
+            error!("ポイント台帳取得エラー: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(PointBalance {
+        balance: ledger.balance(),
+        available: ledger.balance_at(Utc::now()),
+        lots: ledger.lots().to_vec(),
+    }))
+}
+
+/// 予約の完了で付与したポイント
+#[derive(Debug, Serialize)]
+struct Completion {
+    earned_points: u64,
+}
+
+/// 予約を完了し、登録済みのお客様にポイントを付与する
+///
+/// 完了済みの予約はポイントの付与だけを行うため、付与に失敗した場合は再び呼び出せる。
+/// 付与済みの場合は`409 Conflict`を返す。
+async fn complete_reservation(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    RequestMetadata(metadata): RequestMetadata,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("予約完了エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let mut reservations = EventStoreReservationRepository::new(state.client.clone());
+    let mut reservation = reservations
+        .find_by_id(ReservationId::from(id))
+        .await
+        .map_err(|e| internal_error(&e))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if !reservation.is_completed() {
+        if let Err(e) = reservation.complete() {
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response());
+        }
+        reservations
+            .save(&mut reservation, &metadata)
+            .await
+            .map_err(|e| match e {
+                DataAccessError::ConflictError(_) => StatusCode::CONFLICT,
+                e => internal_error(&e),
+            })?;
+    }
+    let customer_id = match reservation.customer() {
+        ReservationCustomer::Registered { id } => *id,
+        _ => return Ok(Json(Completion { earned_points: 0 }).into_response()),
+    };
+    let mut ledgers = EventStorePointLedgerRepository::new(state.client);
+    let mut earned = None;
+    for _ in 0..SAVE_ATTEMPTS {
+        let mut ledger = ledgers
+            .find_by_id(PointLedgerId::from(customer_id))
+            .await
+            .map_err(|e| internal_error(&e))?
+            .unwrap_or_else(|| PointLedger::open(customer_id));
+        let points = ledger
+            .earn(&reservation, &state.points, Utc::now())
+            .map_err(|e| match e {
+                PointError::AlreadyEarned => StatusCode::CONFLICT,
+                e => internal_error(&e),
+            })?;
+        match ledgers.save(&mut ledger, &metadata).await {
+            Ok(_) => {
+                earned = Some(points);
+                break;
+            }
+            Err(DataAccessError::ConflictError(_)) => continue,
+            Err(e) => return Err(internal_error(&e)),
+        }
+    }
+    let earned_points = earned.ok_or(StatusCode::CONFLICT)?;
+    Ok(Json(Completion { earned_points }).into_response())
+}
+
+/// 予約の支払いに使うポイント
+#[derive(Debug, Deserialize)]
+struct PointRedemptionRequest {
+    points: u64,
+    /// ポイント利用の予約詳細のID
+    detail_id: u64,
+}
+
+/// 予約の支払いにポイントを使い、ポイント利用の予約詳細を追加して返す（使えない場合は422と理由を返す）
+async fn redeem_points(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    RequestMetadata(metadata): RequestMetadata,
+    Json(request): Json<PointRedemptionRequest>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("ポイント利用エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let mut reservations = EventStoreReservationRepository::new(state.client.clone());
+    let reservation = reservations
+        .find_by_id(ReservationId::from(id))
+        .await
+        .map_err(|e| internal_error(&e))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let customer_id = match reservation.customer() {
+        ReservationCustomer::Registered { id } => *id,
+        _ => return Err(StatusCode::UNPROCESSABLE_ENTITY),
+    };
+    let mut ledgers = EventStorePointLedgerRepository::new(state.client);
+    let mut redeemed = None;
+    for _ in 0..SAVE_ATTEMPTS {
+        let mut ledger = ledgers
+            .find_by_id(PointLedgerId::from(customer_id))
+            .await
+            .map_err(|e| internal_error(&e))?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let detail = match ledger.redeem(
+            &reservation,
+            request.points,
+            ReservationDetailId::from(request.detail_id),
+            Utc::now(),
+        ) {
+            Ok(detail) => detail,
+            Err(e) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()),
+        };
+        let mut updated = reservation.clone();
+        if let Err(e) = updated.add_detail(detail, &state.tax) {
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response());
+        }
+        match ledgers.save(&mut ledger, &metadata).await {
+            Ok(_) => {
+                redeemed = Some(updated);
+                break;
+            }
+            Err(DataAccessError::ConflictError(_)) => continue,
+            Err(e) => return Err(internal_error(&e)),
+        }
+    }
+    let mut reservation = redeemed.ok_or(StatusCode::CONFLICT)?;
+    let detail = reservation.details()[reservation.details().len() - 1].clone();
+    // 読み込んだ後に予約が更新されていたなどで保存できない場合は使ったポイントを戻す
+    let saved = match reservations.save(&mut reservation, &metadata).await {
+        Ok(_) => Ok(()),
+        Err(DataAccessError::ConflictError(_)) => Err(StatusCode::CONFLICT),
+        Err(e) => Err(internal_error(&e)),
+    };
+    if let Err(status) = saved {
+        restore_redeemed_points(
+            &mut ledgers,
+            PointLedgerId::from(customer_id),
+            ReservationId::from(id),
+            request.points,
+            &metadata,
+        )
+        .await?;
+        return Err(status);
+    }
+    Ok(Json(detail).into_response())
+}
+
+/// 予約を保存できなかった場合に使ったポイントを調整で戻す
+async fn restore_redeemed_points(
+    ledgers: &mut EventStorePointLedgerRepository,
+    id: PointLedgerId,
+    reservation_id: ReservationId,
+    points: u64,
+    metadata: &Metadata,
+) -> Result<(), StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("ポイント利用取り消しエラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let points = i64::try_from(points).map_err(|e| internal_error(&e))?;
+    for _ in 0..SAVE_ATTEMPTS {
+        let mut ledger = ledgers
+            .find_by_id(id)
+            .await
+            .map_err(|e| internal_error(&e))?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        if let Err(e) = ledger.adjust(
+            points,
+            format!("予約{}のポイント利用の取り消し", reservation_id),
+            Utc::now(),
+        ) {
+            return Err(internal_error(&e));
+        }
+        match ledgers.save(&mut ledger, metadata).await {
+            Ok(_) => return Ok(()),
+            Err(DataAccessError::ConflictError(_)) => continue,
+            Err(e) => return Err(internal_error(&e)),
+        }
+    }
+    Err(internal_error(&"使ったポイントを戻せませんでした"))
+}
+
+/// 指名数レポートとランキングの条件
+#[derive(Debug, Deserialize)]
+struct DesignationReportQuery {
+    from: DateTime<FixedOffset>,
+    /// 集計終了日時（この日時を含まない）
+    to: DateTime<FixedOffset>,
+    #[serde(default)]
+    format: ReportFormat,
+}
+
+/// 期間内の予約数を女の子ごとに指名の種類別に返す
+async fn designation_counts(
+    State(state): State<AppState>,
+    Query(query): Query<DesignationReportQuery>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("指名数レポート取得エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let path = state.sqlite_path.ok_or(StatusCode::NOT_FOUND)?;
+    let rows = tokio::task::spawn_blocking(move || {
+        let connection = sqlite::open(path)?;
+        Reports::new(&connection)
+            .designation_counts(query.from.with_timezone(&Utc)..query.to.with_timezone(&Utc))
+    })
+    .await
+    .map_err(|e| internal_error(&e))?
+    .map_err(|e| internal_error(&e))?;
+    Ok(match query.format {
+        ReportFormat::Json => Json(rows).into_response(),
+        ReportFormat::Csv => (
+            [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+            to_csv(&rows),
+        )
+            .into_response(),
+    })
+}
+
+/// 期間内の予約数、指名数、リピート率、売上を女の子ごとに返す
+async fn cast_statistics(
+    State(state): State<AppState>,
+    Query(query): Query<DesignationReportQuery>,
+) -> Result<Response, StatusCode> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!("ランキング取得エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let path = state.sqlite_path.ok_or(StatusCode::NOT_FOUND)?;
+    let rows = tokio::task::spawn_blocking(move || {
+        let connection = sqlite::open(path)?;
+        Reports::new(&connection)
+            .cast_statistics(query.from.with_timezone(&Utc)..query.to.with_timezone(&Utc))
+    })
+    .await
+    .map_err(|e| internal_error(&e))?
+    .map_err(|e| internal_error(&e))?;
+    Ok(match query.format {
+        ReportFormat::Json => Json(rows).into_response(),
+        ReportFormat::Csv => (
+            [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+            to_csv(&rows),
+        )
+            .into_response(),
+    })
+}
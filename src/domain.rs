@@ -1,6 +1,7 @@
 pub mod core;
 
-use derive_more::{Display, From};
+use chrono::{DateTime, Utc};
+use derive_more::{Deref, Display, From};
 use once_cell::sync;
 use serde::{Deserialize, Serialize};
 use snowflake::SnowflakeIdGenerator;
@@ -16,6 +17,7 @@ use tokio::{
     sync::{mpsc, oneshot},
     task::JoinHandle,
 };
+use uuid::Uuid;
 
 /// ID
 pub trait Id:
@@ -35,6 +37,100 @@ pub trait Id:
 pub trait Event: Clone + Eq + Debug + Serialize + for<'de> Deserialize<'de> {
     /// ID
     type Id;
+
+    /// スキーマバージョン（イベントの形を変更したら上げる）
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+/// イベントエンベロープ
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventEnvelope<E> {
+    /// イベント
+    pub event: E,
+    /// メタデータ（メタデータ導入前のイベントは`None`）
+    pub metadata: Option<Metadata>,
+}
+
+/// イベントメタデータ
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// 操作者ID
+    #[serde(rename = "actorId")]
+    pub actor_id: ActorId,
+    /// 操作者の役割
+    pub role: Role,
+    /// 発生日時
+    pub timestamp: DateTime<Utc>,
+    /// 相関ID
+    #[serde(rename = "$correlationId")]
+    pub correlation_id: Uuid,
+    /// 原因ID
+    #[serde(
+        rename = "$causationId",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub causation_id: Option<Uuid>,
+}
+
+impl Metadata {
+    /// 新しい相関IDでメタデータを生成する
+    pub fn new(actor_id: ActorId, role: Role) -> Self {
+        Self {
+            actor_id,
+            role,
+            timestamp: Utc::now(),
+            correlation_id: Uuid::new_v4(),
+            causation_id: None,
+        }
+    }
+
+    /// システムによる操作のメタデータを生成する
+    pub fn system() -> Self {
+        Self::new(ActorId::default(), Role::System)
+    }
+
+    /// 相関IDを指定する
+    pub fn correlated_with(self, correlation_id: Uuid) -> Self {
+        Self {
+            correlation_id,
+            ..self
+        }
+    }
+
+    /// 原因となったイベントIDを指定する
+    pub fn caused_by(self, causation_id: Uuid) -> Self {
+        Self {
+            causation_id: Some(causation_id),
+            ..self
+        }
+    }
+}
+
+/// 操作者ID
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display, From, Deref, Default, Hash,
+)]
+pub struct ActorId(u64);
+
+impl Id for ActorId {
+    type Inner = u64;
+}
+
+/// 操作者の役割
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default, Hash)]
+pub enum Role {
+    /// システム
+    #[default]
+    System,
+    /// 管理者
+    Manager,
+    /// スタッフ
+    Staff,
+    /// 女の子
+    Prostitute,
+    /// お客様
+    Customer,
 }
 
 /// エンティティ
@@ -107,6 +203,9 @@ pub enum DataAccessError {
     ReadError(Box<dyn error::Error>),
     #[display(fmt = "Data write error: {}", "_0.to_string()")]
     WriteError(Box<dyn error::Error>),
+    /// 読み込んだ後に他の書き込みで更新されています
+    #[display(fmt = "Data conflict error: {}", _0)]
+    ConflictError(Box<dyn error::Error>),
     #[display(fmt = "Client side error: {}", "_0.to_string()")]
     ClientSideError(Box<dyn error::Error>),
 }
@@ -118,6 +217,7 @@ impl error::Error for DataAccessError {
             DataAccessError::QueryError(e) => Some(e.as_ref()),
             DataAccessError::ReadError(e) => Some(e.as_ref()),
             DataAccessError::WriteError(e) => Some(e.as_ref()),
+            DataAccessError::ConflictError(e) => Some(e.as_ref()),
             DataAccessError::ClientSideError(e) => Some(e.as_ref()),
         }
     }
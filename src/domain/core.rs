@@ -1,38 +1,66 @@
+mod cash_closing;
+mod coupon;
 mod customer;
 mod extra_service;
 mod media;
+mod payroll;
+mod point_ledger;
 mod prostitute;
+mod receipt;
 mod reservation;
 mod schedule;
 mod service;
+mod tag;
+mod tax;
 
-use std::fmt;
+use std::{cmp::Ordering, fmt};
 
-use derive_more::{Display, From, FromStr};
+use chrono::Duration;
+
+use derive_more::{Display, Error, From, FromStr};
 use num_format::Locale;
 use num_format::ToFormattedString;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 
+pub use self::cash_closing::*;
+pub use self::coupon::*;
 pub use self::customer::*;
 pub use self::extra_service::*;
 pub use self::media::*;
+pub use self::payroll::*;
+pub use self::point_ledger::*;
 pub use self::prostitute::*;
+pub use self::receipt::*;
 pub use self::reservation::*;
 pub use self::schedule::*;
 pub use self::service::*;
+pub use self::tag::*;
+pub use self::tax::*;
 
 /// コアイベント
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, From)]
 pub enum CoreEvent {
+    /// レジ締めイベント
+    CashClosingEvent(CashClosingEvent),
+    /// クーポンイベント
+    CouponEvent(CouponEvent),
     /// オプションサービスイベント
     ExtraServiceEvent(ExtraServiceEvent),
     /// メディアイベント
     MediaEvent(MediaEvent),
+    /// ポイント台帳イベント
+    PointLedgerEvent(PointLedgerEvent),
     /// 女の子イベント
     ProstituteEvent(ProstituteEvent),
+    /// 領収書控えイベント
+    ReceiptBookEvent(ReceiptBookEvent),
+    /// 予約イベント
+    ReservationEvent(ReservationEvent),
     /// スケジュールイベント
     ScheduleEvent(ScheduleEvent),
+    /// タグイベント
+    TagEvent(TagEvent),
 }
 
 /// MIMEタイプ
@@ -53,6 +81,8 @@ impl Default for Mime {
 }
 
 /// 金額
+///
+/// 金額は通貨の最小単位（円、セントなど）で保持する。
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct Money {
     amount: i64,
@@ -64,6 +94,28 @@ impl Money {
         Money { amount, currency }
     }
 
+    /// 金額が0の`Money`を生成する
+    pub fn zero(currency: Currency) -> Money {
+        Money::new(0, currency)
+    }
+
+    /// 金額を合計する（空の場合は`currency`の0を返す）
+    pub fn sum<'a>(
+        iter: impl IntoIterator<Item = &'a Money>,
+        currency: Currency,
+    ) -> Result<Money, MoneyError> {
+        iter.into_iter()
+            .try_fold(Money::zero(currency), |sum, money| sum.checked_add(money))
+    }
+
+    pub fn amount(&self) -> i64 {
+        self.amount
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
     /// `self`が負数である場合は`true`、`0`または正数の場合は`false`を返します。
     pub fn is_positive(&self) -> bool {
         self.amount.is_positive()
@@ -73,12 +125,153 @@ impl Money {
     pub fn is_negative(&self) -> bool {
         self.amount.is_negative()
     }
+
+    /// 金額を加算する
+    pub fn checked_add(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.validate_currency(other)?;
+        self.amount
+            .checked_add(other.amount)
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// 金額を減算する
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.validate_currency(other)?;
+        self.amount
+            .checked_sub(other.amount)
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// 金額に数量を掛ける
+    pub fn checked_mul(&self, quantity: i64) -> Result<Money, MoneyError> {
+        self.amount
+            .checked_mul(quantity)
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// 金額に`numerator / denominator`を掛け、通貨の最小単位に丸める
+    pub fn checked_ratio(
+        &self,
+        numerator: i64,
+        denominator: i64,
+        rounding: Rounding,
+    ) -> Result<Money, MoneyError> {
+        if denominator == 0 {
+            return Err(MoneyError::DivisionByZero);
+        }
+        let product = (self.amount as i128) * (numerator as i128);
+        let amount = rounding.divide(product, denominator as i128);
+        i64::try_from(amount)
+            .map(|amount| Money::new(amount, self.currency))
+            .map_err(|_| MoneyError::Overflow)
+    }
+
+    /// 金額の`percent`%を計算し、通貨の最小単位に丸める
+    pub fn percentage(&self, percent: i64, rounding: Rounding) -> Result<Money, MoneyError> {
+        self.checked_ratio(percent, 100, rounding)
+    }
+
+    /// 金額を`unit`（最小単位での刻み、10円単位なら`10`、1ドル単位なら`100`）の倍数に丸める
+    pub fn round(&self, unit: i64, rounding: Rounding) -> Result<Money, MoneyError> {
+        if unit <= 0 {
+            return Err(MoneyError::DivisionByZero);
+        }
+        let amount = rounding.divide(self.amount as i128, unit as i128) * unit as i128;
+        i64::try_from(amount)
+            .map(|amount| Money::new(amount, self.currency))
+            .map_err(|_| MoneyError::Overflow)
+    }
+
+    fn validate_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        match self.currency == other.currency {
+            true => Ok(()),
+            false => Err(MoneyError::CurrencyMismatch),
+        }
+    }
+}
+
+/// 通貨が異なる場合は比較できない（`None`を返す）
+impl PartialOrd for Money {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.currency == other.currency {
+            true => Some(self.amount.cmp(&other.amount)),
+            false => None,
+        }
+    }
 }
 
 impl fmt::Display for Money {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.currency {
-            Currency::JPY => write!(f, "¥{}", self.amount.to_formatted_string(&Locale::ja)),
+        let sign = if self.is_negative() { "-" } else { "" };
+        let scale = 10_u64.pow(self.currency.minor_units());
+        let major = (self.amount.unsigned_abs() / scale).to_formatted_string(&Locale::ja);
+        let minor = self.amount.unsigned_abs() % scale;
+        write!(f, "{}{}{}", sign, self.currency.symbol(), major)?;
+        match self.currency.minor_units() {
+            0 => Ok(()),
+            digits => write!(f, ".{:0width$}", minor, width = digits as usize),
+        }
+    }
+}
+
+/// 金額の計算エラー
+#[derive(Error, Display, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoneyError {
+    /// 通貨が一致しません
+    #[display(fmt = "Currency does not match")]
+    CurrencyMismatch,
+    /// 金額が範囲を超えました
+    #[display(fmt = "Amount overflowed")]
+    Overflow,
+    /// 0で割ろうとしました
+    #[display(fmt = "Division by zero")]
+    DivisionByZero,
+}
+
+/// 端数の丸め方
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Rounding {
+    /// 切り捨て（0に近づける、日本の消費税の端数処理で一般的）
+    #[default]
+    Down,
+    /// 切り上げ（0から遠ざける）
+    Up,
+    /// 四捨五入
+    HalfUp,
+    /// 偶数丸め（銀行家の丸め、セント単位の通貨で一般的）
+    HalfEven,
+}
+
+impl Rounding {
+    /// `dividend / divisor`をこの丸め方で整数にする
+    fn divide(&self, dividend: i128, divisor: i128) -> i128 {
+        let quotient = dividend / divisor;
+        let remainder = dividend % divisor;
+        if remainder == 0 {
+            return quotient;
+        }
+        let direction = if (dividend < 0) == (divisor < 0) {
+            1
+        } else {
+            -1
+        };
+        let twice = (remainder * 2).abs().cmp(&divisor.abs());
+        let away = match self {
+            Rounding::Down => false,
+            Rounding::Up => true,
+            Rounding::HalfUp => twice != Ordering::Less,
+            Rounding::HalfEven => match twice {
+                Ordering::Less => false,
+                Ordering::Greater => true,
+                Ordering::Equal => quotient % 2 != 0,
+            },
+        };
+        match away {
+            true => quotient + direction,
+            false => quotient,
         }
     }
 }
@@ -89,6 +282,38 @@ pub enum Currency {
     /// 日本円
     #[default]
     JPY,
+    /// 米ドル
+    USD,
+    /// ユーロ
+    EUR,
+    /// 韓国ウォン
+    KRW,
+    /// 台湾ドル
+    TWD,
+    /// 人民元
+    CNY,
+}
+
+impl Currency {
+    /// 最小単位の桁数（円とウォンは0、それ以外は2）
+    pub fn minor_units(&self) -> u32 {
+        match self {
+            Currency::JPY | Currency::KRW => 0,
+            Currency::USD | Currency::EUR | Currency::TWD | Currency::CNY => 2,
+        }
+    }
+
+    /// 通貨記号
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Currency::JPY => "¥",
+            Currency::USD => "$",
+            Currency::EUR => "€",
+            Currency::KRW => "₩",
+            Currency::TWD => "NT$",
+            Currency::CNY => "CN¥",
+        }
+    }
 }
 
 /// 価格単位
@@ -106,11 +331,64 @@ pub enum PriceUnit {
 pub struct Price {
     amount: Money,
     unit: PriceUnit,
+    #[serde(default)]
+    tax_category: TaxCategory,
+    #[serde(default)]
+    tax_inclusion: TaxInclusion,
 }
 
 impl Price {
     pub fn new(amount: Money, unit: PriceUnit) -> Price {
-        Price { amount, unit }
+        Price {
+            amount,
+            unit,
+            ..Default::default()
+        }
+    }
+
+    /// 税区分と税込・税抜を指定する
+    pub fn with_tax(self, tax_category: TaxCategory, tax_inclusion: TaxInclusion) -> Self {
+        Self {
+            tax_category,
+            tax_inclusion,
+            ..self
+        }
+    }
+
+    pub fn amount(&self) -> &Money {
+        &self.amount
+    }
+
+    pub fn unit(&self) -> PriceUnit {
+        self.unit
+    }
+
+    pub fn tax_category(&self) -> TaxCategory {
+        self.tax_category
+    }
+
+    pub fn tax_inclusion(&self) -> TaxInclusion {
+        self.tax_inclusion
+    }
+
+    /// 利用時間に対する金額を計算する
+    ///
+    /// 一回限りの価格は利用時間に関わらず同じ金額を返す。時間単位の価格は`billing`で数えた分数で1時間あたりの価格を按分する（最小単位未満は切り捨て）。
+    pub fn for_duration(
+        &self,
+        duration: Duration,
+        billing: &Billing,
+    ) -> Result<Money, PricingError> {
+        if duration < Duration::zero() {
+            return Err(PricingError::NegativeDuration);
+        }
+        match self.unit {
+            PriceUnit::OneTime => Ok(self.amount.clone()),
+            PriceUnit::Hourly => {
+                let minutes = billing.billed_minutes(duration)?;
+                Ok(self.amount.checked_ratio(minutes, 60, Rounding::Down)?)
+            }
+        }
     }
 
     /// `self`が負数である場合は`true`、`0`または正数の場合は`false`を返します。
@@ -123,3 +401,188 @@ impl Price {
         self.amount.is_negative()
     }
 }
+
+/// 時間単位の価格の課金方法
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Billing {
+    /// 課金単位（分）
+    pub granularity: u32,
+    /// 課金単位に満たない時間の扱い
+    pub rounding: BillingRounding,
+}
+
+impl Billing {
+    /// 課金対象の分数（1分未満は1分に切り上げる）
+    pub fn billed_minutes(&self, duration: Duration) -> Result<i64, PricingError> {
+        if self.granularity == 0 {
+            return Err(PricingError::InvalidGranularity);
+        }
+        let minutes = (duration.num_seconds() + 59).div_euclid(60);
+        let unit = self.granularity as i64;
+        Ok(match self.rounding {
+            BillingRounding::RoundUp => (minutes + unit - 1) / unit * unit,
+            BillingRounding::ProRata => minutes,
+        })
+    }
+}
+
+/// 30分単位で切り上げる
+impl Default for Billing {
+    fn default() -> Self {
+        Billing {
+            granularity: 30,
+            rounding: BillingRounding::RoundUp,
+        }
+    }
+}
+
+/// 課金単位に満たない時間の扱い
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum BillingRounding {
+    /// 1単位に切り上げる
+    #[default]
+    RoundUp,
+    /// 分単位で按分する
+    ProRata,
+}
+
+/// 価格の計算エラー
+#[derive(Error, Display, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PricingError {
+    /// 利用時間が負です
+    #[display(fmt = "Duration cannot be negative")]
+    NegativeDuration,
+    /// 課金単位が不正です
+    #[display(fmt = "Billing granularity must be positive")]
+    InvalidGranularity,
+    /// 金額の計算エラー
+    #[display(fmt = "Money error: {}", _0)]
+    MoneyError(#[error(source)] MoneyError),
+}
+
+impl From<MoneyError> for PricingError {
+    fn from(value: MoneyError) -> Self {
+        PricingError::MoneyError(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yen(amount: i64) -> Money {
+        Money::new(amount, Currency::JPY)
+    }
+
+    #[test]
+    fn test_money_arithmetic() {
+        assert_eq!(yen(1000).checked_add(&yen(500)), Ok(yen(1500)));
+        assert_eq!(yen(1000).checked_sub(&yen(1500)), Ok(yen(-500)));
+        assert_eq!(yen(1000).checked_mul(3), Ok(yen(3000)));
+        assert_eq!(
+            yen(1000).checked_add(&Money::new(500, Currency::USD)),
+            Err(MoneyError::CurrencyMismatch)
+        );
+        assert_eq!(
+            Money::new(i64::MAX, Currency::JPY).checked_add(&yen(1)),
+            Err(MoneyError::Overflow)
+        );
+        assert_eq!(
+            yen(1000).checked_ratio(1, 0, Rounding::Down),
+            Err(MoneyError::DivisionByZero)
+        );
+        assert!(yen(1000) > yen(500));
+        assert_eq!(
+            yen(1000).partial_cmp(&Money::new(1000, Currency::KRW)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_money_rounding() {
+        // 1055円の8%は84.4円、1065円の10%は106.5円
+        assert_eq!(yen(1055).percentage(8, Rounding::Down), Ok(yen(84)));
+        assert_eq!(yen(1055).percentage(8, Rounding::Up), Ok(yen(85)));
+        assert_eq!(yen(1065).percentage(10, Rounding::HalfUp), Ok(yen(107)));
+        assert_eq!(yen(1065).percentage(10, Rounding::HalfEven), Ok(yen(106)));
+        assert_eq!(yen(-1065).percentage(10, Rounding::HalfUp), Ok(yen(-107)));
+        assert_eq!(yen(-1055).percentage(8, Rounding::Down), Ok(yen(-84)));
+        assert_eq!(yen(1234).round(10, Rounding::HalfUp), Ok(yen(1230)));
+        // 12.345ドル相当（1234.5セント）は偶数丸めで1234セント
+        let usd = Money::new(2469, Currency::USD);
+        assert_eq!(
+            usd.checked_ratio(1, 2, Rounding::HalfEven),
+            Ok(Money::new(1234, Currency::USD))
+        );
+    }
+
+    #[test]
+    fn test_money_sum() {
+        let sum = Money::sum(&[yen(100), yen(200), yen(300)], Currency::JPY);
+        assert_eq!(sum, Ok(yen(600)));
+        let sum = Money::sum(&[yen(100), Money::new(200, Currency::EUR)], Currency::JPY);
+        assert_eq!(sum, Err(MoneyError::CurrencyMismatch));
+        let sum = Money::sum(&[Money::new(200, Currency::EUR)], Currency::JPY);
+        assert_eq!(sum, Err(MoneyError::CurrencyMismatch));
+        // 空の場合も指定した通貨の0になる
+        assert_eq!(
+            Money::sum(&[], Currency::USD),
+            Ok(Money::zero(Currency::USD))
+        );
+    }
+
+    #[test]
+    fn test_money_display() {
+        assert_eq!(yen(1000000).to_string(), "¥1,000,000");
+        assert_eq!(yen(-1500).to_string(), "-¥1,500");
+        assert_eq!(Money::new(123456, Currency::USD).to_string(), "$1,234.56");
+        assert_eq!(Money::new(-5, Currency::EUR).to_string(), "-€0.05");
+        assert_eq!(Money::new(50000, Currency::KRW).to_string(), "₩50,000");
+        assert_eq!(Money::new(10000, Currency::TWD).to_string(), "NT$100.00");
+        assert_eq!(Money::new(9990, Currency::CNY).to_string(), "CN¥99.90");
+    }
+
+    #[test]
+    fn test_price_for_duration() {
+        let hourly = Price::new(yen(6000), PriceUnit::Hourly);
+        let billing = |granularity, rounding| Billing {
+            granularity,
+            rounding,
+        };
+        let duration = Duration::minutes(61);
+        assert_eq!(
+            hourly.for_duration(duration, &Billing::default()),
+            Ok(yen(9000))
+        );
+        assert_eq!(
+            hourly.for_duration(duration, &billing(10, BillingRounding::RoundUp)),
+            Ok(yen(7000))
+        );
+        assert_eq!(
+            hourly.for_duration(duration, &billing(10, BillingRounding::ProRata)),
+            Ok(yen(6100))
+        );
+        assert_eq!(
+            hourly.for_duration(
+                Duration::seconds(45 * 60 + 1),
+                &billing(10, BillingRounding::ProRata)
+            ),
+            Ok(yen(4600))
+        );
+        assert_eq!(
+            hourly.for_duration(duration, &billing(0, BillingRounding::RoundUp)),
+            Err(PricingError::InvalidGranularity)
+        );
+        assert_eq!(
+            hourly.for_duration(-duration, &Billing::default()),
+            Err(PricingError::NegativeDuration)
+        );
+
+        let one_time = Price::new(yen(5000), PriceUnit::OneTime);
+        assert_eq!(
+            one_time.for_duration(duration, &Billing::default()),
+            Ok(yen(5000))
+        );
+    }
+}
@@ -0,0 +1,440 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use derive_more::{Deref, Display, Error, From, IntoIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata};
+
+use super::{Money, MoneyError, PaymentMethod, Reservation};
+
+/// レジ締めリポジトリ
+#[async_trait]
+pub trait CashClosingRepository {
+    /// レジ締めをIDで検索する
+    async fn find_by_id(&self, id: CashClosingId) -> Result<Option<CashClosing>, DataAccessError>;
+    /// 指定日時時点のレジ締めをIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: CashClosingId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<CashClosing>, DataAccessError>;
+    /// 指定リビジョン時点のレジ締めをIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: CashClosingId,
+        revision: u64,
+    ) -> Result<Option<CashClosing>, DataAccessError>;
+    /// レジ締めを保存する
+    async fn save(
+        &mut self,
+        entity: &mut CashClosing,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
+}
+
+/// レジ締めID
+///
+/// 営業日ごとに1つだけ作成するため、営業日を`YYYYMMDD`形式の数値にしたものを使う。
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display, From, Deref, Default,
+)]
+pub struct CashClosingId(u64);
+
+impl Id for CashClosingId {
+    type Inner = u64;
+}
+
+impl From<NaiveDate> for CashClosingId {
+    fn from(value: NaiveDate) -> Self {
+        Self(value.year() as u64 * 10000 + value.month() as u64 * 100 + value.day() as u64)
+    }
+}
+
+/// レジ締めイベント
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CashClosingEvent {
+    /// レジ締めが開始された
+    CashClosingOpened {
+        id: CashClosingId,
+        business_day: NaiveDate,
+        expected: Money,
+    },
+    /// 予定額が更新された
+    CashClosingExpectedUpdated { id: CashClosingId, expected: Money },
+    /// 現金が数えられた
+    CashClosingCashCounted { id: CashClosingId, counted: Money },
+    /// レジ締めが確定された
+    CashClosingLocked {
+        id: CashClosingId,
+        difference: Money,
+    },
+}
+
+impl Event for CashClosingEvent {
+    type Id = CashClosingId;
+}
+
+/// レジ締めエンティティ
+#[derive(Debug, Default, Clone, IntoIterator, Serialize, Deserialize)]
+pub struct CashClosing {
+    id: CashClosingId,
+    business_day: NaiveDate,
+    expected: Money,
+    counted: Option<Money>,
+    locked: bool,
+    #[serde(skip)]
+    #[into_iterator]
+    events: EventQueue<CashClosingEvent>,
+}
+
+impl CashClosing {
+    /// 営業日のレジ締めを開始する
+    pub fn open(business_day: NaiveDate, expected: Money) -> Self {
+        let id = CashClosingId::from(business_day);
+        let mut entity = CashClosing {
+            id,
+            business_day,
+            expected: expected.clone(),
+            ..Default::default()
+        };
+        entity.events.push(CashClosingEvent::CashClosingOpened {
+            id,
+            business_day,
+            expected,
+        });
+        entity
+    }
+
+    /// 予約詳細の合計から現金以外の支払い（返金を差し引いた金額）を差し引いて予定額を計算する
+    ///
+    /// 未払いの残高は現金で受け取る予定として含めるため、回収漏れは過不足に現れる。
+    pub fn expected_from(reservations: &[Reservation]) -> Result<Money, MoneyError> {
+        let currency = reservations
+            .iter()
+            .find_map(|r| r.currency())
+            .unwrap_or_default();
+        reservations
+            .iter()
+            .try_fold(Money::zero(currency), |sum, r| {
+                let cash = r
+                    .payments()
+                    .iter()
+                    .filter(|p| p.method() != PaymentMethod::Cash)
+                    .try_fold(r.total()?, |cash, p| cash.checked_sub(&p.net()?))?;
+                sum.checked_add(&cash)
+            })
+    }
+
+    /// 予約の変更に合わせて予定額を更新する
+    pub fn update_expected(&mut self, expected: Money) -> Result<(), CashClosingError> {
+        self.validate_expected_updated(&expected)?;
+        self.expected = expected.clone();
+        self.events
+            .push(CashClosingEvent::CashClosingExpectedUpdated {
+                id: self.id,
+                expected,
+            });
+        Ok(())
+    }
+
+    /// 数えた現金を記録する（数え直した場合は上書きする）
+    pub fn count(&mut self, counted: Money) -> Result<(), CashClosingError> {
+        self.validate_cash_counted(&counted)?;
+        self.counted = Some(counted.clone());
+        self.events.push(CashClosingEvent::CashClosingCashCounted {
+            id: self.id,
+            counted,
+        });
+        Ok(())
+    }
+
+    /// 過不足を記録して営業日を締める
+    pub fn lock(&mut self) -> Result<(), CashClosingError> {
+        self.validate_locked()?;
+        let difference = self.difference()?.ok_or(CashClosingError::NotCounted)?;
+        self.locked = true;
+        self.events.push(CashClosingEvent::CashClosingLocked {
+            id: self.id,
+            difference,
+        });
+        Ok(())
+    }
+
+    pub fn business_day(&self) -> NaiveDate {
+        self.business_day
+    }
+
+    pub fn expected(&self) -> &Money {
+        &self.expected
+    }
+
+    pub fn counted(&self) -> Option<&Money> {
+        self.counted.as_ref()
+    }
+
+    /// 過不足（数えた現金 - 予定額、正数は過剰、負数は不足）
+    pub fn difference(&self) -> Result<Option<Money>, MoneyError> {
+        self.counted
+            .as_ref()
+            .map(|counted| counted.checked_sub(&self.expected))
+            .transpose()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    fn validate_id(&self, id: &CashClosingId) -> Result<(), CashClosingError> {
+        match self.id == *id {
+            true => Ok(()),
+            false => Err(CashClosingError::MismatchedId),
+        }
+    }
+
+    fn validate_unlocked(&self) -> Result<(), CashClosingError> {
+        match self.locked {
+            true => Err(CashClosingError::AlreadyLocked),
+            false => Ok(()),
+        }
+    }
+
+    fn validate_expected_updated(&self, expected: &Money) -> Result<(), CashClosingError> {
+        self.validate_unlocked()?;
+        self.validate_currency(expected)
+    }
+
+    fn validate_cash_counted(&self, counted: &Money) -> Result<(), CashClosingError> {
+        self.validate_unlocked()?;
+        if counted.is_negative() {
+            return Err(CashClosingError::NegativeAmount);
+        }
+        self.validate_currency(counted)
+    }
+
+    fn validate_locked(&self) -> Result<(), CashClosingError> {
+        self.validate_unlocked()?;
+        match self.counted {
+            Some(_) => Ok(()),
+            None => Err(CashClosingError::NotCounted),
+        }
+    }
+
+    fn validate_currency(&self, money: &Money) -> Result<(), CashClosingError> {
+        match money.currency() == self.expected.currency() {
+            true => Ok(()),
+            false => Err(CashClosingError::CurrencyMismatch),
+        }
+    }
+}
+
+impl Entity for CashClosing {
+    type Id = CashClosingId;
+
+    const ENTITY_NAME: &'static str = "cash_closing";
+
+    fn id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+impl Aggregation for CashClosing {
+    type Event = CashClosingEvent;
+    type Error = CashClosingError;
+
+    fn validate(&self, event: &Self::Event) -> Result<(), Self::Error> {
+        match event {
+            CashClosingEvent::CashClosingOpened { .. } => Ok(()),
+            CashClosingEvent::CashClosingExpectedUpdated { id, expected } => {
+                self.validate_id(id)?;
+                self.validate_expected_updated(expected)
+            }
+            CashClosingEvent::CashClosingCashCounted { id, counted } => {
+                self.validate_id(id)?;
+                self.validate_cash_counted(counted)
+            }
+            CashClosingEvent::CashClosingLocked { id, .. } => {
+                self.validate_id(id)?;
+                self.validate_locked()
+            }
+        }
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        match event {
+            CashClosingEvent::CashClosingOpened {
+                id,
+                business_day,
+                expected,
+            } => {
+                if self.id != id {
+                    *self = Self::open(business_day, expected);
+                }
+            }
+            CashClosingEvent::CashClosingExpectedUpdated { id, expected } => {
+                if self.id == id {
+                    if let Err(_e) = self.update_expected(expected) {}
+                }
+            }
+            CashClosingEvent::CashClosingCashCounted { id, counted } => {
+                if self.id == id {
+                    if let Err(_e) = self.count(counted) {}
+                }
+            }
+            CashClosingEvent::CashClosingLocked { id, .. } => {
+                if self.id == id {
+                    if let Err(_e) = self.lock() {}
+                }
+            }
+        }
+    }
+
+    fn events(&self) -> &EventQueue<Self::Event> {
+        &self.events
+    }
+
+    fn events_mut(&mut self) -> &mut EventQueue<Self::Event> {
+        &mut self.events
+    }
+}
+
+impl PartialEq for CashClosing {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.business_day == other.business_day
+            && self.expected == other.expected
+            && self.counted == other.counted
+            && self.locked == other.locked
+    }
+}
+
+impl Eq for CashClosing {}
+
+/// レジ締めエラー
+#[derive(Error, Display, Debug, PartialEq, Eq)]
+pub enum CashClosingError {
+    /// IDが一致しません
+    #[display(fmt = "ID does not match")]
+    MismatchedId,
+    /// 既に締められています
+    #[display(fmt = "Business day is already locked")]
+    AlreadyLocked,
+    /// 現金が数えられていません
+    #[display(fmt = "Cash has not been counted")]
+    NotCounted,
+    /// 金額が負数です
+    #[display(fmt = "Amount cannot be negative")]
+    NegativeAmount,
+    /// 通貨が一致しません
+    #[display(fmt = "Currency does not match")]
+    CurrencyMismatch,
+    /// 金額の計算エラー
+    #[display(fmt = "Money error: {}", _0)]
+    MoneyError(#[error(source)] MoneyError),
+}
+
+impl From<MoneyError> for CashClosingError {
+    fn from(value: MoneyError) -> Self {
+        CashClosingError::MoneyError(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::domain::core::{
+        Currency, Payment, ReservationCustomer, ReservationDetail, TaxTable,
+    };
+
+    fn yen(amount: i64) -> Money {
+        Money::new(amount, Currency::JPY)
+    }
+
+    #[test]
+    fn test_cash_closing() {
+        let day = NaiveDate::from_ymd_opt(2023, 4, 1).unwrap();
+        let mut closing = CashClosing::open(day, yen(50000));
+        assert_eq!(closing.id(), CashClosingId(20230401));
+        assert_eq!(closing.lock(), Err(CashClosingError::NotCounted));
+        assert_eq!(
+            closing.count(yen(-1)),
+            Err(CashClosingError::NegativeAmount)
+        );
+
+        closing.count(yen(49000)).unwrap();
+        closing.update_expected(yen(48500)).unwrap();
+        assert_eq!(closing.difference(), Ok(Some(yen(500))));
+        closing.lock().unwrap();
+        assert!(closing.is_locked());
+        assert_eq!(
+            closing.count(yen(50000)),
+            Err(CashClosingError::AlreadyLocked)
+        );
+        assert_eq!(
+            closing.update_expected(yen(50000)),
+            Err(CashClosingError::AlreadyLocked)
+        );
+
+        let events = closing.pop_all();
+        assert_eq!(
+            events.last(),
+            Some(&CashClosingEvent::CashClosingLocked {
+                id: closing.id(),
+                difference: yen(500),
+            })
+        );
+        let mut replayed = CashClosing::default();
+        for event in events {
+            replayed.validate(&event).unwrap();
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, closing);
+    }
+
+    #[test]
+    fn test_expected_from() {
+        let start = Utc.with_ymd_and_hms(2023, 4, 1, 10, 0, 0).unwrap();
+        let reservation = |id: u64, payments: Vec<(u64, PaymentMethod, i64)>| {
+            let mut reservation = Reservation::create(
+                id.into(),
+                vec![1.into()],
+                start..start + Duration::hours(1),
+                ReservationCustomer::Registered { id: 5.into() },
+            )
+            .unwrap();
+            reservation
+                .add_detail(
+                    ReservationDetail::create(1.into(), "60分コース".to_owned(), 1, yen(20000))
+                        .unwrap(),
+                    &TaxTable::default(),
+                )
+                .unwrap();
+            for (payment_id, method, amount) in payments {
+                reservation
+                    .receive_payment(
+                        Payment::create(payment_id.into(), method, yen(amount), None).unwrap(),
+                    )
+                    .unwrap();
+            }
+            reservation
+        };
+        let mut refunded = reservation(
+            10,
+            vec![
+                (1, PaymentMethod::Cash, 5000),
+                (2, PaymentMethod::CreditCard, 15000),
+            ],
+        );
+        refunded.refund_payment(1.into(), yen(1000)).unwrap();
+        let reservations = vec![
+            refunded,
+            reservation(11, vec![(1, PaymentMethod::CreditCard, 20000)]),
+            reservation(12, vec![(1, PaymentMethod::Cash, 20000)]),
+            // 未払いの残高は現金で受け取る予定として含める
+            reservation(13, vec![]),
+        ];
+        assert_eq!(CashClosing::expected_from(&reservations), Ok(yen(45000)));
+        assert_eq!(CashClosing::expected_from(&[]), Ok(Money::default()));
+    }
+}
@@ -0,0 +1,771 @@
+use std::{cmp::Ordering, ops::Range};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Utc};
+use derive_more::{Deref, Display, Error, From, IntoIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata};
+
+use super::{
+    CustomerId, DiscountType, Money, MoneyError, Reservation, ReservationCustomer,
+    ReservationDetail, ReservationDetailId, ReservationId, ReservationItem, Rounding,
+};
+
+/// クーポンリポジトリ
+#[async_trait]
+pub trait CouponRepository {
+    /// クーポンをIDで検索する
+    async fn find_by_id(&self, id: CouponId) -> Result<Option<Coupon>, DataAccessError>;
+    /// 指定日時時点のクーポンをIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: CouponId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Coupon>, DataAccessError>;
+    /// 指定リビジョン時点のクーポンをIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: CouponId,
+        revision: u64,
+    ) -> Result<Option<Coupon>, DataAccessError>;
+    /// クーポンを保存する
+    async fn save(
+        &mut self,
+        entity: &mut Coupon,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
+}
+
+/// クーポンID
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display, From, Deref, Default,
+)]
+pub struct CouponId(u64);
+
+impl Id for CouponId {
+    type Inner = u64;
+}
+
+/// クーポンを利用できるお客様
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum CouponEligibility {
+    /// 誰でも利用できる
+    #[default]
+    Anyone,
+    /// 初回利用のお客様のみ
+    FirstVisit,
+    /// 誕生月のお客様のみ
+    BirthdayMonth,
+}
+
+/// クーポンの利用条件
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CouponRules {
+    /// 利用できるお客様
+    pub eligibility: CouponEligibility,
+    /// コード全体の利用回数の上限
+    pub max_redemptions: Option<u32>,
+    /// お客様ごとの利用回数の上限
+    pub max_redemptions_per_customer: Option<u32>,
+    /// 利用できる最低金額（割引前の合計）
+    pub minimum_spend: Option<Money>,
+}
+
+/// クーポンを利用する予約の条件
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CouponApplication {
+    /// お客様（未登録のお客様は`None`）
+    pub customer_id: Option<CustomerId>,
+    /// 初回利用であるか
+    pub first_visit: bool,
+    /// お客様の誕生日
+    pub birthday: Option<NaiveDate>,
+    /// 割引前の合計金額
+    pub subtotal: Money,
+    /// 利用日時（誕生月はこの日時のタイムゾーンで判定する）
+    pub time: DateTime<FixedOffset>,
+}
+
+/// クーポンの利用記録
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CouponRedemption {
+    /// 利用した予約
+    pub reservation_id: ReservationId,
+    /// 利用したお客様
+    pub customer_id: Option<CustomerId>,
+    /// 割引額
+    pub discount: Money,
+    /// 利用日時
+    pub redeemed_at: DateTime<Utc>,
+}
+
+/// クーポンイベント
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CouponEvent {
+    /// クーポンが作成された
+    CouponCreated {
+        id: CouponId,
+        code: String,
+        name: String,
+        discount: DiscountType,
+        period: Range<DateTime<Utc>>,
+        rules: CouponRules,
+    },
+    /// クーポンが無効にされた
+    CouponDisabled { id: CouponId },
+    /// クーポンが利用された
+    CouponRedeemed {
+        id: CouponId,
+        redemption: CouponRedemption,
+    },
+    /// クーポンの利用が取り消された
+    CouponRedemptionCanceled {
+        id: CouponId,
+        reservation_id: ReservationId,
+    },
+}
+
+impl Event for CouponEvent {
+    type Id = CouponId;
+}
+
+/// クーポンエンティティ
+#[derive(Debug, Default, Clone, IntoIterator, Serialize, Deserialize)]
+pub struct Coupon {
+    id: CouponId,
+    code: String,
+    name: String,
+    discount: Option<DiscountType>,
+    period: Range<DateTime<Utc>>,
+    rules: CouponRules,
+    disabled: bool,
+    redemptions: Vec<CouponRedemption>,
+    #[serde(skip)]
+    #[into_iterator]
+    events: EventQueue<CouponEvent>,
+}
+
+impl Coupon {
+    /// クーポンを作成する（コードは大文字に揃える）
+    pub fn create(
+        id: CouponId,
+        code: String,
+        name: String,
+        discount: DiscountType,
+        period: Range<DateTime<Utc>>,
+        rules: CouponRules,
+    ) -> Result<Self, CouponError> {
+        let code = Self::normalize(&code);
+        Self::validate_created(&code, &discount, &period)?;
+        let mut entity = Coupon {
+            id,
+            code: code.clone(),
+            name: name.clone(),
+            discount: Some(discount.clone()),
+            period: period.clone(),
+            rules: rules.clone(),
+            ..Default::default()
+        };
+        entity.events.push(CouponEvent::CouponCreated {
+            id,
+            code,
+            name,
+            discount,
+            period,
+            rules,
+        });
+        Ok(entity)
+    }
+
+    /// クーポンを無効にする（利用記録は残す）
+    pub fn disable(&mut self) -> Result<(), CouponError> {
+        self.validate_enabled()?;
+        self.disabled = true;
+        self.events
+            .push(CouponEvent::CouponDisabled { id: self.id });
+        Ok(())
+    }
+
+    /// 見積もりのためにクーポンを検証し、割引額を計算する
+    ///
+    /// 割引額は割引前の合計を超えない。割合での割引の端数は切り捨てる。
+    pub fn quote(&self, application: &CouponApplication) -> Result<Money, CouponError> {
+        self.validate_enabled()?;
+        let time = application.time.with_timezone(&Utc);
+        if !self.period.contains(&time) {
+            return Err(CouponError::OutsidePeriod);
+        }
+        self.validate_eligibility(application)?;
+        self.validate_limits(application.customer_id)?;
+        let subtotal = &application.subtotal;
+        if let Some(minimum) = &self.rules.minimum_spend {
+            match subtotal.partial_cmp(minimum) {
+                Some(Ordering::Less) => return Err(CouponError::MinimumSpendNotMet),
+                None => return Err(CouponError::MoneyError(MoneyError::CurrencyMismatch)),
+                Some(_) => {}
+            }
+        }
+        let discount = match &self.discount {
+            Some(DiscountType::Amount { amount }) => amount.amount().clone(),
+            Some(DiscountType::Percentage { percentage }) => {
+                subtotal.percentage(*percentage as i64, Rounding::Down)?
+            }
+            None => Money::zero(subtotal.currency()),
+        };
+        match discount.partial_cmp(subtotal) {
+            Some(Ordering::Greater) => Ok(subtotal.clone()),
+            Some(_) => Ok(discount),
+            None => Err(CouponError::MoneyError(MoneyError::CurrencyMismatch)),
+        }
+    }
+
+    /// 予約でクーポンを利用し、予約に追加する負の金額の予約詳細を返す
+    ///
+    /// 割引前の合計とお客様は`application`ではなく予約から取得する。
+    /// 予約詳細は税区分を指定しないため、`Reservation::add_detail`で追加すると標準税率の対象から差し引く。
+    pub fn redeem(
+        &mut self,
+        reservation: &Reservation,
+        detail_id: ReservationDetailId,
+        application: &CouponApplication,
+    ) -> Result<ReservationDetail, CouponError> {
+        if reservation.is_completed() {
+            return Err(CouponError::AlreadyCompleted);
+        }
+        if self.redemption(reservation.id()).is_some() {
+            return Err(CouponError::AlreadyRedeemed);
+        }
+        let application = CouponApplication {
+            customer_id: match reservation.customer() {
+                ReservationCustomer::Registered { id } => Some(*id),
+                _ => None,
+            },
+            subtotal: reservation.total()?,
+            ..application.clone()
+        };
+        let discount = self.quote(&application)?;
+        let detail = ReservationDetail::create(
+            detail_id,
+            format!("クーポン割引 {}", self.name),
+            1,
+            Money::new(-discount.amount(), discount.currency()),
+        )
+        .map_err(|_| CouponError::InvalidDiscount)?
+        .with_item(ReservationItem::Coupon(self.id));
+        let redemption = CouponRedemption {
+            reservation_id: reservation.id(),
+            customer_id: application.customer_id,
+            discount,
+            redeemed_at: application.time.with_timezone(&Utc),
+        };
+        self.redemptions.push(redemption.clone());
+        self.events.push(CouponEvent::CouponRedeemed {
+            id: self.id,
+            redemption,
+        });
+        Ok(detail)
+    }
+
+    /// 予約のキャンセルなどでクーポンの利用を取り消す
+    pub fn cancel_redemption(&mut self, reservation_id: ReservationId) -> Result<(), CouponError> {
+        self.validate_redemption_canceled(reservation_id)?;
+        self.redemptions
+            .retain(|r| r.reservation_id != reservation_id);
+        self.events.push(CouponEvent::CouponRedemptionCanceled {
+            id: self.id,
+            reservation_id,
+        });
+        Ok(())
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn discount(&self) -> Option<&DiscountType> {
+        self.discount.as_ref()
+    }
+
+    pub fn period(&self) -> &Range<DateTime<Utc>> {
+        &self.period
+    }
+
+    pub fn rules(&self) -> &CouponRules {
+        &self.rules
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    pub fn redemptions(&self) -> &[CouponRedemption] {
+        &self.redemptions
+    }
+
+    /// 予約での利用記録を取得する
+    pub fn redemption(&self, reservation_id: ReservationId) -> Option<&CouponRedemption> {
+        self.redemptions
+            .iter()
+            .find(|r| r.reservation_id == reservation_id)
+    }
+
+    /// 入力されたコードを比較用に正規化する
+    pub fn normalize(code: &str) -> String {
+        code.trim().to_uppercase()
+    }
+
+    fn validate_id(&self, id: &CouponId) -> Result<(), CouponError> {
+        match self.id == *id {
+            true => Ok(()),
+            false => Err(CouponError::MismatchedId),
+        }
+    }
+
+    fn validate_created(
+        code: &str,
+        discount: &DiscountType,
+        period: &Range<DateTime<Utc>>,
+    ) -> Result<(), CouponError> {
+        if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(CouponError::InvalidCode);
+        }
+        match discount {
+            DiscountType::Amount { amount } if !amount.is_positive() => {
+                return Err(CouponError::InvalidDiscount)
+            }
+            DiscountType::Percentage { percentage } if !(1..=100).contains(percentage) => {
+                return Err(CouponError::InvalidDiscount)
+            }
+            _ => {}
+        }
+        if period.start >= period.end {
+            return Err(CouponError::InvalidPeriod);
+        }
+        Ok(())
+    }
+
+    fn validate_enabled(&self) -> Result<(), CouponError> {
+        match self.disabled {
+            true => Err(CouponError::Disabled),
+            false => Ok(()),
+        }
+    }
+
+    fn validate_eligibility(&self, application: &CouponApplication) -> Result<(), CouponError> {
+        match self.rules.eligibility {
+            CouponEligibility::Anyone => Ok(()),
+            CouponEligibility::FirstVisit => match application.first_visit {
+                true => Ok(()),
+                false => Err(CouponError::NotFirstVisit),
+            },
+            CouponEligibility::BirthdayMonth => match application.birthday {
+                Some(birthday) if birthday.month() == application.time.month() => Ok(()),
+                _ => Err(CouponError::NotBirthdayMonth),
+            },
+        }
+    }
+
+    fn validate_limits(&self, customer_id: Option<CustomerId>) -> Result<(), CouponError> {
+        if let Some(max) = self.rules.max_redemptions {
+            if self.redemptions.len() >= max as usize {
+                return Err(CouponError::RedemptionLimitReached);
+            }
+        }
+        if let Some(max) = self.rules.max_redemptions_per_customer {
+            let customer_id = customer_id.ok_or(CouponError::CustomerRequired)?;
+            let count = self
+                .redemptions
+                .iter()
+                .filter(|r| r.customer_id == Some(customer_id))
+                .count();
+            if count >= max as usize {
+                return Err(CouponError::CustomerLimitReached);
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_redeemed(&self, redemption: &CouponRedemption) -> Result<(), CouponError> {
+        self.validate_enabled()?;
+        if self.redemption(redemption.reservation_id).is_some() {
+            return Err(CouponError::AlreadyRedeemed);
+        }
+        self.validate_limits(redemption.customer_id)
+    }
+
+    fn validate_redemption_canceled(
+        &self,
+        reservation_id: ReservationId,
+    ) -> Result<(), CouponError> {
+        match self.redemption(reservation_id) {
+            Some(_) => Ok(()),
+            None => Err(CouponError::RedemptionNotFound),
+        }
+    }
+}
+
+impl Entity for Coupon {
+    type Id = CouponId;
+
+    const ENTITY_NAME: &'static str = "coupon";
+
+    fn id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+impl Aggregation for Coupon {
+    type Event = CouponEvent;
+    type Error = CouponError;
+
+    fn validate(&self, event: &Self::Event) -> Result<(), Self::Error> {
+        match event {
+            CouponEvent::CouponCreated {
+                code,
+                discount,
+                period,
+                ..
+            } => Self::validate_created(code, discount, period),
+            CouponEvent::CouponDisabled { id } => {
+                self.validate_id(id)?;
+                self.validate_enabled()
+            }
+            CouponEvent::CouponRedeemed { id, redemption } => {
+                self.validate_id(id)?;
+                self.validate_redeemed(redemption)
+            }
+            CouponEvent::CouponRedemptionCanceled { id, reservation_id } => {
+                self.validate_id(id)?;
+                self.validate_redemption_canceled(*reservation_id)
+            }
+        }
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        match event {
+            CouponEvent::CouponCreated {
+                id,
+                code,
+                name,
+                discount,
+                period,
+                rules,
+            } => {
+                if self.id != id {
+                    if let Ok(entity) = Self::create(id, code, name, discount, period, rules) {
+                        *self = entity;
+                    }
+                }
+            }
+            CouponEvent::CouponDisabled { id } => {
+                if self.id == id {
+                    if let Err(_e) = self.disable() {}
+                }
+            }
+            CouponEvent::CouponRedeemed { id, redemption } => {
+                // 利用時の条件（期間や誕生月など）はイベントに残らないため、記録をそのまま復元する
+                if self.id == id && self.validate_redeemed(&redemption).is_ok() {
+                    self.redemptions.push(redemption.clone());
+                    self.events
+                        .push(CouponEvent::CouponRedeemed { id, redemption });
+                }
+            }
+            CouponEvent::CouponRedemptionCanceled { id, reservation_id } => {
+                if self.id == id {
+                    if let Err(_e) = self.cancel_redemption(reservation_id) {}
+                }
+            }
+        }
+    }
+
+    fn events(&self) -> &EventQueue<Self::Event> {
+        &self.events
+    }
+
+    fn events_mut(&mut self) -> &mut EventQueue<Self::Event> {
+        &mut self.events
+    }
+}
+
+impl PartialEq for Coupon {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.code == other.code
+            && self.name == other.name
+            && self.discount == other.discount
+            && self.period == other.period
+            && self.rules == other.rules
+            && self.disabled == other.disabled
+            && self.redemptions == other.redemptions
+    }
+}
+
+impl Eq for Coupon {}
+
+/// クーポンエラー
+#[derive(Error, Display, Debug, PartialEq, Eq)]
+pub enum CouponError {
+    /// IDが一致しません
+    #[display(fmt = "ID does not match")]
+    MismatchedId,
+    /// コードが不正です
+    #[display(fmt = "Code must consist of alphanumerics and hyphens")]
+    InvalidCode,
+    /// 割引が不正です
+    #[display(fmt = "Discount must be a positive amount or 1 to 100 percent")]
+    InvalidDiscount,
+    /// 有効期間が不正です
+    #[display(fmt = "Invalid period")]
+    InvalidPeriod,
+    /// 無効にされています
+    #[display(fmt = "Coupon is disabled")]
+    Disabled,
+    /// 有効期間外です
+    #[display(fmt = "Coupon is not valid at this time")]
+    OutsidePeriod,
+    /// 初回利用ではありません
+    #[display(fmt = "Coupon is only for first visits")]
+    NotFirstVisit,
+    /// 誕生月ではありません
+    #[display(fmt = "Coupon is only for birthday months")]
+    NotBirthdayMonth,
+    /// 利用回数の上限に達しました
+    #[display(fmt = "Coupon has reached its redemption limit")]
+    RedemptionLimitReached,
+    /// お客様ごとの利用回数の上限に達しました
+    #[display(fmt = "Customer has reached the redemption limit")]
+    CustomerLimitReached,
+    /// お客様ごとの上限があるため登録済みのお客様が必要です
+    #[display(fmt = "Registered customer is required")]
+    CustomerRequired,
+    /// 最低金額に達していません
+    #[display(fmt = "Minimum spend is not met")]
+    MinimumSpendNotMet,
+    /// 既に予約で利用されています
+    #[display(fmt = "Coupon is already redeemed for the reservation")]
+    AlreadyRedeemed,
+    /// 予約が完了しています
+    #[display(fmt = "Reservation is already completed")]
+    AlreadyCompleted,
+    /// 利用記録が見つかりません
+    #[display(fmt = "Redemption not found")]
+    RedemptionNotFound,
+    /// 金額の計算エラー
+    #[display(fmt = "Money error: {}", _0)]
+    MoneyError(#[error(source)] MoneyError),
+}
+
+impl From<MoneyError> for CouponError {
+    fn from(value: MoneyError) -> Self {
+        CouponError::MoneyError(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::domain::core::{Currency, Price, PriceUnit, TaxTable};
+    use crate::domain::{ActorId, Role};
+
+    fn yen(amount: i64) -> Money {
+        Money::new(amount, Currency::JPY)
+    }
+
+    fn reservation(id: u64, customer_id: Option<u64>, amount: i64) -> Reservation {
+        let start = Utc.with_ymd_and_hms(2023, 4, 1, 1, 0, 0).unwrap();
+        let customer = match customer_id {
+            Some(id) => ReservationCustomer::Registered { id: id.into() },
+            None => ReservationCustomer::Unregistered {
+                name: "山田".to_owned(),
+                phone: "09000000000".to_owned(),
+            },
+        };
+        let mut reservation = Reservation::create(
+            id.into(),
+            vec![1.into()],
+            start..start + Duration::hours(1),
+            customer,
+        )
+        .unwrap();
+        reservation
+            .add_detail(
+                ReservationDetail::create(1.into(), "60分コース".to_owned(), 1, yen(amount))
+                    .unwrap(),
+                &TaxTable::default(),
+            )
+            .unwrap();
+        reservation
+    }
+
+    fn application(customer_id: u64, subtotal: i64) -> CouponApplication {
+        CouponApplication {
+            customer_id: Some(customer_id.into()),
+            first_visit: false,
+            birthday: NaiveDate::from_ymd_opt(1995, 4, 20),
+            subtotal: yen(subtotal),
+            time: FixedOffset::east_opt(9 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2023, 4, 1, 10, 0, 0)
+                .unwrap(),
+        }
+    }
+
+    fn coupon(discount: DiscountType, rules: CouponRules) -> Coupon {
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        Coupon::create(
+            1.into(),
+            " spring-2023 ".to_owned(),
+            "春の割引".to_owned(),
+            discount,
+            start..start + Duration::days(365),
+            rules,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_quote() {
+        let percent = coupon(
+            DiscountType::Percentage { percentage: 15 },
+            CouponRules {
+                minimum_spend: Some(yen(10000)),
+                ..Default::default()
+            },
+        );
+        assert_eq!(percent.code(), "SPRING-2023");
+        assert_eq!(percent.quote(&application(1, 15999)), Ok(yen(2399)));
+        assert_eq!(
+            percent.quote(&application(1, 9999)),
+            Err(CouponError::MinimumSpendNotMet)
+        );
+
+        let amount = coupon(
+            DiscountType::Amount {
+                amount: Price::new(yen(3000), PriceUnit::OneTime),
+            },
+            CouponRules {
+                eligibility: CouponEligibility::BirthdayMonth,
+                ..Default::default()
+            },
+        );
+        assert_eq!(amount.quote(&application(1, 2000)), Ok(yen(2000)));
+        let mut other_month = application(1, 20000);
+        other_month.birthday = NaiveDate::from_ymd_opt(1995, 5, 20);
+        assert_eq!(
+            amount.quote(&other_month),
+            Err(CouponError::NotBirthdayMonth)
+        );
+
+        let first_visit = coupon(
+            DiscountType::Percentage { percentage: 10 },
+            CouponRules {
+                eligibility: CouponEligibility::FirstVisit,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            first_visit.quote(&application(1, 20000)),
+            Err(CouponError::NotFirstVisit)
+        );
+        let mut expired = application(1, 20000);
+        expired.time += Duration::days(365);
+        assert_eq!(percent.quote(&expired), Err(CouponError::OutsidePeriod));
+    }
+
+    #[test]
+    fn test_redeem() {
+        let mut coupon = coupon(
+            DiscountType::Percentage { percentage: 10 },
+            CouponRules {
+                max_redemptions: Some(3),
+                max_redemptions_per_customer: Some(1),
+                ..Default::default()
+            },
+        );
+        // 割引前の合計とお客様は予約から取得する
+        let mut first = reservation(1, Some(1), 20000);
+        let detail = coupon.redeem(&first, 2.into(), &application(9, 0)).unwrap();
+        assert_eq!(detail.price(), &yen(-2000));
+        assert_eq!(detail.item(), Some(ReservationItem::Coupon(1.into())));
+        first.add_detail(detail, &TaxTable::default()).unwrap();
+        assert_eq!(first.total(), Ok(yen(18000)));
+        assert_eq!(
+            coupon.redemption(1.into()).map(|r| r.customer_id),
+            Some(Some(1.into()))
+        );
+        assert_eq!(
+            coupon.redeem(&first, 3.into(), &application(1, 20000)),
+            Err(CouponError::AlreadyRedeemed)
+        );
+        assert_eq!(
+            coupon.redeem(
+                &reservation(2, Some(1), 20000),
+                2.into(),
+                &application(1, 20000)
+            ),
+            Err(CouponError::CustomerLimitReached)
+        );
+        assert_eq!(
+            coupon.redeem(
+                &reservation(2, None, 20000),
+                2.into(),
+                &application(1, 20000)
+            ),
+            Err(CouponError::CustomerRequired)
+        );
+        for id in [2, 3] {
+            coupon
+                .redeem(
+                    &reservation(id, Some(id), 20000),
+                    2.into(),
+                    &application(id, 20000),
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            coupon.redeem(
+                &reservation(4, Some(4), 20000),
+                2.into(),
+                &application(4, 20000)
+            ),
+            Err(CouponError::RedemptionLimitReached)
+        );
+        coupon.cancel_redemption(3.into()).unwrap();
+        let mut completed = reservation(4, Some(4), 20000);
+        completed
+            .complete_with_override(ActorId::default(), Role::Manager)
+            .unwrap();
+        assert_eq!(
+            coupon.redeem(&completed, 2.into(), &application(4, 20000)),
+            Err(CouponError::AlreadyCompleted)
+        );
+        coupon
+            .redeem(
+                &reservation(4, Some(4), 20000),
+                2.into(),
+                &application(4, 20000),
+            )
+            .unwrap();
+        coupon.disable().unwrap();
+        assert_eq!(
+            coupon.quote(&application(5, 20000)),
+            Err(CouponError::Disabled)
+        );
+
+        let mut replayed = Coupon::default();
+        for event in coupon.pop_all() {
+            replayed.validate(&event).unwrap();
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, coupon);
+        assert_eq!(replayed.redemptions().len(), 3);
+    }
+}
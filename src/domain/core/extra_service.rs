@@ -1,10 +1,11 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use derive_more::{Deref, Display, Error, From, IntoIterator};
 use serde::{Deserialize, Serialize};
 
-use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id};
+use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata};
 
-use super::Money;
+use super::{Money, TaxCategory, TaxInclusion};
 
 /// オプションサービスリポジトリ
 #[async_trait]
@@ -12,10 +13,30 @@ pub trait ExtraServiceRepository {
     /// オプションサービスをIDで検索する
     async fn find_by_id(&self, id: ExtraServiceId)
         -> Result<Option<ExtraService>, DataAccessError>;
+    /// 指定日時時点のオプションサービスをIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: ExtraServiceId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<ExtraService>, DataAccessError>;
+    /// 指定リビジョン時点のオプションサービスをIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: ExtraServiceId,
+        revision: u64,
+    ) -> Result<Option<ExtraService>, DataAccessError>;
     /// オプションサービスを保存する
-    async fn save(&mut self, entity: &mut ExtraService) -> Result<bool, DataAccessError>;
+    async fn save(
+        &mut self,
+        entity: &mut ExtraService,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
     /// オプションサービスを削除する
-    async fn delete(&mut self, entity: &mut ExtraService) -> Result<bool, DataAccessError>;
+    async fn delete(
+        &mut self,
+        entity: &mut ExtraService,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
 }
 
 /// オプションサービスID
@@ -53,6 +74,12 @@ pub enum ExtraServiceEvent {
         id: ExtraServiceId,
         price: Money,
     },
+    /// オプションサービスの税区分が変更された
+    ExtraServiceTaxChanged {
+        id: ExtraServiceId,
+        tax_category: TaxCategory,
+        tax_inclusion: TaxInclusion,
+    },
     /// オプションサービスが削除された
     ExtraServiceDeleted {
         id: ExtraServiceId,
@@ -70,6 +97,10 @@ pub struct ExtraService {
     name: String,
     description: String,
     price: Money,
+    #[serde(default)]
+    tax_category: TaxCategory,
+    #[serde(default)]
+    tax_inclusion: TaxInclusion,
     #[serde(skip)]
     #[into_iterator]
     events: EventQueue<ExtraServiceEvent>,
@@ -121,6 +152,17 @@ impl ExtraService {
             .push(ExtraServiceEvent::ExtraServicePriceChanged { id: self.id, price });
     }
 
+    /// 料金の税区分と税込・税抜を変更する
+    pub fn change_tax(&mut self, tax_category: TaxCategory, tax_inclusion: TaxInclusion) {
+        self.tax_category = tax_category;
+        self.tax_inclusion = tax_inclusion;
+        self.events.push(ExtraServiceEvent::ExtraServiceTaxChanged {
+            id: self.id,
+            tax_category,
+            tax_inclusion,
+        });
+    }
+
     pub fn name(&self) -> &String {
         &self.name
     }
@@ -133,6 +175,14 @@ impl ExtraService {
         &self.price
     }
 
+    pub fn tax_category(&self) -> TaxCategory {
+        self.tax_category
+    }
+
+    pub fn tax_inclusion(&self) -> TaxInclusion {
+        self.tax_inclusion
+    }
+
     fn validate_id(&self, id: &ExtraServiceId) -> Result<(), ExtraServiceError> {
         match self.id == *id {
             true => Ok(()),
@@ -175,6 +225,7 @@ impl Aggregation for ExtraService {
             }
             ExtraServiceEvent::ExtraServiceDescriptionChanged { id, .. }
             | ExtraServiceEvent::ExtraServicePriceChanged { id, .. }
+            | ExtraServiceEvent::ExtraServiceTaxChanged { id, .. }
             | ExtraServiceEvent::ExtraServiceDeleted { id, .. } => self.validate_id(id),
         }
     }
@@ -210,6 +261,15 @@ impl Aggregation for ExtraService {
                     self.change_price(price);
                 }
             }
+            ExtraServiceEvent::ExtraServiceTaxChanged {
+                id,
+                tax_category,
+                tax_inclusion,
+            } => {
+                if self.id == id {
+                    self.change_tax(tax_category, tax_inclusion);
+                }
+            }
             ExtraServiceEvent::ExtraServiceDeleted { .. } => {}
         }
     }
@@ -229,6 +289,8 @@ impl PartialEq for ExtraService {
             && self.name == other.name
             && self.description == other.description
             && self.price == other.price
+            && self.tax_category == other.tax_category
+            && self.tax_inclusion == other.tax_inclusion
     }
 }
 
@@ -264,6 +326,8 @@ mod tests {
         assert_eq!(service.name(), "AF");
         assert_eq!(service.description(), "アナルセックスを指します。");
         assert_eq!(service.price(), &Money::new(10000, Currency::JPY));
+        assert_eq!(service.tax_category(), TaxCategory::Standard);
+        assert_eq!(service.tax_inclusion(), TaxInclusion::Inclusive);
     }
 
     #[test]
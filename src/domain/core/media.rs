@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 use bytes::{Buf, Bytes};
+use chrono::{DateTime, Utc};
 use derive_more::{Deref, Display, Error, From, IntoIterator};
 use image::{
     codecs::{gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder, webp::WebPDecoder},
@@ -7,7 +8,7 @@ use image::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id};
+use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata};
 
 use super::Mime;
 
@@ -16,10 +17,30 @@ use super::Mime;
 pub trait MediaRepository {
     /// メディアをIDで検索する
     async fn find_by_id(&self, id: MediaId) -> Result<Option<Media>, DataAccessError>;
+    /// 指定日時時点のメディアをIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: MediaId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Media>, DataAccessError>;
+    /// 指定リビジョン時点のメディアをIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: MediaId,
+        revision: u64,
+    ) -> Result<Option<Media>, DataAccessError>;
     /// メディアを保存する
-    async fn save(&mut self, entity: &mut Media) -> Result<bool, DataAccessError>;
+    async fn save(
+        &mut self,
+        entity: &mut Media,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
     /// メディアを削除する
-    async fn delete(&mut self, entity: &mut Media) -> Result<bool, DataAccessError>;
+    async fn delete(
+        &mut self,
+        entity: &mut Media,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
 }
 
 /// メディアID
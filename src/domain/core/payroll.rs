@@ -0,0 +1,441 @@
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+use derive_more::{Display, Error};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Currency, Money, MoneyError, ProstituteId, Reservation, ReservationId, ReservationItem,
+    Rounding, Shift, ShiftStatus,
+};
+use crate::domain::Entity;
+
+/// 歩合
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Commission {
+    /// 売上に対する割合（百分率）
+    Rate(u8),
+    /// 数量あたりの固定額（指名料など）
+    Fixed(Money),
+}
+
+impl Default for Commission {
+    fn default() -> Self {
+        Commission::Rate(0)
+    }
+}
+
+/// 歩合のルール
+///
+/// 女の子と対象を省略した場合は、すべての女の子・対象に適用する。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommissionRule {
+    /// 対象の女の子
+    #[serde(default)]
+    pub prostitute_id: Option<ProstituteId>,
+    /// 対象のサービスコースまたはオプションサービス
+    #[serde(default)]
+    pub item: Option<ReservationItem>,
+    /// 歩合
+    pub commission: Commission,
+}
+
+impl CommissionRule {
+    fn matches(&self, prostitute_id: ProstituteId, item: Option<ReservationItem>) -> bool {
+        self.prostitute_id.is_none_or(|id| id == prostitute_id)
+            && self.item.is_none_or(|i| Some(i) == item)
+    }
+
+    /// 女の子と対象の両方を指定したルールを最優先とし、次に対象、女の子の順に優先する
+    fn specificity(&self) -> u8 {
+        (self.item.is_some() as u8) * 2 + self.prostitute_id.is_some() as u8
+    }
+}
+
+/// 給与計算のルール
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PayrollRules {
+    /// どのルールにも該当しない予約詳細の歩合
+    pub default_commission: Commission,
+    /// 確定したシフト1回あたりの出勤手当
+    pub attendance_bonus: Option<Money>,
+    /// 歩合のルール
+    pub rules: Vec<CommissionRule>,
+    /// 支払額の通貨
+    pub currency: Currency,
+}
+
+impl PayrollRules {
+    /// 女の子と対象に適用する歩合を取得する
+    pub fn commission(
+        &self,
+        prostitute_id: ProstituteId,
+        item: Option<ReservationItem>,
+    ) -> &Commission {
+        self.rules
+            .iter()
+            .filter(|r| r.matches(prostitute_id, item))
+            .max_by_key(|r| r.specificity())
+            .map_or(&self.default_commission, |r| &r.commission)
+    }
+
+    /// 期間内の支払明細を作成する
+    ///
+    /// 完了した予約と、`now`までに終了した確定シフトを対象にする。複数の女の子が担当した予約は、売上を人数で等分してから歩合を計算する。
+    /// 指名料は指名された女の子だけに全額計上する。
+    /// 端数は切り捨てる。
+    pub fn statement(
+        &self,
+        prostitute_id: ProstituteId,
+        period: Range<DateTime<Utc>>,
+        reservations: &[Reservation],
+        shifts: &[Shift],
+        now: DateTime<Utc>,
+    ) -> Result<PayoutStatement, PayrollError> {
+        self.validate()?;
+        let mut lines = Vec::new();
+        let mut reservations = reservations
+            .iter()
+            .filter(|r| r.prostitute_ids().contains(&prostitute_id))
+            .filter(|r| period.contains(&r.time().start) && r.is_completed())
+            .collect::<Vec<_>>();
+        reservations.sort_by_key(|r| (r.time().start, *r.id()));
+        for reservation in reservations {
+            for detail in reservation.details() {
+                let casts = match reservation.fee_owner(detail.id()) {
+                    Some(owner) if owner != prostitute_id => continue,
+                    Some(_) => 1,
+                    None => reservation.prostitute_ids().len() as i64,
+                };
+                let sales = detail.subtotal()?.checked_ratio(1, casts, Rounding::Down)?;
+                let commission = self.commission(prostitute_id, detail.item()).clone();
+                let amount = match &commission {
+                    Commission::Rate(rate) => sales.percentage(*rate as i64, Rounding::Down)?,
+                    Commission::Fixed(fixed) => fixed.checked_mul(detail.quantity() as i64)?,
+                };
+                lines.push(PayoutLine {
+                    date: reservation.time().start,
+                    reservation_id: Some(reservation.id()),
+                    description: detail.name().to_owned(),
+                    quantity: detail.quantity(),
+                    sales,
+                    commission,
+                    amount,
+                });
+            }
+        }
+        if let Some(bonus) = &self.attendance_bonus {
+            let mut shifts = shifts
+                .iter()
+                .filter(|s| s.status() == ShiftStatus::Confirmed)
+                .filter(|s| period.contains(&s.time().start) && s.time().end <= now)
+                .collect::<Vec<_>>();
+            shifts.sort_by_key(|s| s.time().start);
+            for shift in shifts {
+                lines.push(PayoutLine {
+                    date: shift.time().start,
+                    reservation_id: None,
+                    description: "出勤手当".to_owned(),
+                    quantity: 1,
+                    sales: Money::zero(bonus.currency()),
+                    commission: Commission::Fixed(bonus.clone()),
+                    amount: bonus.clone(),
+                });
+            }
+        }
+        let statement = PayoutStatement {
+            prostitute_id,
+            period,
+            lines,
+            currency: self.currency,
+        };
+        statement.total()?;
+        Ok(statement)
+    }
+
+    fn validate(&self) -> Result<(), PayrollError> {
+        let rates = std::iter::once(&self.default_commission)
+            .chain(self.rules.iter().map(|r| &r.commission));
+        for commission in rates {
+            if let Commission::Rate(rate) = commission {
+                if *rate > 100 {
+                    return Err(PayrollError::InvalidRate);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 支払明細
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayoutStatement {
+    /// 女の子
+    pub prostitute_id: ProstituteId,
+    /// 対象期間
+    pub period: Range<DateTime<Utc>>,
+    /// 明細行
+    pub lines: Vec<PayoutLine>,
+    /// 支払額の通貨
+    pub currency: Currency,
+}
+
+impl PayoutStatement {
+    /// 支払額の合計（明細行がない場合は支払額の通貨の0）
+    pub fn total(&self) -> Result<Money, MoneyError> {
+        Money::sum(self.lines.iter().map(|l| &l.amount), self.currency)
+    }
+}
+
+/// 支払明細の行
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayoutLine {
+    /// 予約またはシフトの開始日時
+    pub date: DateTime<Utc>,
+    /// 予約（出勤手当の場合は`None`）
+    pub reservation_id: Option<ReservationId>,
+    /// 内容
+    pub description: String,
+    /// 数量
+    pub quantity: u32,
+    /// 女の子の売上（複数人で担当した場合は按分後）
+    pub sales: Money,
+    /// 適用した歩合
+    pub commission: Commission,
+    /// 支払額
+    pub amount: Money,
+}
+
+/// 給与計算エラー
+#[derive(Error, Display, Debug, PartialEq, Eq)]
+pub enum PayrollError {
+    /// 歩合率が不正です
+    #[display(fmt = "Commission rate must be between 0 and 100")]
+    InvalidRate,
+    /// 金額の計算エラー（通貨の混在など）
+    #[display(fmt = "Money error: {}", _0)]
+    MoneyError(#[error(source)] MoneyError),
+}
+
+impl From<MoneyError> for PayrollError {
+    fn from(value: MoneyError) -> Self {
+        PayrollError::MoneyError(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::*;
+    use crate::domain::core::{
+        Currency, Designation, DesignationFees, Price, PriceUnit, ReservationCustomer,
+        ReservationDetail, ShiftId, TaxTable,
+    };
+    use crate::domain::Role;
+
+    fn yen(amount: i64) -> Money {
+        Money::new(amount, Currency::JPY)
+    }
+
+    fn rules() -> PayrollRules {
+        PayrollRules {
+            default_commission: Commission::Rate(50),
+            attendance_bonus: Some(yen(2000)),
+            rules: vec![
+                CommissionRule {
+                    prostitute_id: None,
+                    item: Some(ReservationItem::ExtraService(9.into())),
+                    commission: Commission::Fixed(yen(1000)),
+                },
+                CommissionRule {
+                    prostitute_id: Some(1.into()),
+                    item: None,
+                    commission: Commission::Rate(60),
+                },
+                CommissionRule {
+                    prostitute_id: Some(1.into()),
+                    item: Some(ReservationItem::Service(1.into())),
+                    commission: Commission::Rate(70),
+                },
+            ],
+            currency: Currency::JPY,
+        }
+    }
+
+    #[test]
+    fn test_commission() {
+        let rules = rules();
+        let course = Some(ReservationItem::Service(1.into()));
+        let designation = Some(ReservationItem::ExtraService(9.into()));
+        assert_eq!(rules.commission(1.into(), course), &Commission::Rate(70));
+        assert_eq!(rules.commission(1.into(), None), &Commission::Rate(60));
+        assert_eq!(
+            rules.commission(1.into(), designation),
+            &Commission::Fixed(yen(1000))
+        );
+        assert_eq!(rules.commission(2.into(), course), &Commission::Rate(50));
+    }
+
+    #[test]
+    fn test_statement() {
+        let start = Utc.with_ymd_and_hms(2023, 4, 1, 10, 0, 0).unwrap();
+        let mut reservation = Reservation::create(
+            10.into(),
+            vec![1.into(), 2.into()],
+            start..start + Duration::hours(1),
+            ReservationCustomer::Unregistered {
+                name: "山田".to_owned(),
+                phone: "09000000000".to_owned(),
+            },
+        )
+        .unwrap();
+        reservation
+            .add_detail(
+                ReservationDetail::create(1.into(), "60分コース".to_owned(), 1, yen(20000))
+                    .unwrap()
+                    .with_item(ReservationItem::Service(1.into())),
+                &TaxTable::default(),
+            )
+            .unwrap();
+        reservation
+            .add_detail(
+                ReservationDetail::create(2.into(), "指名料".to_owned(), 1, yen(2000))
+                    .unwrap()
+                    .with_item(ReservationItem::ExtraService(9.into())),
+                &TaxTable::default(),
+            )
+            .unwrap();
+        let shifts = vec![
+            Shift::create(
+                ShiftId::from(1),
+                start..start + Duration::hours(8),
+                ShiftStatus::Confirmed,
+            )
+            .unwrap(),
+            Shift::create(
+                ShiftId::from(2),
+                start + Duration::days(1)..start + Duration::days(1) + Duration::hours(8),
+                ShiftStatus::Canceled,
+            )
+            .unwrap(),
+        ];
+        let period = Utc.with_ymd_and_hms(2023, 4, 1, 0, 0, 0).unwrap()
+            ..Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap();
+
+        // 終了時刻を過ぎていても完了していない予約は含めない
+        let statement = rules()
+            .statement(
+                1.into(),
+                period.clone(),
+                &[reservation.clone()],
+                &shifts,
+                start + Duration::days(2),
+            )
+            .unwrap();
+        assert_eq!(statement.lines.len(), 1);
+        assert_eq!(statement.lines[0].description, "出勤手当");
+
+        let mut completed = reservation.clone();
+        completed
+            .complete_with_override(1.into(), Role::Manager)
+            .unwrap();
+        let statement = rules()
+            .statement(
+                1.into(),
+                period.clone(),
+                &[completed.clone()],
+                &shifts,
+                start + Duration::days(2),
+            )
+            .unwrap();
+        let amounts = statement
+            .lines
+            .iter()
+            .map(|l| (l.description.as_str(), l.sales.amount(), l.amount.amount()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            amounts,
+            vec![
+                ("60分コース", 10000, 7000),
+                ("指名料", 1000, 1000),
+                ("出勤手当", 0, 2000)
+            ]
+        );
+        assert_eq!(statement.total(), Ok(yen(10000)));
+
+        // 終了していないシフトは含めない
+        let statement = rules()
+            .statement(1.into(), period, &[reservation], &shifts, start)
+            .unwrap();
+        assert!(statement.lines.is_empty());
+    }
+
+    #[test]
+    fn test_statement_with_designation_fee() {
+        let start = Utc.with_ymd_and_hms(2023, 4, 1, 10, 0, 0).unwrap();
+        let mut reservation = Reservation::create(
+            10.into(),
+            vec![1.into(), 2.into()],
+            start..start + Duration::hours(1),
+            ReservationCustomer::Registered { id: 5.into() },
+        )
+        .unwrap();
+        let fees = DesignationFees {
+            photo: None,
+            regular: Some(Price::new(yen(3000), PriceUnit::OneTime)),
+        };
+        reservation
+            .designate(
+                2.into(),
+                Designation::Regular,
+                1.into(),
+                &fees,
+                &TaxTable::default(),
+            )
+            .unwrap();
+        reservation
+            .complete_with_override(1.into(), Role::Manager)
+            .unwrap();
+        let period = start..start + Duration::days(1);
+        let lines = |prostitute_id: u64| {
+            PayrollRules::default()
+                .statement(
+                    prostitute_id.into(),
+                    period.clone(),
+                    &[reservation.clone()],
+                    &[],
+                    start + Duration::days(1),
+                )
+                .unwrap()
+                .lines
+        };
+        assert!(lines(1).is_empty());
+        assert_eq!(lines(2)[0].sales, yen(3000));
+    }
+
+    #[test]
+    fn test_empty_statement() {
+        let rules = PayrollRules {
+            currency: Currency::USD,
+            ..PayrollRules::default()
+        };
+        let now = Utc::now();
+        let statement = rules.statement(1.into(), now..now, &[], &[], now).unwrap();
+        assert_eq!(statement.total(), Ok(Money::zero(Currency::USD)));
+    }
+
+    #[test]
+    fn test_invalid_rate() {
+        let rules = PayrollRules {
+            default_commission: Commission::Rate(101),
+            ..PayrollRules::default()
+        };
+        let now = Utc::now();
+        assert_eq!(
+            rules.statement(1.into(), now..now, &[], &[], now),
+            Err(PayrollError::InvalidRate)
+        );
+    }
+}
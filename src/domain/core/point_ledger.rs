@@ -0,0 +1,785 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use derive_more::{Deref, Display, Error, From, IntoIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata};
+
+use super::{
+    Currency, CustomerId, Money, MoneyError, Reservation, ReservationCustomer, ReservationDetail,
+    ReservationDetailId, ReservationId, ReservationItem, Rounding, TaxCategory, TaxInclusion,
+};
+
+/// ポイント台帳リポジトリ
+#[async_trait]
+pub trait PointLedgerRepository {
+    /// ポイント台帳をIDで検索する
+    async fn find_by_id(&self, id: PointLedgerId) -> Result<Option<PointLedger>, DataAccessError>;
+    /// 指定日時時点のポイント台帳をIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: PointLedgerId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<PointLedger>, DataAccessError>;
+    /// 指定リビジョン時点のポイント台帳をIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: PointLedgerId,
+        revision: u64,
+    ) -> Result<Option<PointLedger>, DataAccessError>;
+    /// ポイント台帳を保存する
+    async fn save(
+        &mut self,
+        entity: &mut PointLedger,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
+}
+
+/// ポイント台帳ID
+///
+/// お客様ごとに1つだけ作成するため、お客様IDと同じ値を使う。
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display, From, Deref, Default,
+)]
+pub struct PointLedgerId(u64);
+
+impl Id for PointLedgerId {
+    type Inner = u64;
+}
+
+impl From<CustomerId> for PointLedgerId {
+    fn from(value: CustomerId) -> Self {
+        Self(*value)
+    }
+}
+
+/// ポイントの付与率と有効期限の設定
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PointPolicy {
+    /// 支払額に対して付与するポイントの割合（百分率、1ポイントは1通貨単位として扱う）
+    pub earn_rate: u8,
+    /// 付与してから失効するまでの日数（`None`は無期限）
+    pub expires_after_days: Option<u32>,
+}
+
+impl PointPolicy {
+    /// 付与日時から有効期限を計算する
+    pub fn expires_at(&self, earned_at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.expires_after_days
+            .map(|days| earned_at + Duration::days(days as i64))
+    }
+}
+
+/// 支払額の1%を付与し、1年で失効する
+impl Default for PointPolicy {
+    fn default() -> Self {
+        PointPolicy {
+            earn_rate: 1,
+            expires_after_days: Some(365),
+        }
+    }
+}
+
+/// 1ポイントに相当する金額（通貨の最小単位）
+fn point_value(currency: Currency) -> i64 {
+    10i64.pow(currency.minor_units())
+}
+
+/// 付与ごとのポイントの残高
+///
+/// ポイントは有効期限の近いものから使う。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PointLot {
+    /// 付与した予約（調整で付与した場合は`None`）
+    pub reservation_id: Option<ReservationId>,
+    /// 付与したポイント
+    pub points: u64,
+    /// 残りのポイント
+    pub remaining: u64,
+    /// 付与日時
+    pub earned_at: DateTime<Utc>,
+    /// 有効期限（`None`は無期限）
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl PointLot {
+    fn is_expired(&self, time: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= time)
+    }
+}
+
+/// ポイント台帳イベント
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PointLedgerEvent {
+    /// ポイント台帳が作成された
+    PointLedgerOpened { id: PointLedgerId },
+    /// 完了した予約でポイントが付与された
+    PointsEarned {
+        id: PointLedgerId,
+        reservation_id: ReservationId,
+        points: u64,
+        earned_at: DateTime<Utc>,
+        expires_at: Option<DateTime<Utc>>,
+    },
+    /// 予約の支払いにポイントが使われた
+    PointsRedeemed {
+        id: PointLedgerId,
+        reservation_id: ReservationId,
+        points: u64,
+        redeemed_at: DateTime<Utc>,
+    },
+    /// 有効期限を過ぎたポイントが失効した
+    PointsExpired {
+        id: PointLedgerId,
+        points: u64,
+        expired_at: DateTime<Utc>,
+    },
+    /// ポイントが手動で調整された（正数は加算、負数は減算）
+    PointsAdjusted {
+        id: PointLedgerId,
+        points: i64,
+        reason: String,
+        adjusted_at: DateTime<Utc>,
+    },
+}
+
+impl Event for PointLedgerEvent {
+    type Id = PointLedgerId;
+}
+
+/// ポイント台帳エンティティ
+#[derive(Debug, Default, Clone, IntoIterator, Serialize, Deserialize)]
+pub struct PointLedger {
+    id: PointLedgerId,
+    lots: Vec<PointLot>,
+    #[serde(skip)]
+    #[into_iterator]
+    events: EventQueue<PointLedgerEvent>,
+}
+
+impl PointLedger {
+    /// お客様のポイント台帳を作成する
+    pub fn open(customer_id: CustomerId) -> Self {
+        let id = PointLedgerId::from(customer_id);
+        let mut entity = PointLedger {
+            id,
+            ..Default::default()
+        };
+        entity
+            .events
+            .push(PointLedgerEvent::PointLedgerOpened { id });
+        entity
+    }
+
+    /// 完了した予約の合計金額からポイントを付与し、付与したポイントを返す
+    ///
+    /// 1ポイントは1通貨単位（1円、1ドルなど）として扱い、端数は切り捨てる。
+    /// 付与するポイントがない場合はイベントを記録しない。
+    pub fn earn(
+        &mut self,
+        reservation: &Reservation,
+        policy: &PointPolicy,
+        time: DateTime<Utc>,
+    ) -> Result<u64, PointError> {
+        self.validate_customer(reservation)?;
+        if !reservation.is_completed() {
+            return Err(PointError::NotCompleted);
+        }
+        let total = reservation.total()?;
+        let points = total
+            .checked_ratio(
+                policy.earn_rate as i64,
+                100 * point_value(total.currency()),
+                Rounding::Down,
+            )?
+            .amount()
+            .max(0) as u64;
+        if points == 0 {
+            return Ok(0);
+        }
+        let reservation_id = reservation.id();
+        let expires_at = policy.expires_at(time);
+        self.validate_earned(reservation_id, points)?;
+        self.lots.push(PointLot {
+            reservation_id: Some(reservation_id),
+            points,
+            remaining: points,
+            earned_at: time,
+            expires_at,
+        });
+        self.events.push(PointLedgerEvent::PointsEarned {
+            id: self.id,
+            reservation_id,
+            points,
+            earned_at: time,
+            expires_at,
+        });
+        Ok(points)
+    }
+
+    /// 予約の支払いにポイントを使い、予約に追加する負の金額の予約詳細を返す
+    ///
+    /// ポイントは支払手段として扱うため、予約詳細は税区分を非課税にして税率ごとの内訳を変えない。
+    pub fn redeem(
+        &mut self,
+        reservation: &Reservation,
+        points: u64,
+        detail_id: ReservationDetailId,
+        time: DateTime<Utc>,
+    ) -> Result<ReservationDetail, PointError> {
+        self.validate_customer(reservation)?;
+        if reservation.is_completed() {
+            return Err(PointError::AlreadyCompleted);
+        }
+        let total = reservation.total()?;
+        let amount = i64::try_from(points)
+            .ok()
+            .and_then(|points| points.checked_mul(point_value(total.currency())))
+            .ok_or(MoneyError::Overflow)?;
+        if amount > total.amount() {
+            return Err(PointError::ExceedsTotal);
+        }
+        self.validate_redeemed(points, time)?;
+        let detail = ReservationDetail::create(
+            detail_id,
+            "ポイント利用".to_owned(),
+            1,
+            Money::new(-amount, total.currency()),
+        )
+        .map_err(|_| PointError::InvalidPoints)?
+        .with_item(ReservationItem::Points(self.id))
+        .with_tax(TaxCategory::Exempt, 0, TaxInclusion::Inclusive);
+        self.consume(points, time);
+        self.events.push(PointLedgerEvent::PointsRedeemed {
+            id: self.id,
+            reservation_id: reservation.id(),
+            points,
+            redeemed_at: time,
+        });
+        Ok(detail)
+    }
+
+    /// 有効期限を過ぎたポイントを失効させ、失効したポイントを返す
+    ///
+    /// 失効するポイントがない場合はイベントを記録しない。
+    pub fn expire(&mut self, time: DateTime<Utc>) -> u64 {
+        let points = self.expirable(time);
+        if points == 0 {
+            return 0;
+        }
+        self.expire_lots(time);
+        self.events.push(PointLedgerEvent::PointsExpired {
+            id: self.id,
+            points,
+            expired_at: time,
+        });
+        points
+    }
+
+    /// ポイントを手動で調整する（加算したポイントは無期限）
+    pub fn adjust(
+        &mut self,
+        points: i64,
+        reason: String,
+        time: DateTime<Utc>,
+    ) -> Result<(), PointError> {
+        self.validate_adjusted(points, &reason, time)?;
+        self.adjust_lots(points, time);
+        self.events.push(PointLedgerEvent::PointsAdjusted {
+            id: self.id,
+            points,
+            reason,
+            adjusted_at: time,
+        });
+        Ok(())
+    }
+
+    /// 失効の記録に関わらず、指定日時に使えるポイントの残高
+    pub fn balance_at(&self, time: DateTime<Utc>) -> u64 {
+        self.lots
+            .iter()
+            .filter(|lot| !lot.is_expired(time))
+            .map(|lot| lot.remaining)
+            .sum()
+    }
+
+    /// 記録済みのイベントに基づくポイントの残高
+    pub fn balance(&self) -> u64 {
+        self.lots.iter().map(|lot| lot.remaining).sum()
+    }
+
+    pub fn lots(&self) -> &[PointLot] {
+        &self.lots
+    }
+
+    /// 予約で付与したポイントを取得する
+    pub fn earned(&self, reservation_id: ReservationId) -> Option<&PointLot> {
+        self.lots
+            .iter()
+            .find(|lot| lot.reservation_id == Some(reservation_id))
+    }
+
+    /// 指定日時に失効するポイント
+    fn expirable(&self, time: DateTime<Utc>) -> u64 {
+        self.lots
+            .iter()
+            .filter(|lot| lot.is_expired(time))
+            .map(|lot| lot.remaining)
+            .sum()
+    }
+
+    fn expire_lots(&mut self, time: DateTime<Utc>) {
+        for lot in self.lots.iter_mut().filter(|lot| lot.is_expired(time)) {
+            lot.remaining = 0;
+        }
+    }
+
+    /// 有効期限の近いものからポイントを減らす（残高は検証済み）
+    fn consume(&mut self, mut points: u64, time: DateTime<Utc>) {
+        let mut lots = self
+            .lots
+            .iter_mut()
+            .filter(|lot| lot.remaining > 0 && !lot.is_expired(time))
+            .collect::<Vec<_>>();
+        lots.sort_by_key(|lot| (lot.expires_at.is_none(), lot.expires_at, lot.earned_at));
+        for lot in lots {
+            let used = points.min(lot.remaining);
+            lot.remaining -= used;
+            points -= used;
+            if points == 0 {
+                break;
+            }
+        }
+    }
+
+    fn adjust_lots(&mut self, points: i64, time: DateTime<Utc>) {
+        match points.is_positive() {
+            true => self.lots.push(PointLot {
+                reservation_id: None,
+                points: points as u64,
+                remaining: points as u64,
+                earned_at: time,
+                expires_at: None,
+            }),
+            false => self.consume(points.unsigned_abs(), time),
+        }
+    }
+
+    fn validate_id(&self, id: &PointLedgerId) -> Result<(), PointError> {
+        match self.id == *id {
+            true => Ok(()),
+            false => Err(PointError::MismatchedId),
+        }
+    }
+
+    fn validate_customer(&self, reservation: &Reservation) -> Result<(), PointError> {
+        match reservation.customer() {
+            ReservationCustomer::Registered { id } if PointLedgerId::from(*id) == self.id => Ok(()),
+            _ => Err(PointError::CustomerMismatch),
+        }
+    }
+
+    fn validate_earned(
+        &self,
+        reservation_id: ReservationId,
+        points: u64,
+    ) -> Result<(), PointError> {
+        if points == 0 {
+            return Err(PointError::InvalidPoints);
+        }
+        match self.earned(reservation_id) {
+            Some(_) => Err(PointError::AlreadyEarned),
+            None => Ok(()),
+        }
+    }
+
+    fn validate_redeemed(&self, points: u64, time: DateTime<Utc>) -> Result<(), PointError> {
+        if points == 0 {
+            return Err(PointError::InvalidPoints);
+        }
+        match points <= self.balance_at(time) {
+            true => Ok(()),
+            false => Err(PointError::InsufficientPoints),
+        }
+    }
+
+    fn validate_expired(&self, points: u64, time: DateTime<Utc>) -> Result<(), PointError> {
+        match points != 0 && points == self.expirable(time) {
+            true => Ok(()),
+            false => Err(PointError::InvalidPoints),
+        }
+    }
+
+    fn validate_adjusted(
+        &self,
+        points: i64,
+        reason: &str,
+        time: DateTime<Utc>,
+    ) -> Result<(), PointError> {
+        if reason.trim().is_empty() {
+            return Err(PointError::ReasonRequired);
+        }
+        match points.is_negative() {
+            true => self.validate_redeemed(points.unsigned_abs(), time),
+            false if points == 0 => Err(PointError::InvalidPoints),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Entity for PointLedger {
+    type Id = PointLedgerId;
+
+    const ENTITY_NAME: &'static str = "point_ledger";
+
+    fn id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+impl Aggregation for PointLedger {
+    type Event = PointLedgerEvent;
+    type Error = PointError;
+
+    fn validate(&self, event: &Self::Event) -> Result<(), Self::Error> {
+        match event {
+            PointLedgerEvent::PointLedgerOpened { .. } => Ok(()),
+            PointLedgerEvent::PointsEarned {
+                id,
+                reservation_id,
+                points,
+                ..
+            } => {
+                self.validate_id(id)?;
+                self.validate_earned(*reservation_id, *points)
+            }
+            PointLedgerEvent::PointsRedeemed {
+                id,
+                points,
+                redeemed_at,
+                ..
+            } => {
+                self.validate_id(id)?;
+                self.validate_redeemed(*points, *redeemed_at)
+            }
+            PointLedgerEvent::PointsExpired {
+                id,
+                points,
+                expired_at,
+            } => {
+                self.validate_id(id)?;
+                self.validate_expired(*points, *expired_at)
+            }
+            PointLedgerEvent::PointsAdjusted {
+                id,
+                points,
+                reason,
+                adjusted_at,
+            } => {
+                self.validate_id(id)?;
+                self.validate_adjusted(*points, reason, *adjusted_at)
+            }
+        }
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        match event {
+            PointLedgerEvent::PointLedgerOpened { id } => {
+                if self.id != id {
+                    *self = Self::open(CustomerId::from(*id));
+                }
+            }
+            PointLedgerEvent::PointsEarned {
+                id,
+                reservation_id,
+                points,
+                earned_at,
+                expires_at,
+            } => {
+                // 付与時の予約や設定はイベントに残らないため、記録をそのまま復元する
+                if self.id == id && self.validate_earned(reservation_id, points).is_ok() {
+                    self.lots.push(PointLot {
+                        reservation_id: Some(reservation_id),
+                        points,
+                        remaining: points,
+                        earned_at,
+                        expires_at,
+                    });
+                    self.events.push(PointLedgerEvent::PointsEarned {
+                        id,
+                        reservation_id,
+                        points,
+                        earned_at,
+                        expires_at,
+                    });
+                }
+            }
+            PointLedgerEvent::PointsRedeemed {
+                id,
+                reservation_id,
+                points,
+                redeemed_at,
+            } => {
+                if self.id == id && self.validate_redeemed(points, redeemed_at).is_ok() {
+                    self.consume(points, redeemed_at);
+                    self.events.push(PointLedgerEvent::PointsRedeemed {
+                        id,
+                        reservation_id,
+                        points,
+                        redeemed_at,
+                    });
+                }
+            }
+            PointLedgerEvent::PointsExpired { id, expired_at, .. } => {
+                if self.id == id {
+                    self.expire(expired_at);
+                }
+            }
+            PointLedgerEvent::PointsAdjusted {
+                id,
+                points,
+                reason,
+                adjusted_at,
+            } => {
+                if self.id == id {
+                    if let Err(_e) = self.adjust(points, reason, adjusted_at) {}
+                }
+            }
+        }
+    }
+
+    fn events(&self) -> &EventQueue<Self::Event> {
+        &self.events
+    }
+
+    fn events_mut(&mut self) -> &mut EventQueue<Self::Event> {
+        &mut self.events
+    }
+}
+
+impl PartialEq for PointLedger {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.lots == other.lots
+    }
+}
+
+impl Eq for PointLedger {}
+
+/// ポイントエラー
+#[derive(Error, Display, Debug, PartialEq, Eq)]
+pub enum PointError {
+    /// IDが一致しません
+    #[display(fmt = "ID does not match")]
+    MismatchedId,
+    /// 予約したお客様が台帳のお客様と一致しません
+    #[display(fmt = "Reservation customer does not match the ledger")]
+    CustomerMismatch,
+    /// 予約が完了していません
+    #[display(fmt = "Reservation is not completed")]
+    NotCompleted,
+    /// 予約は既に完了しています
+    #[display(fmt = "Reservation is already completed")]
+    AlreadyCompleted,
+    /// 予約で既にポイントを付与しています
+    #[display(fmt = "Points are already earned for the reservation")]
+    AlreadyEarned,
+    /// ポイントが不正です
+    #[display(fmt = "Invalid points")]
+    InvalidPoints,
+    /// ポイントが足りません
+    #[display(fmt = "Insufficient points")]
+    InsufficientPoints,
+    /// ポイントが予約の合計金額を超えています
+    #[display(fmt = "Points exceed the reservation total")]
+    ExceedsTotal,
+    /// 調整の理由が指定されていません
+    #[display(fmt = "Reason is not specified")]
+    ReasonRequired,
+    /// 金額エラー
+    #[display(fmt = "Money error: {}", _0)]
+    MoneyError(MoneyError),
+}
+
+impl From<MoneyError> for PointError {
+    fn from(value: MoneyError) -> Self {
+        Self::MoneyError(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::domain::core::{Currency, Payment, PaymentMethod, TaxTable};
+
+    fn reservation(id: u64, customer_id: u64, amount: i64) -> Reservation {
+        let start = Utc.with_ymd_and_hms(2023, 4, 1, 10, 0, 0).unwrap();
+        let mut reservation = Reservation::create(
+            id.into(),
+            vec![1.into()],
+            start..start + Duration::hours(1),
+            ReservationCustomer::Registered {
+                id: customer_id.into(),
+            },
+        )
+        .unwrap();
+        reservation
+            .add_detail(
+                ReservationDetail::create(
+                    1.into(),
+                    "60分コース".to_owned(),
+                    1,
+                    Money::new(amount, Currency::JPY),
+                )
+                .unwrap(),
+                &TaxTable::default(),
+            )
+            .unwrap();
+        reservation
+    }
+
+    fn complete(reservation: &mut Reservation) {
+        let balance = reservation.balance_due().unwrap();
+        reservation
+            .receive_payment(Payment::create(1.into(), PaymentMethod::Cash, balance, None).unwrap())
+            .unwrap();
+        reservation.complete().unwrap();
+    }
+
+    #[test]
+    fn test_earn_and_redeem() {
+        let time = Utc.with_ymd_and_hms(2023, 4, 1, 12, 0, 0).unwrap();
+        let policy = PointPolicy::default();
+        let mut ledger = PointLedger::open(5.into());
+
+        let mut first = reservation(1, 5, 22000);
+        assert_eq!(
+            ledger.earn(&first, &policy, time),
+            Err(PointError::NotCompleted)
+        );
+        complete(&mut first);
+        assert_eq!(ledger.earn(&first, &policy, time), Ok(220));
+        assert_eq!(
+            ledger.earn(&first, &policy, time),
+            Err(PointError::AlreadyEarned)
+        );
+        assert_eq!(
+            ledger.earn(&reservation(2, 6, 22000), &policy, time),
+            Err(PointError::CustomerMismatch)
+        );
+        ledger.adjust(100, "キャンペーン".to_owned(), time).unwrap();
+        assert_eq!(ledger.balance(), 320);
+
+        let mut second = reservation(3, 5, 16500);
+        assert_eq!(
+            ledger.redeem(&second, 321, 2.into(), time),
+            Err(PointError::InsufficientPoints)
+        );
+        let detail = ledger.redeem(&second, 250, 2.into(), time).unwrap();
+        assert_eq!(detail.price(), &Money::new(-250, Currency::JPY));
+        assert_eq!(detail.item(), Some(ReservationItem::Points(5.into())));
+        second.add_detail(detail, &TaxTable::default()).unwrap();
+        assert_eq!(second.total(), Ok(Money::new(16250, Currency::JPY)));
+        assert_eq!(ledger.balance(), 70);
+        // 有効期限のあるポイントから使う
+        assert_eq!(ledger.earned(1.into()).map(|lot| lot.remaining), Some(0));
+
+        let events = ledger.pop_all();
+        let mut replayed = PointLedger::default();
+        for event in events {
+            replayed.validate(&event).unwrap();
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, ledger);
+    }
+
+    #[test]
+    fn test_points_in_currency_units() {
+        let time = Utc.with_ymd_and_hms(2023, 4, 1, 12, 0, 0).unwrap();
+        let policy = PointPolicy {
+            earn_rate: 10,
+            ..Default::default()
+        };
+        let mut ledger = PointLedger::open(5.into());
+        let usd = |id: u64, cents: i64| {
+            let mut reservation = reservation(id, 5, 0);
+            reservation.delete_detail(1.into()).unwrap();
+            reservation
+                .add_detail(
+                    ReservationDetail::create(
+                        1.into(),
+                        "60 min".to_owned(),
+                        1,
+                        Money::new(cents, Currency::USD),
+                    )
+                    .unwrap(),
+                    &TaxTable::default(),
+                )
+                .unwrap();
+            reservation
+        };
+
+        // $123.45の10%は12ポイント（1ポイントは1ドル）
+        let mut first = usd(1, 12345);
+        complete(&mut first);
+        assert_eq!(ledger.earn(&first, &policy, time), Ok(12));
+
+        let second = usd(2, 1000);
+        assert_eq!(
+            ledger.redeem(&second, 11, 2.into(), time),
+            Err(PointError::ExceedsTotal)
+        );
+        let detail = ledger.redeem(&second, 10, 2.into(), time).unwrap();
+        assert_eq!(detail.price(), &Money::new(-1000, Currency::USD));
+        assert_eq!(ledger.balance(), 2);
+    }
+
+    #[test]
+    fn test_expire() {
+        let time = Utc.with_ymd_and_hms(2023, 4, 1, 12, 0, 0).unwrap();
+        let policy = PointPolicy {
+            earn_rate: 5,
+            expires_after_days: Some(30),
+        };
+        let mut ledger = PointLedger::open(5.into());
+        let mut first = reservation(1, 5, 10000);
+        complete(&mut first);
+        ledger.earn(&first, &policy, time).unwrap();
+        ledger.adjust(50, "お詫び".to_owned(), time).unwrap();
+        assert_eq!(
+            ledger.adjust(0, "お詫び".to_owned(), time),
+            Err(PointError::InvalidPoints)
+        );
+        assert_eq!(
+            ledger.adjust(-10, " ".to_owned(), time),
+            Err(PointError::ReasonRequired)
+        );
+
+        let later = time + Duration::days(30);
+        assert_eq!(ledger.balance_at(later), 50);
+        assert_eq!(ledger.balance(), 550);
+        assert_eq!(ledger.expire(time), 0);
+        assert_eq!(ledger.expire(later), 500);
+        assert_eq!(ledger.balance(), 50);
+        ledger.adjust(-20, "誤付与".to_owned(), later).unwrap();
+        assert_eq!(ledger.balance(), 30);
+
+        let events = ledger.pop_all();
+        assert_eq!(
+            events[3],
+            PointLedgerEvent::PointsExpired {
+                id: 5.into(),
+                points: 500,
+                expired_at: later,
+            }
+        );
+        let mut replayed = PointLedger::default();
+        for event in events {
+            replayed.validate(&event).unwrap();
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, ledger);
+    }
+}
@@ -5,19 +5,39 @@ use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
 use derive_more::{Deref, Display, Error, From, IntoIterator};
 use serde::{Deserialize, Serialize};
 
-use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id};
+use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata};
 
-use super::MediaId;
+use super::{MediaId, TagEvent, TagId};
 
 /// 女の子リポジトリ
 #[async_trait]
 pub trait ProstituteRepository {
     /// IDで女の子を検索する
     async fn find_by_id(&self, id: ProstituteId) -> Result<Option<Prostitute>, DataAccessError>;
+    /// 指定日時時点の女の子をIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: ProstituteId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Prostitute>, DataAccessError>;
+    /// 指定リビジョン時点の女の子をIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: ProstituteId,
+        revision: u64,
+    ) -> Result<Option<Prostitute>, DataAccessError>;
     /// 女の子を保存する
-    async fn save(&mut self, entity: &mut Prostitute) -> Result<bool, DataAccessError>;
+    async fn save(
+        &mut self,
+        entity: &mut Prostitute,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
     /// 女の子を削除する
-    async fn delete(&mut self, entity: &mut Prostitute) -> Result<bool, DataAccessError>;
+    async fn delete(
+        &mut self,
+        entity: &mut Prostitute,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
 }
 
 /// 女の子ID
@@ -52,7 +72,7 @@ pub enum ProstituteEvent {
     /// 女の子が退職した
     ProstituteLeaved { id: ProstituteId },
     /// 女の子の名前が変更された
-    ProstituteExtraServiceNameChanged { id: ProstituteId, name: String },
+    ProstituteNameChanged { id: ProstituteId, name: String },
     /// 女の子のキャッチフレーズが変更された
     ProstituteCatchphraseChanged {
         id: ProstituteId,
@@ -112,12 +132,19 @@ pub enum ProstituteEvent {
         id: ProstituteId,
         media_id: Option<MediaId>,
     },
+    /// 女の子にタグが付けられた
+    ProstituteTagAdded { id: ProstituteId, tag_id: TagId },
+    /// 女の子からタグが外された
+    ProstituteTagRemoved { id: ProstituteId, tag_id: TagId },
     /// 女の子が削除された
     ProstituteDeleted { id: ProstituteId },
 }
 
 impl Event for ProstituteEvent {
     type Id = ProstituteId;
+
+    /// 2: `ProstituteExtraServiceNameChanged`を`ProstituteNameChanged`に改名
+    const SCHEMA_VERSION: u32 = 2;
 }
 
 /// 女の子エンティティ
@@ -145,6 +172,9 @@ pub struct Prostitute {
     images: Vec<MediaId>,
     /// 動画
     video: Option<MediaId>,
+    /// タグ
+    #[serde(default)]
+    tags: Vec<TagId>,
     /// 退職済みか
     leaved: bool,
     #[serde(skip)]
@@ -217,7 +247,7 @@ impl Prostitute {
         Self::validate_name(&name)?;
         self.name = name.clone();
         self.events
-            .push(ProstituteEvent::ProstituteExtraServiceNameChanged { id: self.id, name });
+            .push(ProstituteEvent::ProstituteNameChanged { id: self.id, name });
         Ok(())
     }
 
@@ -366,6 +396,39 @@ impl Prostitute {
         self.video = video;
     }
 
+    pub fn add_tag(&mut self, tag_id: TagId) -> Result<(), ProstituteError> {
+        self.validate_tag_added(&tag_id)?;
+        self.tags.push(tag_id);
+        self.events.push(ProstituteEvent::ProstituteTagAdded {
+            id: self.id,
+            tag_id,
+        });
+        Ok(())
+    }
+
+    pub fn remove_tag(&mut self, tag_id: TagId) -> Result<(), ProstituteError> {
+        self.validate_tag_removed(&tag_id)?;
+        self.tags.retain(|&t| t != tag_id);
+        self.events.push(ProstituteEvent::ProstituteTagRemoved {
+            id: self.id,
+            tag_id,
+        });
+        Ok(())
+    }
+
+    /// タグのイベントを反映する（削除されたタグは女の子からも外す）
+    ///
+    /// タグの削除はタグのストリームに記録されるため、女の子のイベントは発行しない
+    pub fn apply_tag_event(&mut self, event: &TagEvent) {
+        if let TagEvent::TagDeleted { id } = event {
+            self.tags.retain(|t| t != id);
+        }
+    }
+
+    pub fn tags(&self) -> &[TagId] {
+        &self.tags
+    }
+
     fn validate_id(&self, id: &ProstituteId) -> Result<(), ProstituteError> {
         match self.id == *id {
             true => Ok(()),
@@ -456,6 +519,20 @@ impl Prostitute {
         }
     }
 
+    fn validate_tag_added(&self, tag_id: &TagId) -> Result<(), ProstituteError> {
+        match self.tags.contains(tag_id) {
+            true => Err(ProstituteError::DuplicateTag),
+            false => Ok(()),
+        }
+    }
+
+    fn validate_tag_removed(&self, tag_id: &TagId) -> Result<(), ProstituteError> {
+        match self.tags.contains(tag_id) {
+            true => Ok(()),
+            false => Err(ProstituteError::TagNotFound),
+        }
+    }
+
     fn validate_question_not_found(&self, index: &usize) -> Result<(), ProstituteError> {
         if *index >= self.questions.len() {
             Err(ProstituteError::QuestionNotFound)
@@ -502,7 +579,7 @@ impl Aggregation for Prostitute {
                 self.validate_id(id)?;
                 self.validate_leaved()
             }
-            ProstituteEvent::ProstituteExtraServiceNameChanged { id, name } => {
+            ProstituteEvent::ProstituteNameChanged { id, name } => {
                 self.validate_id(id)?;
                 Self::validate_name(name)
             }
@@ -546,6 +623,14 @@ impl Aggregation for Prostitute {
                 self.validate_id(id)?;
                 self.validate_image_swapped(media_id_a, media_id_b)
             }
+            ProstituteEvent::ProstituteTagAdded { id, tag_id } => {
+                self.validate_id(id)?;
+                self.validate_tag_added(tag_id)
+            }
+            ProstituteEvent::ProstituteTagRemoved { id, tag_id } => {
+                self.validate_id(id)?;
+                self.validate_tag_removed(tag_id)
+            }
             ProstituteEvent::ProstituteVideoChanged { id, .. }
             | ProstituteEvent::ProstituteDeleted { id, .. } => self.validate_id(id),
         }
@@ -594,7 +679,7 @@ impl Aggregation for Prostitute {
                     if let Err(_e) = self.leave() {}
                 }
             }
-            ProstituteEvent::ProstituteExtraServiceNameChanged { id, name } => {
+            ProstituteEvent::ProstituteNameChanged { id, name } => {
                 if self.id == id {
                     if let Err(_e) = self.change_name(name) {}
                 }
@@ -682,6 +767,16 @@ impl Aggregation for Prostitute {
                     self.change_video(media_id)
                 }
             }
+            ProstituteEvent::ProstituteTagAdded { id, tag_id } => {
+                if self.id == id {
+                    if let Err(_e) = self.add_tag(tag_id) {}
+                }
+            }
+            ProstituteEvent::ProstituteTagRemoved { id, tag_id } => {
+                if self.id == id {
+                    if let Err(_e) = self.remove_tag(tag_id) {}
+                }
+            }
             ProstituteEvent::ProstituteDeleted { .. } => {}
         }
     }
@@ -708,6 +803,7 @@ impl PartialEq for Prostitute {
             && self.questions == other.questions
             && self.images == other.images
             && self.video == other.video
+            && self.tags == other.tags
             && self.leaved == other.leaved
     }
 }
@@ -747,6 +843,12 @@ pub enum ProstituteError {
     /// 画像のインデックスが重複しています
     #[display(fmt = "Duplicate image index")]
     DuplicateImageIndex,
+    /// タグが既に付いています
+    #[display(fmt = "Tag already exists")]
+    DuplicateTag,
+    /// タグが見つかりません
+    #[display(fmt = "Tag not found")]
+    TagNotFound,
 }
 
 /// 体型
@@ -933,10 +1035,19 @@ pub enum BloodType {
 }
 
 /// 誕生日
-#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize, From)]
 pub struct Birthday(NaiveDate);
 
 impl Birthday {
+    /// 指定した日付時点の年齢
+    pub fn age_on(&self, date: NaiveDate) -> i32 {
+        let age = date.year() - self.0.year();
+        match (date.month(), date.day()) < (self.0.month(), self.0.day()) {
+            true => age - 1,
+            false => age,
+        }
+    }
+
     pub fn age<Tz: TimeZone>(&self, timezone: &Tz) -> i32 {
         let current = Utc::now().with_timezone(timezone);
         let birthday = DateTime::<Tz>::from_utc(
@@ -991,4 +1102,63 @@ mod tests {
         assert_eq!(CupSize::new(92, 65), CupSize::H);
         assert_eq!(CupSize::new(98, 80), CupSize::D);
     }
+
+    #[test]
+    fn test_birthday_age_on() {
+        let birthday = Birthday::from(NaiveDate::from_ymd_opt(2000, 4, 20).unwrap());
+        assert_eq!(
+            birthday.age_on(NaiveDate::from_ymd_opt(2023, 4, 19).unwrap()),
+            22
+        );
+        assert_eq!(
+            birthday.age_on(NaiveDate::from_ymd_opt(2023, 4, 20).unwrap()),
+            23
+        );
+        assert_eq!(
+            birthday.age_on(NaiveDate::from_ymd_opt(2023, 12, 1).unwrap()),
+            23
+        );
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut prostitute = Prostitute::join(
+            1.into(),
+            "あい".to_owned(),
+            "よろしくお願いします".to_owned(),
+            String::new(),
+            String::new(),
+            Figure::default(),
+            None,
+            None,
+            Vec::new(),
+            Vec::new(),
+            None,
+        )
+        .unwrap();
+        prostitute.add_tag(1.into()).unwrap();
+        prostitute.add_tag(2.into()).unwrap();
+        assert!(matches!(
+            prostitute.add_tag(1.into()),
+            Err(ProstituteError::DuplicateTag)
+        ));
+        prostitute.remove_tag(1.into()).unwrap();
+        assert!(matches!(
+            prostitute.remove_tag(1.into()),
+            Err(ProstituteError::TagNotFound)
+        ));
+        assert_eq!(prostitute.tags(), &[2.into()]);
+
+        let mut replayed = Prostitute::default();
+        for event in prostitute.pop_all() {
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, prostitute);
+
+        prostitute.add_tag(3.into()).unwrap();
+        prostitute.pop_all();
+        prostitute.apply_tag_event(&TagEvent::TagDeleted { id: 2.into() });
+        assert_eq!(prostitute.tags(), &[3.into()]);
+        assert!(prostitute.pop_all().is_empty());
+    }
 }
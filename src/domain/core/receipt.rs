@@ -0,0 +1,581 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use derive_more::{Deref, Display, Error, From, IntoIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata};
+
+use super::{
+    Money, MoneyError, Reservation, ReservationId, TaxBreakdown, TaxCategory, TaxInclusion,
+};
+
+/// 領収書控えリポジトリ
+#[async_trait]
+pub trait ReceiptBookRepository {
+    /// 領収書控えをIDで検索する
+    async fn find_by_id(&self, id: ReceiptBookId) -> Result<Option<ReceiptBook>, DataAccessError>;
+    /// 指定日時時点の領収書控えをIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: ReceiptBookId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<ReceiptBook>, DataAccessError>;
+    /// 指定リビジョン時点の領収書控えをIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: ReceiptBookId,
+        revision: u64,
+    ) -> Result<Option<ReceiptBook>, DataAccessError>;
+    /// 領収書控えを保存する
+    async fn save(
+        &mut self,
+        entity: &mut ReceiptBook,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
+}
+
+/// 領収書控えID
+///
+/// 領収書番号は年ごとに1から振り直すため、発行年をIDとする。
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display, From, Deref, Default,
+)]
+pub struct ReceiptBookId(u64);
+
+impl Id for ReceiptBookId {
+    type Inner = u64;
+}
+
+/// 発行事業者
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Issuer {
+    /// 氏名または名称
+    pub name: String,
+    /// 登録番号（`T`とチェックデジットを含む13桁の数字）
+    pub registration_number: String,
+    /// 所在地
+    pub address: Option<String>,
+    /// 電話番号
+    pub phone: Option<String>,
+}
+
+impl Issuer {
+    fn validate(&self) -> Result<(), ReceiptError> {
+        if self.name.trim().is_empty() {
+            return Err(ReceiptError::IssuerNameIsBlank);
+        }
+        let digits = self
+            .registration_number
+            .strip_prefix('T')
+            .unwrap_or_default()
+            .chars()
+            .map(|c| c.to_digit(10))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+        // 先頭の1桁は法人番号と同じ計算方法のチェックデジット
+        match digits.split_first() {
+            Some((check_digit, rest)) if rest.len() == 12 => {
+                let sum: u32 = rest
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .map(|(i, d)| if i % 2 == 0 { *d } else { d * 2 })
+                    .sum();
+                match *check_digit == 9 - sum % 9 {
+                    true => Ok(()),
+                    false => Err(ReceiptError::InvalidRegistrationNumber),
+                }
+            }
+            _ => Err(ReceiptError::InvalidRegistrationNumber),
+        }
+    }
+}
+
+/// 書類の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ReceiptKind {
+    /// 領収書
+    #[default]
+    Receipt,
+    /// 請求書
+    Invoice,
+}
+
+impl ReceiptKind {
+    /// 書類の表題
+    pub fn title(&self) -> &'static str {
+        match self {
+            ReceiptKind::Receipt => "領収書",
+            ReceiptKind::Invoice => "請求書",
+        }
+    }
+}
+
+/// 領収書の明細行
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptLine {
+    /// 内容
+    pub name: String,
+    /// 数量
+    pub quantity: u32,
+    /// 金額（単価×数量）
+    pub amount: Money,
+    /// 税区分
+    pub tax_category: TaxCategory,
+    /// 税率（百分率）
+    pub tax_rate: u8,
+    /// 金額が税込か税抜か
+    pub tax_inclusion: TaxInclusion,
+}
+
+/// 適格請求書の要件を満たす領収書・請求書
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    /// 領収書控え（発行年）
+    pub book_id: ReceiptBookId,
+    /// 年ごとの通し番号
+    pub number: u64,
+    /// 書類の種類
+    pub kind: ReceiptKind,
+    /// 予約
+    pub reservation_id: ReservationId,
+    /// 発行事業者
+    pub issuer: Issuer,
+    /// 宛名
+    pub addressee: String,
+    /// 取引日時（予約の開始日時）
+    pub transaction_date: DateTime<Utc>,
+    /// 明細行
+    pub lines: Vec<ReceiptLine>,
+    /// 税率ごとの内訳
+    pub tax_breakdown: Vec<TaxBreakdown>,
+    /// 税込の合計金額
+    pub total: Money,
+    /// 最初の発行日時
+    pub issued_at: DateTime<Utc>,
+    /// 再発行した回数
+    #[serde(default)]
+    pub reissue_count: u32,
+}
+
+impl Receipt {
+    /// 予約から領収書を作成する
+    pub fn from_reservation(
+        book_id: ReceiptBookId,
+        number: u64,
+        kind: ReceiptKind,
+        reservation: &Reservation,
+        issuer: Issuer,
+        addressee: String,
+        issued_at: DateTime<Utc>,
+    ) -> Result<Self, ReceiptError> {
+        issuer.validate()?;
+        if reservation.details().is_empty() {
+            return Err(ReceiptError::NoDetails);
+        }
+        let lines = reservation
+            .details()
+            .iter()
+            .map(|d| {
+                Ok(ReceiptLine {
+                    name: d.name().to_owned(),
+                    quantity: d.quantity(),
+                    amount: d.subtotal()?,
+                    tax_category: d.tax_category(),
+                    tax_rate: d.tax_rate(),
+                    tax_inclusion: d.tax_inclusion(),
+                })
+            })
+            .collect::<Result<Vec<_>, MoneyError>>()?;
+        Ok(Receipt {
+            book_id,
+            number,
+            kind,
+            reservation_id: reservation.id(),
+            issuer,
+            addressee,
+            transaction_date: reservation.time().start,
+            lines,
+            tax_breakdown: reservation.tax_breakdown()?,
+            total: reservation.total()?,
+            issued_at,
+            reissue_count: 0,
+        })
+    }
+
+    /// 表示用の領収書番号（`2023-000001`形式）
+    pub fn display_number(&self) -> String {
+        format!("{:04}-{:06}", *self.book_id, self.number)
+    }
+
+    /// 再発行した領収書であるか
+    pub fn is_reissued(&self) -> bool {
+        self.reissue_count > 0
+    }
+}
+
+/// 領収書控えイベント
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReceiptBookEvent {
+    /// 領収書控えが作成された
+    ReceiptBookOpened { id: ReceiptBookId },
+    /// 領収書が発行された
+    ReceiptIssued {
+        id: ReceiptBookId,
+        receipt: Box<Receipt>,
+    },
+    /// 領収書が再発行された
+    ReceiptReissued {
+        id: ReceiptBookId,
+        number: u64,
+        issued_at: DateTime<Utc>,
+    },
+}
+
+impl Event for ReceiptBookEvent {
+    type Id = ReceiptBookId;
+}
+
+/// 領収書控えエンティティ
+///
+/// 発行した領収書を年ごとに通し番号で保持し、同じ予約への二重発行を防ぐ。
+#[derive(Debug, Default, Clone, IntoIterator, Serialize, Deserialize)]
+pub struct ReceiptBook {
+    id: ReceiptBookId,
+    receipts: Vec<Receipt>,
+    #[serde(skip)]
+    #[into_iterator]
+    events: EventQueue<ReceiptBookEvent>,
+}
+
+impl ReceiptBook {
+    /// 発行年の領収書控えを作成する
+    pub fn open(id: ReceiptBookId) -> Self {
+        let mut entity = ReceiptBook {
+            id,
+            ..Default::default()
+        };
+        entity
+            .events
+            .push(ReceiptBookEvent::ReceiptBookOpened { id });
+        entity
+    }
+
+    /// 予約の領収書を次の通し番号で発行する
+    ///
+    /// 同じ予約に同じ種類の書類を発行済みの場合は失敗するため、`reissue`で再発行する。
+    pub fn issue(
+        &mut self,
+        kind: ReceiptKind,
+        reservation: &Reservation,
+        issuer: Issuer,
+        addressee: String,
+        issued_at: DateTime<Utc>,
+    ) -> Result<&Receipt, ReceiptError> {
+        let receipt = Receipt::from_reservation(
+            self.id,
+            self.next_number(),
+            kind,
+            reservation,
+            issuer,
+            addressee,
+            issued_at,
+        )?;
+        self.add(receipt)
+    }
+
+    /// 発行済みの領収書を同じ番号で再発行する
+    pub fn reissue(
+        &mut self,
+        number: u64,
+        issued_at: DateTime<Utc>,
+    ) -> Result<&Receipt, ReceiptError> {
+        let index = self.position(number)?;
+        self.receipts[index].reissue_count += 1;
+        self.events.push(ReceiptBookEvent::ReceiptReissued {
+            id: self.id,
+            number,
+            issued_at,
+        });
+        Ok(&self.receipts[index])
+    }
+
+    pub fn receipts(&self) -> &[Receipt] {
+        &self.receipts
+    }
+
+    /// 通し番号で領収書を取得する
+    pub fn receipt(&self, number: u64) -> Option<&Receipt> {
+        self.receipts.iter().find(|r| r.number == number)
+    }
+
+    /// 予約に発行済みの領収書を取得する
+    pub fn find_by_reservation(
+        &self,
+        reservation_id: ReservationId,
+        kind: ReceiptKind,
+    ) -> Option<&Receipt> {
+        self.receipts
+            .iter()
+            .find(|r| r.reservation_id == reservation_id && r.kind == kind)
+    }
+
+    fn next_number(&self) -> u64 {
+        self.receipts.len() as u64 + 1
+    }
+
+    fn add(&mut self, receipt: Receipt) -> Result<&Receipt, ReceiptError> {
+        self.validate_issued(&receipt)?;
+        self.receipts.push(receipt.clone());
+        self.events.push(ReceiptBookEvent::ReceiptIssued {
+            id: self.id,
+            receipt: Box::new(receipt),
+        });
+        Ok(&self.receipts[self.receipts.len() - 1])
+    }
+
+    fn position(&self, number: u64) -> Result<usize, ReceiptError> {
+        self.receipts
+            .iter()
+            .position(|r| r.number == number)
+            .ok_or(ReceiptError::ReceiptNotFound)
+    }
+
+    fn validate_id(&self, id: &ReceiptBookId) -> Result<(), ReceiptError> {
+        match self.id == *id {
+            true => Ok(()),
+            false => Err(ReceiptError::MismatchedId),
+        }
+    }
+
+    fn validate_issued(&self, receipt: &Receipt) -> Result<(), ReceiptError> {
+        self.validate_id(&receipt.book_id)?;
+        if receipt.number != self.next_number() {
+            return Err(ReceiptError::InvalidNumber);
+        }
+        if self
+            .find_by_reservation(receipt.reservation_id, receipt.kind)
+            .is_some()
+        {
+            return Err(ReceiptError::AlreadyIssued);
+        }
+        receipt.issuer.validate()
+    }
+}
+
+impl Entity for ReceiptBook {
+    type Id = ReceiptBookId;
+
+    const ENTITY_NAME: &'static str = "receipt_book";
+
+    fn id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+impl Aggregation for ReceiptBook {
+    type Event = ReceiptBookEvent;
+    type Error = ReceiptError;
+
+    fn validate(&self, event: &Self::Event) -> Result<(), Self::Error> {
+        match event {
+            ReceiptBookEvent::ReceiptBookOpened { .. } => Ok(()),
+            ReceiptBookEvent::ReceiptIssued { id, receipt } => {
+                self.validate_id(id)?;
+                self.validate_issued(receipt)
+            }
+            ReceiptBookEvent::ReceiptReissued { id, number, .. } => {
+                self.validate_id(id)?;
+                self.position(*number).map(|_| ())
+            }
+        }
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        match event {
+            ReceiptBookEvent::ReceiptBookOpened { id } => {
+                if self.id != id {
+                    *self = Self::open(id);
+                }
+            }
+            ReceiptBookEvent::ReceiptIssued { id, receipt } => {
+                if self.id == id {
+                    if let Err(_e) = self.add(*receipt) {}
+                }
+            }
+            ReceiptBookEvent::ReceiptReissued {
+                id,
+                number,
+                issued_at,
+            } => {
+                if self.id == id {
+                    if let Err(_e) = self.reissue(number, issued_at) {}
+                }
+            }
+        }
+    }
+
+    fn events(&self) -> &EventQueue<Self::Event> {
+        &self.events
+    }
+
+    fn events_mut(&mut self) -> &mut EventQueue<Self::Event> {
+        &mut self.events
+    }
+}
+
+impl PartialEq for ReceiptBook {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.receipts == other.receipts
+    }
+}
+
+impl Eq for ReceiptBook {}
+
+/// 領収書エラー
+#[derive(Error, Display, Debug, PartialEq, Eq)]
+pub enum ReceiptError {
+    /// IDが一致しません
+    #[display(fmt = "ID does not match")]
+    MismatchedId,
+    /// 発行事業者の名称が空欄です
+    #[display(fmt = "Issuer name cannot be blank")]
+    IssuerNameIsBlank,
+    /// 登録番号が不正です
+    #[display(
+        fmt = "Registration number must be T followed by 13 digits with a valid check digit"
+    )]
+    InvalidRegistrationNumber,
+    /// 予約詳細がありません
+    #[display(fmt = "Reservation has no details")]
+    NoDetails,
+    /// 通し番号が連続していません
+    #[display(fmt = "Receipt number is not sequential")]
+    InvalidNumber,
+    /// 既に発行されています
+    #[display(fmt = "Receipt is already issued for the reservation")]
+    AlreadyIssued,
+    /// 領収書が見つかりません
+    #[display(fmt = "Receipt not found")]
+    ReceiptNotFound,
+    /// 金額の計算エラー
+    #[display(fmt = "Money error: {}", _0)]
+    MoneyError(#[error(source)] MoneyError),
+}
+
+impl From<MoneyError> for ReceiptError {
+    fn from(value: MoneyError) -> Self {
+        ReceiptError::MoneyError(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::domain::core::{Currency, ReservationCustomer, ReservationDetail, TaxTable};
+
+    fn issuer() -> Issuer {
+        Issuer {
+            name: "デリヘル".to_owned(),
+            registration_number: "T9234567890123".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn reservation(id: u64) -> Reservation {
+        let start = Utc.with_ymd_and_hms(2023, 11, 1, 10, 0, 0).unwrap();
+        let mut reservation = Reservation::create(
+            id.into(),
+            vec![1.into()],
+            start..start + Duration::hours(1),
+            ReservationCustomer::Registered { id: 1.into() },
+        )
+        .unwrap();
+        reservation
+            .add_detail(
+                ReservationDetail::create(
+                    1.into(),
+                    "60分コース".to_owned(),
+                    1,
+                    Money::new(20000, Currency::JPY),
+                )
+                .unwrap()
+                .with_tax(TaxCategory::Standard, 10, TaxInclusion::Exclusive),
+                &TaxTable::default(),
+            )
+            .unwrap();
+        reservation
+    }
+
+    #[test]
+    fn test_receipt_book() {
+        let now = Utc.with_ymd_and_hms(2023, 11, 2, 0, 0, 0).unwrap();
+        let mut book = ReceiptBook::open(2023.into());
+        // チェックデジットが一致しない登録番号（すべて0の仮の番号など）は発行できない
+        for registration_number in ["1234567890123", "T1234567890123", "T0000000000000"] {
+            assert_eq!(
+                book.issue(
+                    ReceiptKind::Receipt,
+                    &reservation(1),
+                    Issuer {
+                        registration_number: registration_number.to_owned(),
+                        ..issuer()
+                    },
+                    "株式会社テスト".to_owned(),
+                    now,
+                ),
+                Err(ReceiptError::InvalidRegistrationNumber)
+            );
+        }
+
+        let receipt = book
+            .issue(
+                ReceiptKind::Receipt,
+                &reservation(1),
+                issuer(),
+                "株式会社テスト".to_owned(),
+                now,
+            )
+            .unwrap();
+        assert_eq!(receipt.display_number(), "2023-000001");
+        assert_eq!(receipt.total, Money::new(22000, Currency::JPY));
+        assert_eq!(
+            receipt.tax_breakdown[0].tax,
+            Money::new(2000, Currency::JPY)
+        );
+        assert_eq!(
+            book.issue(
+                ReceiptKind::Receipt,
+                &reservation(1),
+                issuer(),
+                "株式会社テスト".to_owned(),
+                now,
+            ),
+            Err(ReceiptError::AlreadyIssued)
+        );
+        let invoice = book
+            .issue(
+                ReceiptKind::Invoice,
+                &reservation(1),
+                issuer(),
+                "株式会社テスト".to_owned(),
+                now,
+            )
+            .unwrap();
+        assert_eq!(invoice.number, 2);
+
+        let reissued = book.reissue(1, now + Duration::days(1)).unwrap();
+        assert!(reissued.is_reissued());
+        assert_eq!(book.reissue(3, now), Err(ReceiptError::ReceiptNotFound));
+
+        let mut replayed = ReceiptBook::default();
+        for event in book.pop_all() {
+            replayed.validate(&event).unwrap();
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, book);
+    }
+}
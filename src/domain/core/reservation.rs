@@ -1,22 +1,48 @@
-use std::ops::Range;
+use std::{cmp::Ordering, ops::Range};
 
 use chrono::{DateTime, Duration, Utc};
 use derive_more::{Deref, Display, Error, From, IntoIterator};
 use serde::{Deserialize, Serialize};
 
-use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id};
+use crate::domain::{
+    ActorId, Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata, Role,
+};
 
-use super::{CustomerId, ProstituteId, Money};
+use super::{
+    Billing, CouponId, Currency, CustomerId, ExtraServiceId, Money, MoneyError, PointLedgerId,
+    Price, PricingError, ProstituteId, Rounding, Service, ServiceId, TaxBreakdown, TaxCategory,
+    TaxError, TaxInclusion, TaxTable,
+};
 
 /// 予約リポジトリ
 #[async_trait::async_trait]
 pub trait ReservationRepository {
     /// IDで予約を検索する
     async fn find_by_id(&self, id: ReservationId) -> Result<Option<Reservation>, DataAccessError>;
+    /// 指定日時時点の予約をIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: ReservationId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Reservation>, DataAccessError>;
+    /// 指定リビジョン時点の予約をIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: ReservationId,
+        revision: u64,
+    ) -> Result<Option<Reservation>, DataAccessError>;
     /// 予約を保存する
-    async fn save(&mut self, entity: &mut Reservation) -> Result<bool, DataAccessError>;
+    async fn save(
+        &mut self,
+        entity: &mut Reservation,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
     /// 予約を削除する
-    async fn delete(&mut self, entity: &mut Reservation) -> Result<bool, DataAccessError>;
+    async fn delete(
+        &mut self,
+        entity: &mut Reservation,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
 }
 
 /// 予約ID
@@ -54,6 +80,28 @@ pub enum ReservationEvent {
         id: ReservationId,
         detail_id: ReservationDetailId,
     },
+    /// 女の子の指名の種類が変更された（指名料の予約詳細は別のイベントで追加・削除する）
+    ReservationDesignated {
+        id: ReservationId,
+        prostitute_id: ProstituteId,
+        designation: Designation,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fee_detail_id: Option<ReservationDetailId>,
+    },
+    /// 支払いを受け取った
+    ReservationPaymentReceived { id: ReservationId, payment: Payment },
+    /// 支払いを返金した
+    ReservationPaymentRefunded {
+        id: ReservationId,
+        payment_id: PaymentId,
+        amount: Money,
+    },
+    /// 予約が完了した（未払いのまま管理者が承認した場合は承認者を記録する）
+    ReservationCompleted {
+        id: ReservationId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        approved_by: Option<ActorId>,
+    },
     /// 予約が削除された
     ReservationDeleted { id: ReservationId },
 }
@@ -70,6 +118,12 @@ pub struct Reservation {
     time: Range<DateTime<Utc>>,
     customer: ReservationCustomer,
     details: Vec<ReservationDetail>,
+    #[serde(default)]
+    designations: Vec<CastDesignation>,
+    #[serde(default)]
+    payments: Vec<Payment>,
+    #[serde(default)]
+    completed: bool,
     #[serde(skip)]
     #[into_iterator]
     events: EventQueue<ReservationEvent>,
@@ -99,15 +153,60 @@ impl Reservation {
         Ok(entity)
     }
 
-    pub fn extend_time(&mut self, duration: Duration) -> Result<(), ReservationError> {
+    /// サービスの延長価格で時間を延長し、延長料金の予約詳細を追加する
+    ///
+    /// 延長料金は`billing`で計算し、税率は予約の開始日時の日付で`tax_table`から取得する。
+    pub fn extend_time(
+        &mut self,
+        duration: Duration,
+        detail_id: ReservationDetailId,
+        service: &Service,
+        billing: &Billing,
+        tax_table: &TaxTable,
+    ) -> Result<&ReservationDetail, ReservationError> {
+        self.validate_time_extended(&duration)?;
+        let price = service
+            .extension_price()
+            .ok_or(ReservationError::NoExtensionPrice)?;
+        let tax_rate = tax_table.rate(price.tax_category(), self.time.start.date_naive())?;
+        let detail = ReservationDetail::create(
+            detail_id,
+            format!("{} 延長{}分", service.name(), duration.num_minutes()),
+            1,
+            price.for_duration(duration, billing)?,
+        )
+        .map_err(ReservationError::ReservationDetailError)?
+        .with_item(ReservationItem::Service(service.id()))
+        .with_tax(price.tax_category(), tax_rate, price.tax_inclusion());
+        self.validate_detail_added(&detail)?;
+        self.lengthen(duration)?;
+        self.push_detail(detail)?;
+        Ok(&self.details[self.details.len() - 1])
+    }
+
+    fn lengthen(&mut self, duration: Duration) -> Result<(), ReservationError> {
         self.validate_time_extended(&duration)?;
         self.time = self.time.start..(self.time.end + duration);
-        self.events
-            .push(ReservationEvent::ReservationTimeExtended { id: self.id, second: duration.num_seconds() });
+        self.events.push(ReservationEvent::ReservationTimeExtended {
+            id: self.id,
+            second: duration.num_seconds(),
+        });
         Ok(())
     }
 
-    pub fn add_detail(&mut self, detail: ReservationDetail) -> Result<(), ReservationError> {
+    /// 予約詳細を追加する
+    ///
+    /// 税率は予約詳細の税区分と予約の開始日時の日付で`tax_table`から取得する。
+    pub fn add_detail(
+        &mut self,
+        detail: ReservationDetail,
+        tax_table: &TaxTable,
+    ) -> Result<(), ReservationError> {
+        let tax_rate = tax_table.rate(detail.tax_category, self.time.start.date_naive())?;
+        self.push_detail(ReservationDetail { tax_rate, ..detail })
+    }
+
+    fn push_detail(&mut self, detail: ReservationDetail) -> Result<(), ReservationError> {
         self.validate_detail_added(&detail)?;
         self.details.push(detail.clone());
         self.events.push(ReservationEvent::ReservationDetailAdded {
@@ -131,6 +230,111 @@ impl Reservation {
         Ok(())
     }
 
+    /// 女の子の指名の種類を変更し、指名料の予約詳細を追加する
+    ///
+    /// 以前の指名料の予約詳細は削除する。指名料は`fees`から取得し、税率は予約の開始日時の日付で`tax_table`から取得する。
+    /// 指名料が設定されていない場合は予約詳細を追加しない。
+    pub fn designate(
+        &mut self,
+        prostitute_id: ProstituteId,
+        designation: Designation,
+        detail_id: ReservationDetailId,
+        fees: &DesignationFees,
+        tax_table: &TaxTable,
+    ) -> Result<(), ReservationError> {
+        let previous = self.designation_of(prostitute_id);
+        if previous.map(|d| d.designation) == Some(designation) {
+            return Ok(());
+        }
+        let detail = match fees.fee(designation) {
+            Some(price) => {
+                let tax_rate =
+                    tax_table.rate(price.tax_category(), self.time.start.date_naive())?;
+                let detail = ReservationDetail::create(
+                    detail_id,
+                    designation.fee_name().to_owned(),
+                    1,
+                    price.amount().clone(),
+                )
+                .map_err(ReservationError::ReservationDetailError)?
+                .with_item(ReservationItem::Designation(designation))
+                .with_tax(price.tax_category(), tax_rate, price.tax_inclusion());
+                self.validate_detail_added(&detail)?;
+                Some(detail)
+            }
+            None => None,
+        };
+        let fee_detail_id = detail.as_ref().map(|d| d.id);
+        self.validate_designated(&prostitute_id, &fee_detail_id)?;
+        if let Some(previous) = previous.and_then(|d| d.fee_detail_id) {
+            if self.details.iter().any(|d| d.id == previous) {
+                self.delete_detail(previous)?;
+            }
+        }
+        if let Some(detail) = detail {
+            self.push_detail(detail)?;
+        }
+        self.set_designation(prostitute_id, designation, fee_detail_id);
+        Ok(())
+    }
+
+    /// 支払いを受け取る（分割して支払うこともできる）
+    pub fn receive_payment(&mut self, payment: Payment) -> Result<(), ReservationError> {
+        self.validate_payment_received(&payment)?;
+        self.payments.push(payment.clone());
+        self.events
+            .push(ReservationEvent::ReservationPaymentReceived {
+                id: self.id,
+                payment,
+            });
+        Ok(())
+    }
+
+    /// 支払いの一部または全部を返金する
+    pub fn refund_payment(
+        &mut self,
+        payment_id: PaymentId,
+        amount: Money,
+    ) -> Result<(), ReservationError> {
+        self.validate_payment_refunded(&payment_id, &amount)?;
+        if let Some(payment) = self.payments.iter_mut().find(|p| p.id == payment_id) {
+            payment.refunded = payment.refunded.checked_add(&amount)?;
+        }
+        self.events
+            .push(ReservationEvent::ReservationPaymentRefunded {
+                id: self.id,
+                payment_id,
+                amount,
+            });
+        Ok(())
+    }
+
+    /// 予約を完了する（未払いがある場合は完了できない）
+    pub fn complete(&mut self) -> Result<(), ReservationError> {
+        self.validate_completed(None)?;
+        self.completed = true;
+        self.events.push(ReservationEvent::ReservationCompleted {
+            id: self.id,
+            approved_by: None,
+        });
+        Ok(())
+    }
+
+    /// 未払いがあっても管理者の承認で予約を完了する
+    pub fn complete_with_override(
+        &mut self,
+        approver: ActorId,
+        role: Role,
+    ) -> Result<(), ReservationError> {
+        self.validate_completed(Some(role))?;
+        self.completed = true;
+        self.events.push(ReservationEvent::ReservationCompleted {
+            id: self.id,
+            approved_by: Some(approver),
+        });
+        Ok(())
+    }
+
     pub fn prostitute_ids(&self) -> &[ProstituteId] {
         &self.prostitute_ids
     }
@@ -147,6 +351,103 @@ impl Reservation {
         &self.details
     }
 
+    pub fn designations(&self) -> &[CastDesignation] {
+        &self.designations
+    }
+
+    /// 女の子の指名の種類（指名されていない場合はフリー）
+    pub fn designation(&self, prostitute_id: ProstituteId) -> Designation {
+        self.designation_of(prostitute_id)
+            .map_or(Designation::Free, |d| d.designation)
+    }
+
+    /// 指名料の予約詳細を指名された女の子を取得する
+    pub fn fee_owner(&self, detail_id: ReservationDetailId) -> Option<ProstituteId> {
+        self.designations
+            .iter()
+            .find(|d| d.fee_detail_id == Some(detail_id))
+            .map(|d| d.prostitute_id)
+    }
+
+    fn designation_of(&self, prostitute_id: ProstituteId) -> Option<&CastDesignation> {
+        self.designations
+            .iter()
+            .find(|d| d.prostitute_id == prostitute_id)
+    }
+
+    fn set_designation(
+        &mut self,
+        prostitute_id: ProstituteId,
+        designation: Designation,
+        fee_detail_id: Option<ReservationDetailId>,
+    ) {
+        self.designations
+            .retain(|d| d.prostitute_id != prostitute_id);
+        self.designations.push(CastDesignation {
+            prostitute_id,
+            designation,
+            fee_detail_id,
+        });
+        self.events.push(ReservationEvent::ReservationDesignated {
+            id: self.id,
+            prostitute_id,
+            designation,
+            fee_detail_id,
+        });
+    }
+
+    /// 予約の通貨（予約詳細も支払いもない場合は`None`）
+    pub fn currency(&self) -> Option<Currency> {
+        self.details
+            .first()
+            .map(|d| d.price.currency())
+            .or_else(|| self.payments.first().map(|p| p.amount.currency()))
+    }
+
+    /// 予約詳細の税込の合計金額
+    pub fn total(&self) -> Result<Money, MoneyError> {
+        self.tax_breakdown()?
+            .iter()
+            .try_fold(self.zero(), |sum, b| sum.checked_add(&b.total()?))
+    }
+
+    /// 予約詳細の税率ごとの内訳（消費税の端数は税率ごとに1回だけ切り捨てる）
+    pub fn tax_breakdown(&self) -> Result<Vec<TaxBreakdown>, MoneyError> {
+        let amounts = self
+            .details
+            .iter()
+            .map(|d| {
+                let subtotal = d.subtotal()?;
+                Ok((d.tax_category(), d.tax_rate(), d.tax_inclusion(), subtotal))
+            })
+            .collect::<Result<Vec<_>, MoneyError>>()?;
+        TaxBreakdown::calculate(amounts, Rounding::Down)
+    }
+
+    pub fn payments(&self) -> &[Payment] {
+        &self.payments
+    }
+
+    /// 返金を差し引いた支払済みの金額
+    pub fn paid(&self) -> Result<Money, MoneyError> {
+        self.payments
+            .iter()
+            .try_fold(self.zero(), |sum, p| sum.checked_add(&p.net()?))
+    }
+
+    /// 未払いの金額（予約詳細の合計 - 支払済みの金額、負数は払い過ぎ）
+    pub fn balance_due(&self) -> Result<Money, MoneyError> {
+        self.total()?.checked_sub(&self.paid()?)
+    }
+
+    fn zero(&self) -> Money {
+        Money::zero(self.currency().unwrap_or_default())
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.completed
+    }
+
     fn validate_id(&self, id: &ReservationId) -> Result<(), ReservationError> {
         if self.id != *id {
             return Err(ReservationError::MismatchedId);
@@ -173,10 +474,14 @@ impl Reservation {
     }
 
     fn validate_detail_added(&self, detail: &ReservationDetail) -> Result<(), ReservationError> {
+        // 完了後に料金を追加すると支払済みの予約に残高が生じるため追加できない
+        if self.completed {
+            return Err(ReservationError::AlreadyCompleted);
+        }
         if self.details.iter().any(|d| d.id == detail.id) {
             return Err(ReservationError::DuplicateDetail);
         }
-        Ok(())
+        self.validate_currency(&detail.price)
     }
 
     fn validate_detail_deleted(
@@ -189,6 +494,70 @@ impl Reservation {
         Ok(())
     }
 
+    fn validate_designated(
+        &self,
+        prostitute_id: &ProstituteId,
+        fee_detail_id: &Option<ReservationDetailId>,
+    ) -> Result<(), ReservationError> {
+        if !self.prostitute_ids.contains(prostitute_id) {
+            return Err(ReservationError::ProstituteNotAssigned);
+        }
+        match fee_detail_id {
+            Some(detail_id) if self.fee_owner(*detail_id).is_some() => {
+                Err(ReservationError::DuplicateDetail)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_payment_received(&self, payment: &Payment) -> Result<(), ReservationError> {
+        if self.payments.iter().any(|p| p.id == payment.id) {
+            return Err(ReservationError::DuplicatePayment);
+        }
+        self.validate_currency(&payment.amount)
+    }
+
+    fn validate_payment_refunded(
+        &self,
+        payment_id: &PaymentId,
+        amount: &Money,
+    ) -> Result<(), ReservationError> {
+        let payment = self
+            .payments
+            .iter()
+            .find(|p| p.id == *payment_id)
+            .ok_or(ReservationError::PaymentNotFound)?;
+        if !amount.is_positive() {
+            return Err(ReservationError::InvalidRefund);
+        }
+        match amount.partial_cmp(&payment.net()?) {
+            None => Err(ReservationError::CurrencyMismatch),
+            Some(Ordering::Greater) => Err(ReservationError::RefundExceedsPayment),
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn validate_completed(&self, override_role: Option<Role>) -> Result<(), ReservationError> {
+        if self.completed {
+            return Err(ReservationError::AlreadyCompleted);
+        }
+        match override_role {
+            Some(Role::Manager) => Ok(()),
+            Some(_) => Err(ReservationError::OverrideNotPermitted),
+            None if self.balance_due()?.is_positive() => Err(ReservationError::BalanceOutstanding),
+            None => Ok(()),
+        }
+    }
+
+    fn validate_currency(&self, money: &Money) -> Result<(), ReservationError> {
+        match self.currency() {
+            Some(currency) if currency != money.currency() => {
+                Err(ReservationError::CurrencyMismatch)
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn validate_prostitute_ids(prostitute_ids: &[ProstituteId]) -> Result<(), ReservationError> {
         if prostitute_ids.is_empty() {
             return Err(ReservationError::NoProstitutes);
@@ -256,6 +625,31 @@ impl Aggregation for Reservation {
                 self.validate_id(id)?;
                 self.validate_detail_deleted(detail_id)?;
             }
+            ReservationEvent::ReservationDesignated {
+                id,
+                prostitute_id,
+                fee_detail_id,
+                ..
+            } => {
+                self.validate_id(id)?;
+                self.validate_designated(prostitute_id, fee_detail_id)?;
+            }
+            ReservationEvent::ReservationPaymentReceived { id, payment } => {
+                self.validate_id(id)?;
+                self.validate_payment_received(payment)?;
+            }
+            ReservationEvent::ReservationPaymentRefunded {
+                id,
+                payment_id,
+                amount,
+            } => {
+                self.validate_id(id)?;
+                self.validate_payment_refunded(payment_id, amount)?;
+            }
+            ReservationEvent::ReservationCompleted { id, approved_by } => {
+                self.validate_id(id)?;
+                self.validate_completed(approved_by.map(|_| Role::Manager))?;
+            }
             ReservationEvent::ReservationDeleted { id } => {
                 self.validate_id(id)?;
             }
@@ -279,12 +673,12 @@ impl Aggregation for Reservation {
             }
             ReservationEvent::ReservationTimeExtended { id, second } => {
                 if self.id == id {
-                    if let Err(_) = self.extend_time(Duration::seconds(second)) {};
+                    if let Err(_) = self.lengthen(Duration::seconds(second)) {};
                 }
             }
             ReservationEvent::ReservationDetailAdded { id, detail } => {
                 if self.id == id {
-                    if let Err(_) = self.add_detail(detail) {};
+                    if let Err(_) = self.push_detail(detail) {};
                 }
             }
             ReservationEvent::ReservationDetailDeleted { id, detail_id } => {
@@ -292,6 +686,44 @@ impl Aggregation for Reservation {
                     if let Err(_) = self.delete_detail(detail_id) {};
                 }
             }
+            ReservationEvent::ReservationDesignated {
+                id,
+                prostitute_id,
+                designation,
+                fee_detail_id,
+            } => {
+                // 指名料の予約詳細は先に記録されたイベントで追加・削除されている
+                if self.id == id
+                    && self
+                        .validate_designated(&prostitute_id, &fee_detail_id)
+                        .is_ok()
+                {
+                    self.set_designation(prostitute_id, designation, fee_detail_id);
+                }
+            }
+            ReservationEvent::ReservationPaymentReceived { id, payment } => {
+                if self.id == id {
+                    if let Err(_e) = self.receive_payment(payment) {};
+                }
+            }
+            ReservationEvent::ReservationPaymentRefunded {
+                id,
+                payment_id,
+                amount,
+            } => {
+                if self.id == id {
+                    if let Err(_e) = self.refund_payment(payment_id, amount) {};
+                }
+            }
+            ReservationEvent::ReservationCompleted { id, approved_by } => {
+                if self.id == id {
+                    let result = match approved_by {
+                        Some(approver) => self.complete_with_override(approver, Role::Manager),
+                        None => self.complete(),
+                    };
+                    if let Err(_e) = result {};
+                }
+            }
             ReservationEvent::ReservationDeleted { .. } => {}
         }
     }
@@ -312,6 +744,9 @@ impl PartialEq for Reservation {
             && self.time == other.time
             && self.customer == other.customer
             && self.details == other.details
+            && self.designations == other.designations
+            && self.payments == other.payments
+            && self.completed == other.completed
     }
 }
 
@@ -326,6 +761,9 @@ pub enum ReservationError {
     /// 女の子が指定されていません
     #[display(fmt = "No prostitute_ids are specified")]
     NoProstitutes,
+    /// 予約を担当していない女の子です
+    #[display(fmt = "Prostitute is not assigned to the reservation")]
+    ProstituteNotAssigned,
     /// 時間が不正です
     #[display(fmt = "Invalid time")]
     InvalidTime,
@@ -350,6 +788,60 @@ pub enum ReservationError {
     /// 予約詳細のエラー
     #[display(fmt = "Reservation detail error: {}", _0)]
     ReservationDetailError(#[error(source)] ReservationDetailError),
+    /// 支払いが重複しています
+    #[display(fmt = "Duplicate payment")]
+    DuplicatePayment,
+    /// 支払いが見つかりません
+    #[display(fmt = "Payment not found")]
+    PaymentNotFound,
+    /// 返金額が不正です
+    #[display(fmt = "Refund amount must be positive")]
+    InvalidRefund,
+    /// 返金額が支払額を超えています
+    #[display(fmt = "Refund exceeds the payment")]
+    RefundExceedsPayment,
+    /// 通貨が一致しません
+    #[display(fmt = "Currency does not match")]
+    CurrencyMismatch,
+    /// 未払いがあります
+    #[display(fmt = "Balance is outstanding")]
+    BalanceOutstanding,
+    /// 管理者以外は未払いのまま完了できません
+    #[display(fmt = "Only managers can override the outstanding balance")]
+    OverrideNotPermitted,
+    /// 既に完了しています
+    #[display(fmt = "Reservation is already completed")]
+    AlreadyCompleted,
+    /// 延長価格が設定されていません
+    #[display(fmt = "Service has no extension price")]
+    NoExtensionPrice,
+    /// 金額の計算エラー
+    #[display(fmt = "Money error: {}", _0)]
+    MoneyError(#[error(source)] MoneyError),
+    /// 価格の計算エラー
+    #[display(fmt = "Pricing error: {}", _0)]
+    PricingError(#[error(source)] PricingError),
+    /// 税率のエラー
+    #[display(fmt = "Tax error: {}", _0)]
+    TaxError(#[error(source)] TaxError),
+}
+
+impl From<MoneyError> for ReservationError {
+    fn from(value: MoneyError) -> Self {
+        ReservationError::MoneyError(value)
+    }
+}
+
+impl From<PricingError> for ReservationError {
+    fn from(value: PricingError) -> Self {
+        ReservationError::PricingError(value)
+    }
+}
+
+impl From<TaxError> for ReservationError {
+    fn from(value: TaxError) -> Self {
+        ReservationError::TaxError(value)
+    }
 }
 
 /// 予約したお客様
@@ -364,6 +856,59 @@ pub enum ReservationCustomer {
     Unregistered { name: String, phone: String },
 }
 
+/// 指名の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Designation {
+    /// フリー（指名なし）
+    #[default]
+    Free,
+    /// 写真指名
+    Photo,
+    /// 本指名
+    Regular,
+}
+
+impl Designation {
+    /// 指名料の予約詳細の名前
+    pub fn fee_name(&self) -> &'static str {
+        match self {
+            Designation::Free => "フリー",
+            Designation::Photo => "写真指名料",
+            Designation::Regular => "本指名料",
+        }
+    }
+}
+
+/// 女の子ごとの指名
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CastDesignation {
+    pub prostitute_id: ProstituteId,
+    pub designation: Designation,
+    /// 指名料の予約詳細（指名料がない場合は`None`）
+    pub fee_detail_id: Option<ReservationDetailId>,
+}
+
+/// 指名料
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DesignationFees {
+    /// 写真指名料
+    pub photo: Option<Price>,
+    /// 本指名料
+    pub regular: Option<Price>,
+}
+
+impl DesignationFees {
+    /// 指名の種類の指名料を取得する（フリーは常に`None`）
+    pub fn fee(&self, designation: Designation) -> Option<&Price> {
+        match designation {
+            Designation::Free => None,
+            Designation::Photo => self.photo.as_ref(),
+            Designation::Regular => self.regular.as_ref(),
+        }
+    }
+}
+
 /// 予約詳細ID
 #[derive(
     Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display, From, Deref, Default,
@@ -381,6 +926,29 @@ pub struct ReservationDetail {
     name: String,
     quantity: u32,
     price: Money,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    item: Option<ReservationItem>,
+    #[serde(default)]
+    tax_category: TaxCategory,
+    #[serde(default)]
+    tax_rate: u8,
+    #[serde(default)]
+    tax_inclusion: TaxInclusion,
+}
+
+/// 予約詳細の対象
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReservationItem {
+    /// サービスコース
+    Service(ServiceId),
+    /// オプションサービス
+    ExtraService(ExtraServiceId),
+    /// ポイント利用
+    Points(PointLedgerId),
+    /// 指名料
+    Designation(Designation),
+    /// クーポン割引
+    Coupon(CouponId),
 }
 
 impl ReservationDetail {
@@ -396,17 +964,69 @@ impl ReservationDetail {
             name,
             quantity,
             price,
+            ..Default::default()
         })
     }
 
+    /// 予約詳細の対象を指定する
+    pub fn with_item(self, item: ReservationItem) -> Self {
+        Self {
+            item: Some(item),
+            ..self
+        }
+    }
+
+    /// 予約詳細の税区分、取引日に適用した税率、税込・税抜を指定する
+    ///
+    /// 税率は`TaxTable::rate`で取得する。指定しない場合は標準税率・税込として扱う。
+    /// `Reservation::add_detail`で追加する場合、税率は予約の日付で税率表から取得し直す。
+    pub fn with_tax(
+        self,
+        tax_category: TaxCategory,
+        tax_rate: u8,
+        tax_inclusion: TaxInclusion,
+    ) -> Self {
+        Self {
+            tax_category,
+            tax_rate,
+            tax_inclusion,
+            ..self
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    pub fn quantity(&self) -> u32 {
+        self.quantity
+    }
+
     pub fn price(&self) -> &Money {
         &self.price
     }
 
+    pub fn item(&self) -> Option<ReservationItem> {
+        self.item
+    }
+
+    pub fn tax_category(&self) -> TaxCategory {
+        self.tax_category
+    }
+
+    pub fn tax_rate(&self) -> u8 {
+        self.tax_rate
+    }
+
+    pub fn tax_inclusion(&self) -> TaxInclusion {
+        self.tax_inclusion
+    }
+
+    /// 単価に数量を掛けた金額
+    pub fn subtotal(&self) -> Result<Money, MoneyError> {
+        self.price.checked_mul(self.quantity as i64)
+    }
+
     fn validate_created(
         name: &str,
         quantity: u32,
@@ -452,3 +1072,346 @@ pub enum ReservationDetailError {
     #[display(fmt = "Invalid quantity")]
     InvalidQuantity,
 }
+
+/// 支払いID
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display, From, Deref, Default,
+)]
+pub struct PaymentId(u64);
+
+impl Id for PaymentId {
+    type Inner = u64;
+}
+
+/// 支払方法
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum PaymentMethod {
+    /// 現金
+    #[default]
+    Cash,
+    /// クレジットカード
+    CreditCard,
+    /// QRコード決済
+    QrPayment,
+    /// ポイント
+    Points,
+    /// 金券
+    Voucher,
+}
+
+/// 支払いエンティティ
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Payment {
+    id: PaymentId,
+    method: PaymentMethod,
+    amount: Money,
+    /// カードの承認番号や金券の番号など
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+    #[serde(default)]
+    refunded: Money,
+}
+
+impl Payment {
+    pub fn create(
+        id: PaymentId,
+        method: PaymentMethod,
+        amount: Money,
+        reference: Option<String>,
+    ) -> Result<Self, PaymentError> {
+        Self::validate_created(&amount)?;
+        Ok(Payment {
+            id,
+            method,
+            refunded: Money::zero(amount.currency()),
+            amount,
+            reference,
+        })
+    }
+
+    pub fn method(&self) -> PaymentMethod {
+        self.method
+    }
+
+    pub fn amount(&self) -> &Money {
+        &self.amount
+    }
+
+    pub fn reference(&self) -> Option<&str> {
+        self.reference.as_deref()
+    }
+
+    pub fn refunded(&self) -> &Money {
+        &self.refunded
+    }
+
+    /// 返金を差し引いた金額
+    pub fn net(&self) -> Result<Money, MoneyError> {
+        self.amount.checked_sub(&self.refunded)
+    }
+
+    fn validate_created(amount: &Money) -> Result<(), PaymentError> {
+        if !amount.is_positive() {
+            return Err(PaymentError::InvalidAmount);
+        }
+        Ok(())
+    }
+}
+
+impl Entity for Payment {
+    type Id = PaymentId;
+
+    const ENTITY_NAME: &'static str = "payment";
+
+    fn id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+/// 支払いエラー
+#[derive(Error, Display, Debug)]
+pub enum PaymentError {
+    /// 金額が不正です
+    #[display(fmt = "Payment amount must be positive")]
+    InvalidAmount,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::domain::core::{Price, PriceUnit};
+
+    fn yen(amount: i64) -> Money {
+        Money::new(amount, Currency::JPY)
+    }
+
+    fn reservation() -> Reservation {
+        let start = Utc.with_ymd_and_hms(2023, 4, 1, 10, 0, 0).unwrap();
+        let mut reservation = Reservation::create(
+            1.into(),
+            vec![1.into()],
+            start..start + Duration::hours(1),
+            ReservationCustomer::Registered { id: 1.into() },
+        )
+        .unwrap();
+        reservation
+            .add_detail(
+                ReservationDetail::create(1.into(), "60分コース".to_owned(), 1, yen(20000))
+                    .unwrap(),
+                &TaxTable::default(),
+            )
+            .unwrap();
+        reservation
+    }
+
+    #[test]
+    fn test_payments() {
+        let mut reservation = reservation();
+        let card = Payment::create(1.into(), PaymentMethod::CreditCard, yen(15000), None).unwrap();
+        let cash = Payment::create(2.into(), PaymentMethod::Cash, yen(5000), None).unwrap();
+        reservation.receive_payment(card.clone()).unwrap();
+        assert_eq!(reservation.balance_due().unwrap(), yen(5000));
+        assert!(matches!(
+            reservation.complete(),
+            Err(ReservationError::BalanceOutstanding)
+        ));
+        assert!(matches!(
+            reservation.receive_payment(card),
+            Err(ReservationError::DuplicatePayment)
+        ));
+        assert!(matches!(
+            reservation.receive_payment(
+                Payment::create(
+                    3.into(),
+                    PaymentMethod::Cash,
+                    Money::new(500, Currency::USD),
+                    None
+                )
+                .unwrap()
+            ),
+            Err(ReservationError::CurrencyMismatch)
+        ));
+        reservation.receive_payment(cash).unwrap();
+        assert_eq!(reservation.balance_due().unwrap(), yen(0));
+
+        assert!(matches!(
+            reservation.refund_payment(2.into(), yen(6000)),
+            Err(ReservationError::RefundExceedsPayment)
+        ));
+        reservation.refund_payment(2.into(), yen(2000)).unwrap();
+        assert_eq!(reservation.paid().unwrap(), yen(18000));
+        assert_eq!(reservation.balance_due().unwrap(), yen(2000));
+        assert!(matches!(
+            reservation.complete_with_override(2.into(), Role::Staff),
+            Err(ReservationError::OverrideNotPermitted)
+        ));
+        reservation
+            .complete_with_override(2.into(), Role::Manager)
+            .unwrap();
+        assert!(reservation.is_completed());
+        assert!(matches!(
+            reservation.complete(),
+            Err(ReservationError::AlreadyCompleted)
+        ));
+
+        let mut replayed = Reservation::default();
+        for event in reservation.pop_all() {
+            replayed.validate(&event).unwrap();
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, reservation);
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut reservation = reservation();
+        reservation
+            .receive_payment(
+                Payment::create(1.into(), PaymentMethod::QrPayment, yen(20000), None).unwrap(),
+            )
+            .unwrap();
+        reservation.complete().unwrap();
+        assert_eq!(
+            reservation.pop_all().last(),
+            Some(&ReservationEvent::ReservationCompleted {
+                id: 1.into(),
+                approved_by: None
+            })
+        );
+        assert!(matches!(
+            reservation.add_detail(
+                ReservationDetail::create(2.into(), "ドリンク".to_owned(), 1, yen(1000)).unwrap(),
+                &TaxTable::default(),
+            ),
+            Err(ReservationError::AlreadyCompleted)
+        ));
+        assert_eq!(reservation.details().len(), 1);
+    }
+
+    #[test]
+    fn test_tax_breakdown() {
+        let mut reservation = reservation();
+        for (id, price) in [(2, 1005), (3, 1005)] {
+            reservation
+                .add_detail(
+                    ReservationDetail::create(id.into(), "ドリンク".to_owned(), 1, yen(price))
+                        .unwrap()
+                        .with_tax(TaxCategory::Reduced, 8, TaxInclusion::Exclusive),
+                    &TaxTable::default(),
+                )
+                .unwrap();
+        }
+        let breakdown = reservation.tax_breakdown().unwrap();
+        assert_eq!(
+            breakdown
+                .iter()
+                .map(|b| (b.rate, b.taxable.amount(), b.tax.amount()))
+                .collect::<Vec<_>>(),
+            vec![(10, 18182, 1818), (8, 2010, 160)]
+        );
+        assert_eq!(reservation.total().unwrap(), yen(22170));
+    }
+
+    #[test]
+    fn test_extend_time() {
+        let mut reservation = reservation();
+        let mut service = Service::create(
+            1.into(),
+            "スタンダード".to_owned(),
+            String::new(),
+            Price::new(yen(20000), PriceUnit::OneTime),
+        )
+        .unwrap();
+        let billing = Billing::default();
+        let tax_table = TaxTable::default();
+        assert!(matches!(
+            reservation.extend_time(
+                Duration::minutes(40),
+                2.into(),
+                &service,
+                &billing,
+                &tax_table
+            ),
+            Err(ReservationError::NoExtensionPrice)
+        ));
+
+        service
+            .change_extension_price(Some(
+                Price::new(yen(12000), PriceUnit::Hourly)
+                    .with_tax(TaxCategory::Standard, TaxInclusion::Inclusive),
+            ))
+            .unwrap();
+        let detail = reservation
+            .extend_time(
+                Duration::minutes(40),
+                2.into(),
+                &service,
+                &billing,
+                &tax_table,
+            )
+            .unwrap();
+        assert_eq!(detail.name(), "スタンダード 延長40分");
+        assert_eq!(detail.price(), &yen(12000));
+        assert_eq!(detail.tax_rate(), 10);
+        assert_eq!(detail.item(), Some(ReservationItem::Service(1.into())));
+        assert_eq!(
+            reservation.time().end - reservation.time().start,
+            Duration::minutes(100)
+        );
+        assert_eq!(reservation.total().unwrap(), yen(32000));
+
+        let events = reservation.pop_all();
+        let mut replayed = Reservation::default();
+        for event in events {
+            replayed.validate(&event).unwrap();
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, reservation);
+    }
+
+    #[test]
+    fn test_designate() {
+        let mut reservation = reservation();
+        let fees = DesignationFees {
+            photo: Some(Price::new(yen(1000), PriceUnit::OneTime)),
+            regular: Some(Price::new(yen(3000), PriceUnit::OneTime)),
+        };
+        let tax_table = TaxTable::default();
+        assert_eq!(reservation.designation(1.into()), Designation::Free);
+        assert!(matches!(
+            reservation.designate(2.into(), Designation::Photo, 2.into(), &fees, &tax_table),
+            Err(ReservationError::ProstituteNotAssigned)
+        ));
+
+        reservation
+            .designate(1.into(), Designation::Photo, 2.into(), &fees, &tax_table)
+            .unwrap();
+        assert_eq!(reservation.fee_owner(2.into()), Some(1.into()));
+        assert_eq!(reservation.total().unwrap(), yen(21000));
+
+        // 指名の種類を変更すると以前の指名料は削除する
+        reservation
+            .designate(1.into(), Designation::Regular, 3.into(), &fees, &tax_table)
+            .unwrap();
+        assert_eq!(reservation.designation(1.into()), Designation::Regular);
+        assert_eq!(reservation.fee_owner(2.into()), None);
+        let fee = &reservation.details()[1];
+        assert_eq!(fee.name(), "本指名料");
+        assert_eq!(
+            fee.item(),
+            Some(ReservationItem::Designation(Designation::Regular))
+        );
+        assert_eq!(fee.tax_rate(), 10);
+        assert_eq!(reservation.total().unwrap(), yen(23000));
+
+        let events = reservation.pop_all();
+        let mut replayed = Reservation::default();
+        for event in events {
+            replayed.validate(&event).unwrap();
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, reservation);
+    }
+}
@@ -6,7 +6,7 @@ use chrono::{DateTime, Utc};
 use derive_more::{Deref, Display, Error, From, IntoIterator};
 use serde::{Deserialize, Serialize};
 
-use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id};
+use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata};
 
 use super::ProstituteId;
 
@@ -15,10 +15,30 @@ use super::ProstituteId;
 pub trait ScheduleRepository {
     /// IDからスケジュールを取得する
     async fn find_by_id(&self, id: ScheduleId) -> Result<Option<Schedule>, DataAccessError>;
+    /// 指定日時時点のスケジュールをIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: ScheduleId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Schedule>, DataAccessError>;
+    /// 指定リビジョン時点のスケジュールをIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: ScheduleId,
+        revision: u64,
+    ) -> Result<Option<Schedule>, DataAccessError>;
     /// スケジュールを保存する
-    async fn save(&mut self, entity: &mut Schedule) -> Result<bool, DataAccessError>;
+    async fn save(
+        &mut self,
+        entity: &mut Schedule,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
     /// スケジュールを削除する
-    async fn delete(&mut self, entity: &mut Schedule) -> Result<bool, DataAccessError>;
+    async fn delete(
+        &mut self,
+        entity: &mut Schedule,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
 }
 
 /// スケジュールID
@@ -138,6 +158,14 @@ impl Schedule {
         self.shifts.iter().find(|s| s.id == *shift_id)
     }
 
+    pub fn prostitute_id(&self) -> ProstituteId {
+        self.prostitute_id
+    }
+
+    pub fn shifts(&self) -> &[Shift] {
+        &self.shifts
+    }
+
     fn validate_id(&self, id: &ScheduleId) -> Result<(), ScheduleError> {
         match self.id == *id {
             true => Ok(()),
@@ -327,7 +355,8 @@ impl Shift {
         status: ShiftStatus,
     ) -> Result<Self, ShiftError> {
         let entity = Shift { id, time, status };
-        entity.validate(&entity)?;
+        // 作成時はステータスの遷移がないため、時間だけを検証する（同じステータスへの遷移は常に無効になる）
+        entity.validate_time(&entity.time)?;
         Ok(entity)
     }
 
@@ -355,11 +384,6 @@ impl Shift {
         self.time.end - self.time.start
     }
 
-    fn validate(&self, other: &Self) -> Result<(), ShiftError> {
-        self.validate_time(&other.time)?;
-        self.validate_status(&other.status)
-    }
-
     fn validate_time(&self, time: &Range<DateTime<Utc>>) -> Result<(), ShiftError> {
         if time.start > time.end {
             Err(ShiftError::InvalidDuration)
@@ -417,3 +441,32 @@ pub enum ShiftStatus {
     /// キャンセル
     Canceled,
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    #[test]
+    fn test_shift_create() {
+        let start = Utc.with_ymd_and_hms(2023, 4, 1, 10, 0, 0).unwrap();
+        for status in [
+            ShiftStatus::Editing,
+            ShiftStatus::Reviewing,
+            ShiftStatus::Confirmed,
+            ShiftStatus::Canceled,
+        ] {
+            let shift = Shift::create(1.into(), start..start + Duration::hours(8), status).unwrap();
+            assert_eq!(shift.status, status);
+        }
+        assert!(matches!(
+            Shift::create(
+                1.into(),
+                start..start - Duration::hours(1),
+                ShiftStatus::Editing
+            ),
+            Err(ShiftError::InvalidDuration)
+        ));
+    }
+}
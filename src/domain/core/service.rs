@@ -84,6 +84,48 @@ pub struct Service {
 }
 
 impl Service {
+    pub fn create(
+        id: ServiceId,
+        name: String,
+        description: String,
+        default_price: Price,
+    ) -> Result<Self, ServiceError> {
+        let entity = Service {
+            id,
+            name,
+            description,
+            default_price,
+            ..Default::default()
+        };
+        entity.validate_name(&entity.name)?;
+        entity.validate_default_price(&entity.default_price)?;
+        Ok(entity)
+    }
+
+    /// 延長価格を変更する（`None`の場合は延長できない）
+    pub fn change_extension_price(
+        &mut self,
+        extension_price: Option<Price>,
+    ) -> Result<(), ServiceError> {
+        if let Some(price) = &extension_price {
+            self.validate_price_is_negative(price)?;
+        }
+        self.extension_price = extension_price;
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn default_price(&self) -> &Price {
+        &self.default_price
+    }
+
+    pub fn extension_price(&self) -> Option<&Price> {
+        self.extension_price.as_ref()
+    }
+
     pub fn calculate_price(&self, date_time: DateTime<Utc>) -> Money {
         todo!("calculate_price")
     }
@@ -133,6 +175,44 @@ impl Entity for Service {
     }
 }
 
+/// 設定で定義するサービス
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceSettings {
+    pub id: ServiceId,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub default_price: Price,
+    /// 延長価格（未指定の場合は延長できない）
+    #[serde(default)]
+    pub extension_price: Option<Price>,
+}
+
+/// サービスの一覧
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ServiceCatalog(Vec<ServiceSettings>);
+
+impl ServiceCatalog {
+    /// IDでサービスを取得する（見つからない場合は`None`）
+    pub fn find_by_id(&self, id: ServiceId) -> Result<Option<Service>, ServiceError> {
+        self.0
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| {
+                let mut service = Service::create(
+                    s.id,
+                    s.name.clone(),
+                    s.description.clone(),
+                    s.default_price.clone(),
+                )?;
+                service.change_extension_price(s.extension_price.clone())?;
+                Ok(service)
+            })
+            .transpose()
+    }
+}
+
 /// サービスエラー
 #[derive(Error, Display, Debug)]
 pub enum ServiceError {
@@ -0,0 +1,232 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use derive_more::{Deref, Display, Error, From, IntoIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Aggregation, DataAccessError, Entity, Event, EventQueue, Id, Metadata};
+
+/// タグリポジトリ
+#[async_trait]
+pub trait TagRepository {
+    /// タグをIDで検索する
+    async fn find_by_id(&self, id: TagId) -> Result<Option<Tag>, DataAccessError>;
+    /// 指定日時時点のタグをIDで検索する
+    async fn find_by_id_at(
+        &self,
+        id: TagId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Tag>, DataAccessError>;
+    /// 指定リビジョン時点のタグをIDで検索する
+    async fn find_by_id_at_revision(
+        &self,
+        id: TagId,
+        revision: u64,
+    ) -> Result<Option<Tag>, DataAccessError>;
+    /// タグを保存する
+    async fn save(
+        &mut self,
+        entity: &mut Tag,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
+    /// タグを削除する
+    async fn delete(
+        &mut self,
+        entity: &mut Tag,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError>;
+}
+
+/// タグID
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display, From, Deref, Default, Hash,
+)]
+pub struct TagId(u64);
+
+impl Id for TagId {
+    type Inner = u64;
+}
+
+/// タグの分類
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TagCategory {
+    /// 趣味
+    #[default]
+    Hobby,
+    /// タイプ（清楚系、ギャル系など）
+    Type,
+    /// 対応できるオプションサービス
+    ExtraService,
+}
+
+/// タグイベント
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TagEvent {
+    /// タグが作成された
+    TagCreated {
+        id: TagId,
+        category: TagCategory,
+        name: String,
+    },
+    /// タグの名前が変更された
+    TagRenamed { id: TagId, name: String },
+    /// タグが削除された
+    TagDeleted { id: TagId },
+}
+
+impl Event for TagEvent {
+    type Id = TagId;
+}
+
+/// タグ
+///
+/// 女の子のプロフィールに付ける語彙を管理する。女の子にはタグのIDを付けるため、名前を変更しても付け直す必要はない。
+#[derive(Clone, Default, Debug, IntoIterator, Serialize, Deserialize)]
+pub struct Tag {
+    id: TagId,
+    /// 分類
+    category: TagCategory,
+    /// 名前
+    name: String,
+    #[serde(skip)]
+    #[into_iterator]
+    events: EventQueue<TagEvent>,
+}
+
+impl Tag {
+    pub fn create(id: TagId, category: TagCategory, name: String) -> Result<Self, TagError> {
+        Self::validate_name(&name)?;
+        let mut entity = Tag {
+            id,
+            category,
+            name: name.clone(),
+            ..Default::default()
+        };
+        entity
+            .events
+            .push(TagEvent::TagCreated { id, category, name });
+        Ok(entity)
+    }
+
+    pub fn rename(&mut self, name: String) -> Result<(), TagError> {
+        Self::validate_name(&name)?;
+        self.name = name.clone();
+        self.events.push(TagEvent::TagRenamed { id: self.id, name });
+        Ok(())
+    }
+
+    pub fn category(&self) -> TagCategory {
+        self.category
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validate_id(&self, id: &TagId) -> Result<(), TagError> {
+        match self.id == *id {
+            true => Ok(()),
+            false => Err(TagError::MismatchedId),
+        }
+    }
+
+    fn validate_name(name: &str) -> Result<(), TagError> {
+        match name.trim().is_empty() {
+            true => Err(TagError::NameIsBlank),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Entity for Tag {
+    type Id = TagId;
+
+    const ENTITY_NAME: &'static str = "tag";
+
+    fn id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+impl Aggregation for Tag {
+    type Event = TagEvent;
+    type Error = TagError;
+
+    fn validate(&self, event: &Self::Event) -> Result<(), Self::Error> {
+        match event {
+            TagEvent::TagCreated { name, .. } => Self::validate_name(name),
+            TagEvent::TagRenamed { id, name } => {
+                self.validate_id(id)?;
+                Self::validate_name(name)
+            }
+            TagEvent::TagDeleted { id } => self.validate_id(id),
+        }
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        match event {
+            TagEvent::TagCreated { id, category, name } => {
+                if self.id != id {
+                    if let Ok(entity) = Self::create(id, category, name) {
+                        *self = entity;
+                    }
+                }
+            }
+            TagEvent::TagRenamed { id, name } => {
+                if self.id == id {
+                    if let Err(_e) = self.rename(name) {}
+                }
+            }
+            TagEvent::TagDeleted { .. } => {}
+        }
+    }
+
+    fn events(&self) -> &EventQueue<Self::Event> {
+        &self.events
+    }
+
+    fn events_mut(&mut self) -> &mut EventQueue<Self::Event> {
+        &mut self.events
+    }
+}
+
+impl PartialEq for Tag {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.category == other.category && self.name == other.name
+    }
+}
+
+impl Eq for Tag {}
+
+/// タグエラー
+#[derive(Error, Display, Debug)]
+pub enum TagError {
+    /// IDが一致しません
+    #[display(fmt = "ID does not match")]
+    MismatchedId,
+    /// 名前が空欄です
+    #[display(fmt = "Name cannot be blank")]
+    NameIsBlank,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag() {
+        assert!(matches!(
+            Tag::create(1.into(), TagCategory::Hobby, " ".to_owned()),
+            Err(TagError::NameIsBlank)
+        ));
+        let mut tag = Tag::create(1.into(), TagCategory::Hobby, "映画鑑賞".to_owned()).unwrap();
+        tag.rename("カフェ巡り".to_owned()).unwrap();
+        assert_eq!(tag.name(), "カフェ巡り");
+        assert_eq!(tag.category(), TagCategory::Hobby);
+
+        let mut replayed = Tag::default();
+        for event in tag.pop_all() {
+            replayed.apply(event);
+        }
+        assert_eq!(replayed, tag);
+    }
+}
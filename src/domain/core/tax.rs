@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use derive_more::{Display, Error};
+use serde::{Deserialize, Serialize};
+
+use super::{Money, MoneyError, Rounding};
+
+/// 税区分
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Default,
+)]
+pub enum TaxCategory {
+    /// 標準税率
+    #[default]
+    Standard,
+    /// 軽減税率
+    Reduced,
+    /// 非課税
+    Exempt,
+}
+
+/// 価格が税込か税抜か
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TaxInclusion {
+    /// 税込価格
+    #[default]
+    Inclusive,
+    /// 税抜価格
+    Exclusive,
+}
+
+/// 税率
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaxRate {
+    /// 税区分
+    pub category: TaxCategory,
+    /// 税率（百分率）
+    pub rate: u8,
+    /// 適用開始日
+    pub effective_from: NaiveDate,
+}
+
+/// 税率表
+///
+/// 税区分ごとに、取引日以前で最も新しい適用開始日の税率を使う。非課税は常に0%とする。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaxTable {
+    pub rates: Vec<TaxRate>,
+}
+
+impl TaxTable {
+    /// 取引日に適用する税率を取得する
+    pub fn rate(&self, category: TaxCategory, date: NaiveDate) -> Result<u8, TaxError> {
+        if category == TaxCategory::Exempt {
+            return Ok(0);
+        }
+        self.rates
+            .iter()
+            .filter(|r| r.category == category && r.effective_from <= date)
+            .max_by_key(|r| r.effective_from)
+            .map(|r| r.rate)
+            .ok_or(TaxError::RateNotFound)
+    }
+}
+
+/// 日本の消費税率（2014年4月から8%、2019年10月から10%と軽減税率8%）
+impl Default for TaxTable {
+    fn default() -> Self {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap_or_default();
+        TaxTable {
+            rates: vec![
+                TaxRate {
+                    category: TaxCategory::Standard,
+                    rate: 8,
+                    effective_from: date(2014, 4, 1),
+                },
+                TaxRate {
+                    category: TaxCategory::Standard,
+                    rate: 10,
+                    effective_from: date(2019, 10, 1),
+                },
+                TaxRate {
+                    category: TaxCategory::Reduced,
+                    rate: 8,
+                    effective_from: date(2019, 10, 1),
+                },
+            ],
+        }
+    }
+}
+
+/// 税率ごとの内訳
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaxBreakdown {
+    /// 税区分
+    pub category: TaxCategory,
+    /// 税率（百分率）
+    pub rate: u8,
+    /// 税抜金額
+    pub taxable: Money,
+    /// 消費税額
+    pub tax: Money,
+}
+
+impl TaxBreakdown {
+    /// 税込金額
+    pub fn total(&self) -> Result<Money, MoneyError> {
+        self.taxable.checked_add(&self.tax)
+    }
+
+    /// 明細の金額から税区分・税率ごとの内訳を計算する
+    ///
+    /// 端数処理は請求書ごとに税率ごとに1回だけ行う。税込価格と税抜価格が混在する場合は、税込価格の合計と税抜価格の合計を端数処理せずに合算してから税額を計算する。
+    pub fn calculate<I>(amounts: I, rounding: Rounding) -> Result<Vec<TaxBreakdown>, MoneyError>
+    where
+        I: IntoIterator<Item = (TaxCategory, u8, TaxInclusion, Money)>,
+    {
+        let mut groups: BTreeMap<(TaxCategory, u8), (Option<Money>, Option<Money>)> =
+            BTreeMap::new();
+        for (category, rate, inclusion, amount) in amounts {
+            let (inclusive, exclusive) = groups.entry((category, rate)).or_default();
+            let sum = match inclusion {
+                TaxInclusion::Inclusive => inclusive,
+                TaxInclusion::Exclusive => exclusive,
+            };
+            *sum = Some(match sum.take() {
+                Some(sum) => sum.checked_add(&amount)?,
+                None => amount,
+            });
+        }
+        groups
+            .into_iter()
+            .map(|((category, rate), amounts)| {
+                let rate_i64 = rate as i64;
+                let (taxable, tax) = match amounts {
+                    (None, Some(exclusive)) => {
+                        let tax = exclusive.percentage(rate_i64, rounding)?;
+                        (exclusive, tax)
+                    }
+                    (inclusive, exclusive) => {
+                        // 税込金額の100倍（端数処理前）
+                        let scaled = match (inclusive, exclusive) {
+                            (Some(inclusive), Some(exclusive)) => inclusive
+                                .checked_mul(100)?
+                                .checked_add(&exclusive.checked_mul(100 + rate_i64)?)?,
+                            (inclusive, _) => inclusive.unwrap_or_default().checked_mul(100)?,
+                        };
+                        let total = scaled.checked_ratio(1, 100, rounding)?;
+                        let tax =
+                            scaled.checked_ratio(rate_i64, 100 * (100 + rate_i64), rounding)?;
+                        (total.checked_sub(&tax)?, tax)
+                    }
+                };
+                Ok(TaxBreakdown {
+                    category,
+                    rate,
+                    taxable,
+                    tax,
+                })
+            })
+            .collect()
+    }
+}
+
+/// 税金エラー
+#[derive(Error, Display, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaxError {
+    /// 取引日に適用できる税率がありません
+    #[display(fmt = "No tax rate is effective on the date")]
+    RateNotFound,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::core::Currency;
+
+    fn yen(amount: i64) -> Money {
+        Money::new(amount, Currency::JPY)
+    }
+
+    #[test]
+    fn test_tax_rate() {
+        let table = TaxTable::default();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(table.rate(TaxCategory::Standard, date(2019, 9, 30)), Ok(8));
+        assert_eq!(table.rate(TaxCategory::Standard, date(2019, 10, 1)), Ok(10));
+        assert_eq!(table.rate(TaxCategory::Reduced, date(2023, 4, 1)), Ok(8));
+        assert_eq!(table.rate(TaxCategory::Exempt, date(2023, 4, 1)), Ok(0));
+        assert_eq!(
+            table.rate(TaxCategory::Reduced, date(2019, 9, 30)),
+            Err(TaxError::RateNotFound)
+        );
+    }
+
+    #[test]
+    fn test_tax_breakdown() {
+        use TaxCategory::*;
+        use TaxInclusion::*;
+
+        // 明細ごとに端数処理すると1円ずつ切り捨てられるが、請求書ごとに1回だけ丸める
+        let breakdown = TaxBreakdown::calculate(
+            vec![
+                (Standard, 10, Exclusive, yen(105)),
+                (Standard, 10, Exclusive, yen(105)),
+                (Reduced, 8, Inclusive, yen(1080)),
+                (Exempt, 0, Inclusive, yen(500)),
+            ],
+            Rounding::Down,
+        )
+        .unwrap();
+        assert_eq!(
+            breakdown,
+            vec![
+                TaxBreakdown {
+                    category: Standard,
+                    rate: 10,
+                    taxable: yen(210),
+                    tax: yen(21),
+                },
+                TaxBreakdown {
+                    category: Reduced,
+                    rate: 8,
+                    taxable: yen(1000),
+                    tax: yen(80),
+                },
+                TaxBreakdown {
+                    category: Exempt,
+                    rate: 0,
+                    taxable: yen(500),
+                    tax: yen(0),
+                },
+            ]
+        );
+
+        // 税込価格と税抜価格の混在
+        let breakdown = TaxBreakdown::calculate(
+            vec![
+                (Standard, 10, Inclusive, yen(11000)),
+                (Standard, 10, Exclusive, yen(999)),
+            ],
+            Rounding::Down,
+        )
+        .unwrap();
+        assert_eq!(breakdown[0].total(), Ok(yen(12098)));
+        assert_eq!(breakdown[0].tax, yen(1099));
+        assert_eq!(breakdown[0].taxable, yen(10999));
+
+        // 税抜価格の税額を先に丸めると1円少なくなる（100 * 8 / 108 + 20 * 8 / 100 = 9.007...）
+        let breakdown = TaxBreakdown::calculate(
+            vec![
+                (Reduced, 8, Inclusive, yen(100)),
+                (Reduced, 8, Exclusive, yen(20)),
+            ],
+            Rounding::Down,
+        )
+        .unwrap();
+        assert_eq!(breakdown[0].tax, yen(9));
+        assert_eq!(breakdown[0].total(), Ok(yen(121)));
+    }
+}
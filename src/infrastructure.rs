@@ -1,13 +1,24 @@
 pub mod core;
+pub mod csv;
+pub mod pdf;
+pub mod projection;
+pub mod sqlite;
+pub mod text;
 
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
+use chrono::{DateTime, Utc};
 use derive_more::{Display, Error};
-use eventstore::{EventData, ResolvedEvent};
-use serde::de::DeserializeOwned;
+use eventstore::{Client, EventData, ExpectedRevision, RecordedEvent, ResolvedEvent};
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::{json, Value};
 
-use crate::domain::{DataAccessError, Aggregation, Event, Id};
+use crate::domain::{
+    core::Prostitute, Aggregation, DataAccessError, Entity, Event, EventEnvelope, Id, Metadata,
+};
 
 impl From<eventstore::Error> for DataAccessError {
     fn from(value: eventstore::Error) -> Self {
@@ -26,8 +37,8 @@ impl From<eventstore::Error> for DataAccessError {
             eventstore::Error::ResourceNotFound | eventstore::Error::ResourceDeleted => {
                 Self::ReadError(Box::new(value))
             }
-            eventstore::Error::ResourceAlreadyExists
-            | eventstore::Error::WrongExpectedVersion { .. } => Self::WriteError(Box::new(value)),
+            eventstore::Error::ResourceAlreadyExists => Self::WriteError(Box::new(value)),
+            eventstore::Error::WrongExpectedVersion { .. } => Self::ConflictError(Box::new(value)),
             eventstore::Error::IllegalStateError(_) => Self::ClientSideError(Box::new(value)),
         }
     }
@@ -65,25 +76,449 @@ fn stream_name<E: Aggregation>(id: E::Id) -> String {
     E::ENTITY_NAME.to_owned() + "-" + &id.to_string()
 }
 
-fn from_event<E: Event>(event: E) -> EventData {
+async fn find_by_id_while<A, P>(
+    client: &Client,
+    id: A::Id,
+    predicate: P,
+) -> Result<Option<A>, DataAccessError>
+where
+    A: Aggregation,
+    A::Event: TryFrom<ResolvedEvent, Error = EventConvertError>,
+    P: Fn(&RecordedEvent) -> bool,
+{
+    Ok(read_while(client, id, predicate)
+        .await?
+        .map(|(entity, _)| entity))
+}
+
+/// 集約と適用した最後のイベントのリビジョンを取得する
+async fn find_by_id_with_revision<A>(
+    client: &Client,
+    id: A::Id,
+) -> Result<Option<(A, u64)>, DataAccessError>
+where
+    A: Aggregation,
+    A::Event: TryFrom<ResolvedEvent, Error = EventConvertError>,
+{
+    read_while(client, id, |_| true).await
+}
+
+async fn read_while<A, P>(
+    client: &Client,
+    id: A::Id,
+    predicate: P,
+) -> Result<Option<(A, u64)>, DataAccessError>
+where
+    A: Aggregation,
+    A::Event: TryFrom<ResolvedEvent, Error = EventConvertError>,
+    P: Fn(&RecordedEvent) -> bool,
+{
+    match client
+        .read_stream(stream_name::<A>(id), &Default::default())
+        .await
+    {
+        Ok(mut stream) => {
+            let mut entity = A::default();
+            let mut revision = 0;
+            loop {
+                match stream.next().await {
+                    Ok(Some(e)) => {
+                        let event_revision = e.get_original_event().revision;
+                        if !apply_while(&mut entity, e, &predicate)? {
+                            break;
+                        }
+                        revision = event_revision;
+                    }
+                    Ok(None) => break,
+                    Err(eventstore::Error::ResourceDeleted) => return Ok(None),
+                    Err(eventstore::Error::ResourceNotFound) => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Ok(replayed(entity).map(|entity| (entity, revision)))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 読み込んだ集約のストリームのリビジョン（保存時の楽観的排他制御に使う）
+#[derive(Clone, Default)]
+struct Revisions(Arc<Mutex<HashMap<String, u64>>>);
+
+impl Revisions {
+    /// 読み込んだリビジョンを記録する
+    fn set(&self, stream_name: String, revision: u64) {
+        self.0.lock().unwrap().insert(stream_name, revision);
+    }
+
+    /// 保存時に期待するリビジョン（読み込んでいない場合はストリームが存在することだけを期待する）
+    fn expected(&self, stream_name: &str) -> ExpectedRevision {
+        self.0
+            .lock()
+            .unwrap()
+            .get(stream_name)
+            .map_or(ExpectedRevision::StreamExists, |r| {
+                ExpectedRevision::Exact(*r)
+            })
+    }
+}
+
+/// 条件を満たすイベントを集約に適用する（条件を満たさないイベントに達した場合は`false`を返す）
+fn apply_while<A, P>(
+    entity: &mut A,
+    event: ResolvedEvent,
+    predicate: &P,
+) -> Result<bool, EventConvertError>
+where
+    A: Aggregation,
+    A::Event: TryFrom<ResolvedEvent, Error = EventConvertError>,
+    P: Fn(&RecordedEvent) -> bool,
+{
+    if !predicate(event.get_original_event()) {
+        return Ok(false);
+    }
+    entity.apply(TryFrom::try_from(event)?);
+    Ok(true)
+}
+
+/// 再生した集約を取得する（適用できたイベントがない場合は`None`）
+fn replayed<A: Aggregation>(mut entity: A) -> Option<A> {
+    if entity.peek().is_none() {
+        None
+    } else {
+        entity.clear();
+        Some(entity)
+    }
+}
+
+fn from_event<E: Event>(event: E, metadata: &Metadata) -> EventData {
     let root = serde_json::to_value(event).unwrap();
     let event_type = root.as_object().unwrap().keys().next().unwrap();
     let mut data = root[event_type].clone();
     data.as_object_mut().unwrap().remove("id");
-    EventData::json(event_type, data).unwrap()
+    EventData::json(event_type, data)
+        .and_then(|e| e.metadata_as_json(versioned_metadata::<E>(metadata)))
+        .unwrap()
+}
+
+fn versioned_metadata<E: Event>(metadata: &Metadata) -> Value {
+    let mut value = json!(metadata);
+    value
+        .as_object_mut()
+        .unwrap()
+        .insert("schemaVersion".to_owned(), json!(E::SCHEMA_VERSION));
+    value
+}
+
+fn metadata(event: &RecordedEvent) -> Option<Metadata> {
+    serde_json::from_slice(event.custom_metadata.as_ref()).ok()
+}
+
+fn schema_version(event: &RecordedEvent) -> u32 {
+    serde_json::from_slice::<Value>(event.custom_metadata.as_ref())
+        .ok()
+        .and_then(|v| v.get("schemaVersion")?.as_u64())
+        .map_or(1, |v| v as u32)
+}
+
+/// アップキャスト関数（イベントタイプとデータを受け取り、次のバージョンの形に変換する）
+pub type Upcast = fn(String, Value) -> (String, Value);
+
+/// アップキャスターレジストリ
+#[derive(Default)]
+pub struct Upcasters {
+    upcasters: Vec<(&'static str, u32, Upcast)>,
+}
+
+impl Upcasters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// エンティティ名と変換元バージョンを指定してアップキャスターを登録する
+    pub fn register(mut self, entity_name: &'static str, version: u32, upcast: Upcast) -> Self {
+        self.upcasters.push((entity_name, version, upcast));
+        self.upcasters.sort_by_key(|(_, v, _)| *v);
+        self
+    }
+
+    /// 登録済みのアップキャスターを順に適用して現在の形に変換する
+    pub fn upcast(
+        &self,
+        entity_name: &str,
+        version: u32,
+        event_type: String,
+        data: Value,
+    ) -> (String, Value) {
+        self.upcasters
+            .iter()
+            .filter(|(name, v, _)| *name == entity_name && *v >= version)
+            .fold((event_type, data), |(t, d), (_, _, upcast)| upcast(t, d))
+    }
+
+    /// 登録済みのアップキャスターから現在のスキーマバージョンを取得する
+    pub fn current_version(&self, entity_name: &str) -> u32 {
+        self.upcasters
+            .iter()
+            .filter(|(name, _, _)| *name == entity_name)
+            .map(|(_, v, _)| v + 1)
+            .max()
+            .unwrap_or(1)
+    }
 }
 
-fn try_from_resolved_event<E, I>(value: ResolvedEvent) -> Result<E, EventConvertError>
+pub static UPCASTERS: Lazy<Upcasters> =
+    Lazy::new(|| Upcasters::new().register(Prostitute::ENTITY_NAME, 1, core::upcast_prostitute_v1));
+
+fn try_from_resolved_event<E, I>(
+    value: ResolvedEvent,
+) -> Result<EventEnvelope<E>, EventConvertError>
 where
     E: DeserializeOwned + Event<Id = I>,
     I: Id,
 {
     let event = value.get_original_event();
     let id = entity_id::<I, I::Inner>(&event.stream_id).ok_or(EventConvertError)?;
-    let mut data: Value = serde_json::from_slice(event.data.as_ref())?;
+    let (event_type, mut data) = UPCASTERS.upcast(
+        event.stream_id.split('-').next().unwrap_or_default(),
+        schema_version(event),
+        event.event_type.clone(),
+        serde_json::from_slice(event.data.as_ref())?,
+    );
     data.as_object_mut()
         .unwrap()
         .insert("id".to_owned(), json!(id));
-    let json = json!({ &event.event_type: data });
-    Ok(serde_json::from_value(json)?)
+    let json = json!({ event_type: data });
+    Ok(EventEnvelope {
+        event: serde_json::from_value(json)?,
+        metadata: metadata(event),
+    })
+}
+
+/// 履歴エントリ
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HistoryEntry<E> {
+    /// ストリーム内のリビジョン
+    pub revision: u64,
+    /// 記録日時
+    pub created: DateTime<Utc>,
+    /// イベント
+    pub event: E,
+    /// メタデータ
+    pub metadata: Option<Metadata>,
+    /// 直前の状態からの変更
+    pub changes: Vec<FieldChange>,
+}
+
+/// フィールドの変更
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    /// フィールド名（ネストしたフィールドは`.`区切り）
+    pub field: String,
+    /// 変更前の値
+    pub before: Value,
+    /// 変更後の値
+    pub after: Value,
+}
+
+/// 集約のイベント履歴を古い順に取得する
+pub async fn history<A>(
+    client: &Client,
+    id: A::Id,
+) -> Result<Option<Vec<HistoryEntry<A::Event>>>, DataAccessError>
+where
+    A: Aggregation,
+    EventEnvelope<A::Event>: TryFrom<ResolvedEvent, Error = EventConvertError>,
+{
+    let mut stream = client
+        .read_stream(stream_name::<A>(id), &Default::default())
+        .await?;
+    let mut entity = A::default();
+    let mut before = serde_json::to_value(&entity).map_err(EventConvertError::from)?;
+    let mut entries = Vec::new();
+    loop {
+        match stream.next().await {
+            Ok(Some(e)) => {
+                let revision = e.get_original_event().revision;
+                let created = e.get_original_event().created;
+                let envelope = EventEnvelope::<A::Event>::try_from(e)?;
+                entity.apply(envelope.event.clone());
+                entity.clear();
+                let after = serde_json::to_value(&entity).map_err(EventConvertError::from)?;
+                entries.push(HistoryEntry {
+                    revision,
+                    created,
+                    event: envelope.event,
+                    metadata: envelope.metadata,
+                    changes: diff(&before, &after),
+                });
+                before = after;
+            }
+            Ok(_) => break,
+            Err(eventstore::Error::ResourceDeleted) => return Ok(None),
+            Err(eventstore::Error::ResourceNotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(Some(entries))
+}
+
+fn diff(before: &Value, after: &Value) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    diff_fields("", before, after, &mut changes);
+    changes
+}
+
+fn diff_fields(path: &str, before: &Value, after: &Value, changes: &mut Vec<FieldChange>) {
+    match (before, after) {
+        (Value::Object(b), Value::Object(a)) => {
+            let mut keys = b.keys().chain(a.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let field = match path {
+                    "" => key.to_owned(),
+                    _ => format!("{}.{}", path, key),
+                };
+                diff_fields(
+                    &field,
+                    b.get(key).unwrap_or(&Value::Null),
+                    a.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        _ if before != after => changes.push(FieldChange {
+            field: path.to_owned(),
+            before: before.clone(),
+            after: after.clone(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+    use eventstore::Position;
+    use serde_json::json;
+
+    use super::*;
+    use crate::domain::core::Schedule;
+
+    fn resolved_event(revision: u64, event_type: &str, data: Value) -> ResolvedEvent {
+        ResolvedEvent {
+            event: Some(RecordedEvent {
+                stream_id: "schedule-1".to_owned(),
+                id: Default::default(),
+                revision,
+                event_type: event_type.to_owned(),
+                data: serde_json::to_vec(&data).unwrap().into(),
+                metadata: Default::default(),
+                custom_metadata: Default::default(),
+                is_json: true,
+                position: Position {
+                    commit: revision,
+                    prepare: revision,
+                },
+                created: Utc.with_ymd_and_hms(2023, 4, 20, 0, 0, 0).unwrap()
+                    + Duration::hours(revision as i64),
+            }),
+            link: None,
+            commit_position: None,
+        }
+    }
+
+    fn replay<P>(predicate: P) -> Option<Schedule>
+    where
+        P: Fn(&RecordedEvent) -> bool,
+    {
+        let events = vec![
+            resolved_event(0, "ScheduleCreated", json!({"prostitute_id": 1})),
+            // 存在しないシフトのため適用されない
+            resolved_event(
+                1,
+                "ScheduleShiftStatusChanged",
+                json!({"shift_id": 1, "status": "Reviewing"}),
+            ),
+            resolved_event(
+                2,
+                "ScheduleShiftAdded",
+                json!({"shift": {
+                    "id": 1,
+                    "time": {"start": "2023-04-21T10:00:00Z", "end": "2023-04-21T18:00:00Z"},
+                    "status": "Editing",
+                }}),
+            ),
+        ];
+        let mut entity = Schedule::default();
+        for event in events {
+            if !apply_while(&mut entity, event, &predicate).unwrap() {
+                break;
+            }
+        }
+        replayed(entity)
+    }
+
+    #[test]
+    fn test_replay_while() {
+        let schedule = replay(|_| true).unwrap();
+        assert_eq!(schedule.prostitute_id(), 1.into());
+        assert_eq!(schedule.shifts().len(), 1);
+        assert!(schedule.peek().is_none());
+
+        // 日時で打ち切る
+        let time = Utc.with_ymd_and_hms(2023, 4, 20, 1, 30, 0).unwrap();
+        assert!(replay(|e| e.created <= time).unwrap().shifts().is_empty());
+
+        // リビジョンで打ち切る（適用されないイベントも読み飛ばす）
+        for (revision, shifts) in [(0, 0), (1, 0), (2, 1)] {
+            let schedule = replay(|e| e.revision <= revision).unwrap();
+            assert_eq!(schedule.shifts().len(), shifts);
+        }
+
+        // 作成前の時点では存在しない
+        assert_eq!(replay(|_| false), None);
+    }
+
+    #[test]
+    fn test_revisions() {
+        let revisions = Revisions::default();
+        assert_eq!(revisions.expected("tag-1"), ExpectedRevision::StreamExists);
+        revisions.clone().set("tag-1".to_owned(), 2);
+        assert_eq!(revisions.expected("tag-1"), ExpectedRevision::Exact(2));
+        assert_eq!(revisions.expected("tag-2"), ExpectedRevision::StreamExists);
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = json!({
+            "id": 1,
+            "name": "さくら",
+            "figure": { "height": 155, "weight": null },
+            "images": [1, 2],
+        });
+        let after = json!({
+            "id": 1,
+            "name": "さくら",
+            "figure": { "height": 158, "weight": null },
+            "images": [2, 1],
+        });
+        assert_eq!(
+            diff(&before, &after),
+            vec![
+                FieldChange {
+                    field: "figure.height".to_owned(),
+                    before: json!(155),
+                    after: json!(158),
+                },
+                FieldChange {
+                    field: "images".to_owned(),
+                    before: json!([1, 2]),
+                    after: json!([2, 1]),
+                },
+            ]
+        );
+        assert_eq!(diff(&before, &before), vec![]);
+    }
 }
@@ -1,21 +1,36 @@
+mod cash_closing;
+mod coupon;
 // mod customer;
 mod extra_service;
 mod media;
+mod point_ledger;
 mod prostitute;
+mod receipt;
+mod reservation;
 mod schedule;
+mod tag;
 
 use eventstore::ResolvedEvent;
 
 use crate::domain::{
-    core::{CoreEvent, ExtraService, Media, Prostitute, Schedule},
+    core::{
+        CashClosing, CoreEvent, Coupon, ExtraService, Media, PointLedger, Prostitute, ReceiptBook,
+        Reservation, Schedule, Tag,
+    },
      Entity,
 };
 
+pub use self::cash_closing::*;
+pub use self::coupon::*;
 // pub use self::customer::*;
 pub use self::extra_service::*;
 pub use self::media::*;
+pub use self::point_ledger::*;
 pub use self::prostitute::*;
+pub use self::receipt::*;
+pub use self::reservation::*;
 pub use self::schedule::*;
+pub use self::tag::*;
 
 use super::EventConvertError;
 
@@ -29,12 +44,18 @@ impl TryFrom<ResolvedEvent> for CoreEvent {
             .next()
             .ok_or(EventConvertError)?;
         match x {
+            CashClosing::ENTITY_NAME => Ok(CoreEvent::CashClosingEvent(TryFrom::try_from(value)?)),
+            Coupon::ENTITY_NAME => Ok(CoreEvent::CouponEvent(TryFrom::try_from(value)?)),
             ExtraService::ENTITY_NAME => {
                 Ok(CoreEvent::ExtraServiceEvent(TryFrom::try_from(value)?))
             }
             Media::ENTITY_NAME => Ok(CoreEvent::MediaEvent(TryFrom::try_from(value)?)),
+            PointLedger::ENTITY_NAME => Ok(CoreEvent::PointLedgerEvent(TryFrom::try_from(value)?)),
             Prostitute::ENTITY_NAME => Ok(CoreEvent::ProstituteEvent(TryFrom::try_from(value)?)),
+            ReceiptBook::ENTITY_NAME => Ok(CoreEvent::ReceiptBookEvent(TryFrom::try_from(value)?)),
+            Reservation::ENTITY_NAME => Ok(CoreEvent::ReservationEvent(TryFrom::try_from(value)?)),
             Schedule::ENTITY_NAME => Ok(CoreEvent::ScheduleEvent(TryFrom::try_from(value)?)),
+            Tag::ENTITY_NAME => Ok(CoreEvent::TagEvent(TryFrom::try_from(value)?)),
             _ => Err(EventConvertError),
         }
     }
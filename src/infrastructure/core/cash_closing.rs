@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eventstore::{AppendToStreamOptions, Client, ExpectedRevision, ResolvedEvent};
+
+use crate::domain::core::{CashClosing, CashClosingEvent, CashClosingId, CashClosingRepository};
+use crate::domain::{Aggregation, DataAccessError, Entity, EventEnvelope, Metadata};
+use crate::infrastructure::{find_by_id_while, from_event, try_from_resolved_event};
+use crate::infrastructure::{stream_name, EventConvertError};
+
+#[derive(Clone)]
+pub struct EventStoreCashClosingRepository {
+    client: Client,
+}
+
+impl EventStoreCashClosingRepository {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl CashClosingRepository for EventStoreCashClosingRepository {
+    async fn find_by_id(&self, id: CashClosingId) -> Result<Option<CashClosing>, DataAccessError> {
+        find_by_id_while(&self.client, id, |_| true).await
+    }
+
+    async fn find_by_id_at(
+        &self,
+        id: CashClosingId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<CashClosing>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.created <= time).await
+    }
+
+    async fn find_by_id_at_revision(
+        &self,
+        id: CashClosingId,
+        revision: u64,
+    ) -> Result<Option<CashClosing>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.revision <= revision).await
+    }
+
+    async fn save(
+        &mut self,
+        entity: &mut CashClosing,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
+        let stream_name = stream_name::<CashClosing>(entity.id());
+        // 同じ営業日のレジ締めが既に開始されている場合は失敗する
+        let rev = match entity.peek() {
+            Some(CashClosingEvent::CashClosingOpened { .. }) => ExpectedRevision::NoStream,
+            Some(_) => ExpectedRevision::StreamExists,
+            None => return Ok(false),
+        };
+        self.client
+            .append_to_stream(
+                &stream_name,
+                &AppendToStreamOptions::default().expected_revision(rev),
+                entity
+                    .pop_all()
+                    .into_iter()
+                    .map(|e| from_event(e, metadata))
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+        Ok(true)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for CashClosingEvent {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        EventEnvelope::try_from(value).map(|e| e.event)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for EventEnvelope<CashClosingEvent> {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        try_from_resolved_event(value)
+    }
+}
@@ -1,11 +1,12 @@
 use async_trait::async_trait;
-use eventstore::{AppendToStreamOptions, Client, EventData, ExpectedRevision, ResolvedEvent};
+use chrono::{DateTime, Utc};
+use eventstore::{AppendToStreamOptions, Client, ExpectedRevision, ResolvedEvent};
 
 use crate::domain::core::{
     ExtraService, ExtraServiceEvent, ExtraServiceId, ExtraServiceRepository,
 };
-use crate::domain::{DataAccessError, Aggregation, Entity};
-use crate::infrastructure::{from_event, try_from_resolved_event};
+use crate::domain::{Aggregation, DataAccessError, Entity, EventEnvelope, Metadata};
+use crate::infrastructure::{find_by_id_while, from_event, try_from_resolved_event};
 use crate::infrastructure::{stream_name, EventConvertError};
 
 #[derive(Clone)]
@@ -25,34 +26,30 @@ impl ExtraServiceRepository for EventStoreExtraServiceRepository {
         &self,
         id: ExtraServiceId,
     ) -> Result<Option<ExtraService>, DataAccessError> {
-        match self
-            .client
-            .read_stream(stream_name::<ExtraService>(id), &Default::default())
-            .await
-        {
-            Ok(mut stream) => {
-                let mut entity = ExtraService::default();
-                loop {
-                    match stream.next().await {
-                        Ok(Some(e)) => entity.apply(TryFrom::try_from(e)?),
-                        Ok(_) => break,
-                        Err(eventstore::Error::ResourceDeleted) => return Ok(None),
-                        Err(eventstore::Error::ResourceNotFound) => return Ok(None),
-                        Err(e) => return Err(e.into()),
-                    }
-                }
-                if let None = entity.peek() {
-                    Ok(None)
-                } else {
-                    entity.clear();
-                    Ok(Some(entity))
-                }
-            }
-            Err(e) => Err(e.into()),
-        }
+        find_by_id_while(&self.client, id, |_| true).await
+    }
+
+    async fn find_by_id_at(
+        &self,
+        id: ExtraServiceId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<ExtraService>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.created <= time).await
+    }
+
+    async fn find_by_id_at_revision(
+        &self,
+        id: ExtraServiceId,
+        revision: u64,
+    ) -> Result<Option<ExtraService>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.revision <= revision).await
     }
 
-    async fn save(&mut self, entity: &mut ExtraService) -> Result<bool, DataAccessError> {
+    async fn save(
+        &mut self,
+        entity: &mut ExtraService,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
         let stream_name = stream_name::<ExtraService>(entity.id());
         let rev = match entity.peek() {
             Some(ExtraServiceEvent::ExtraServiceCreated { .. }) => ExpectedRevision::NoStream,
@@ -66,19 +63,23 @@ impl ExtraServiceRepository for EventStoreExtraServiceRepository {
                 entity
                     .pop_all()
                     .into_iter()
-                    .map(EventData::from)
+                    .map(|e| from_event(e, metadata))
                     .collect::<Vec<_>>(),
             )
             .await?;
         Ok(true)
     }
 
-    async fn delete(&mut self, entity: &mut ExtraService) -> Result<bool, DataAccessError> {
+    async fn delete(
+        &mut self,
+        entity: &mut ExtraService,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
         let stream_name = stream_name::<ExtraService>(entity.id());
         self.client.append_to_stream(
             &stream_name,
             &AppendToStreamOptions::default().expected_revision(ExpectedRevision::StreamExists),
-            EventData::from(ExtraServiceEvent::ExtraServiceDeleted { id: entity.id() }),
+            from_event(ExtraServiceEvent::ExtraServiceDeleted { id: entity.id() }, metadata),
         ).await?;
         self.client
             .delete_stream(&stream_name, &Default::default())
@@ -87,13 +88,15 @@ impl ExtraServiceRepository for EventStoreExtraServiceRepository {
     }
 }
 
-impl From<ExtraServiceEvent> for EventData {
-    fn from(value: ExtraServiceEvent) -> Self {
-        from_event(value)
+impl TryFrom<ResolvedEvent> for ExtraServiceEvent {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        EventEnvelope::try_from(value).map(|e| e.event)
     }
 }
 
-impl TryFrom<ResolvedEvent> for ExtraServiceEvent {
+impl TryFrom<ResolvedEvent> for EventEnvelope<ExtraServiceEvent> {
     type Error = EventConvertError;
 
     fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
@@ -109,8 +112,9 @@ mod tests {
     use crate::{
         domain::{
             core::{Currency, ExtraService, ExtraServiceEvent, ExtraServiceRepository, Money},
-            ID_GENERATOR,
+            ActorId, EventEnvelope, Metadata, Role, ID_GENERATOR,
         },
+        infrastructure::{from_event, versioned_metadata},
         DelyConfig,
     };
 
@@ -122,6 +126,7 @@ mod tests {
         let config = DelyConfig::load().unwrap();
         let client = Client::new(config.eventstore.url.parse().unwrap()).unwrap();
         let mut repo = EventStoreExtraServiceRepository::new(client.clone());
+        let metadata = Metadata::new(ActorId::from(1), Role::Manager);
 
         let id = ID_GENERATOR.generate().await;
 
@@ -136,7 +141,7 @@ mod tests {
         entity.change_name("サービス名改".to_owned()).unwrap();
 
         // エンティティ登録確認
-        assert_eq!(repo.save(&mut entity).await.unwrap(), true);
+        assert_eq!(repo.save(&mut entity, &metadata).await.unwrap(), true);
         assert_eq!(
             repo.find_by_id(id).await.unwrap(),
             ExtraService::create(
@@ -148,13 +153,26 @@ mod tests {
             .ok()
         );
 
+        // 過去時点のエンティティ確認
+        assert_eq!(
+            repo.find_by_id_at_revision(id, 0).await.unwrap(),
+            ExtraService::create(
+                id,
+                "名前".to_owned(),
+                "説明".to_owned(),
+                Money::new(1500, Currency::JPY),
+            )
+            .ok()
+        );
+
         // エンティティ削除確認
-        assert_eq!(repo.delete(&mut entity).await.unwrap(), true);
+        assert_eq!(repo.delete(&mut entity, &metadata).await.unwrap(), true);
         assert_eq!(repo.find_by_id(id).await.unwrap(), None);
     }
 
     #[test]
     fn test_event_data_from() {
+        let metadata = Metadata::system();
         let event = ExtraServiceEvent::ExtraServiceCreated {
             id: 999.into(),
             name: "サービス名".to_owned(),
@@ -172,9 +190,10 @@ mod tests {
                 }
             }),
         )
+        .and_then(|e| e.metadata_as_json(versioned_metadata::<ExtraServiceEvent>(&metadata)))
         .unwrap();
         assert_eq!(
-            format!("{:?}", EventData::from(event)),
+            format!("{:?}", from_event(event, &metadata)),
             format!("{:?}", expected),
         );
     }
@@ -216,4 +235,38 @@ mod tests {
         };
         assert_eq!(ExtraServiceEvent::try_from(event).ok(), Some(expected));
     }
+
+    #[test]
+    fn test_event_envelope_try_from() {
+        let metadata = Metadata::new(ActorId::from(7), Role::Staff);
+        let event = ResolvedEvent {
+            event: Some(RecordedEvent {
+                stream_id: "extra_service-100".to_owned(),
+                id: Default::default(),
+                revision: Default::default(),
+                event_type: "ExtraServiceNameChanged".to_owned(),
+                data: serde_json::to_vec(&json!({ "name": "サービス名" }))
+                    .unwrap()
+                    .into(),
+                metadata: Default::default(),
+                custom_metadata: serde_json::to_vec(&metadata).unwrap().into(),
+                is_json: Default::default(),
+                position: Position {
+                    commit: Default::default(),
+                    prepare: Default::default(),
+                },
+                created: Default::default(),
+            }),
+            link: None,
+            commit_position: None,
+        };
+        let expected = EventEnvelope {
+            event: ExtraServiceEvent::ExtraServiceNameChanged {
+                id: 100.into(),
+                name: "サービス名".to_owned(),
+            },
+            metadata: Some(metadata),
+        };
+        assert_eq!(EventEnvelope::try_from(event).ok(), Some(expected));
+    }
 }
@@ -1,16 +1,18 @@
 use async_trait::async_trait;
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use eventstore::{
     AppendToStreamOptions, Client, DeleteStreamOptions, EventData, ExpectedRevision, ResolvedEvent,
 };
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::borrow::Borrow;
-use std::collections::HashMap;
 
 use crate::domain::core::{Media, MediaEvent, MediaId, MediaRepository};
-use crate::domain::{Aggregation, DataAccessError, Entity};
+use crate::domain::{Aggregation, DataAccessError, Entity, EventEnvelope, Metadata};
 use crate::infrastructure::EventConvertError;
-use crate::infrastructure::{entity_id, stream_name};
+use crate::infrastructure::{
+    entity_id, find_by_id_while, metadata, stream_name, versioned_metadata,
+};
 
 #[derive(Clone)]
 pub struct EventStoreMediaRepository {
@@ -26,34 +28,30 @@ impl EventStoreMediaRepository {
 #[async_trait]
 impl MediaRepository for EventStoreMediaRepository {
     async fn find_by_id(&self, id: MediaId) -> Result<Option<Media>, DataAccessError> {
-        match self
-            .client
-            .read_stream(stream_name::<Media>(id), &Default::default())
-            .await
-        {
-            Ok(mut stream) => {
-                let mut entity = Media::default();
-                loop {
-                    match stream.next().await {
-                        Ok(Some(e)) => entity.apply(TryFrom::try_from(e)?),
-                        Ok(_) => break,
-                        Err(eventstore::Error::ResourceDeleted) => return Ok(None),
-                        Err(eventstore::Error::ResourceNotFound) => return Ok(None),
-                        Err(e) => return Err(e.into()),
-                    }
-                }
-                if let None = entity.peek() {
-                    Ok(None)
-                } else {
-                    entity.clear();
-                    Ok(Some(entity))
-                }
-            }
-            Err(e) => Err(e.into()),
-        }
+        find_by_id_while(&self.client, id, |_| true).await
+    }
+
+    async fn find_by_id_at(
+        &self,
+        id: MediaId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Media>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.created <= time).await
     }
 
-    async fn save(&mut self, entity: &mut Media) -> Result<bool, DataAccessError> {
+    async fn find_by_id_at_revision(
+        &self,
+        id: MediaId,
+        revision: u64,
+    ) -> Result<Option<Media>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.revision <= revision).await
+    }
+
+    async fn save(
+        &mut self,
+        entity: &mut Media,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
         let stream_name = stream_name::<Media>(entity.id());
         let rev = match entity.peek() {
             Some(MediaEvent::MediaCreated { .. }) => ExpectedRevision::NoStream,
@@ -62,7 +60,7 @@ impl MediaRepository for EventStoreMediaRepository {
         };
         let mut events = Vec::new();
         while let Some(e) = entity.pop() {
-            events.push(EventData::from(e))
+            events.push(event_data(e, metadata))
         }
         self.client
             .append_to_stream(
@@ -74,13 +72,17 @@ impl MediaRepository for EventStoreMediaRepository {
         Ok(true)
     }
 
-    async fn delete(&mut self, entity: &mut Media) -> Result<bool, DataAccessError> {
+    async fn delete(
+        &mut self,
+        entity: &mut Media,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
         let stream_name = stream_name::<Media>(entity.id());
         self.client
             .append_to_stream(
                 &stream_name,
                 &AppendToStreamOptions::default().expected_revision(ExpectedRevision::StreamExists),
-                EventData::from(MediaEvent::MediaDeleted { id: entity.id() }),
+                event_data(MediaEvent::MediaDeleted { id: entity.id() }, metadata),
             )
             .await?;
         self.client
@@ -90,28 +92,39 @@ impl MediaRepository for EventStoreMediaRepository {
     }
 }
 
-impl From<MediaEvent> for EventData {
-    fn from(value: MediaEvent) -> Self {
-        match value {
-            MediaEvent::MediaCreated { mime, data, .. } => {
-                let mut meta = HashMap::new();
-                meta.insert("contentType".to_owned(), mime.to_string());
-                EventData::binary("MediaCreated", data)
-                    .metadata_as_json(meta)
-                    .unwrap()
-            }
-            MediaEvent::MediaDeleted { .. } => EventData::binary("MediaDeleted", Bytes::default()),
+fn event_data(value: MediaEvent, metadata: &Metadata) -> EventData {
+    let mut meta = versioned_metadata::<MediaEvent>(metadata);
+    match value {
+        MediaEvent::MediaCreated { mime, data, .. } => {
+            meta.as_object_mut()
+                .unwrap()
+                .insert("contentType".to_owned(), json!(mime.to_string()));
+            EventData::binary("MediaCreated", data)
+                .metadata_as_json(meta)
+                .unwrap()
         }
+        MediaEvent::MediaDeleted { .. } => EventData::binary("MediaDeleted", Bytes::default())
+            .metadata_as_json(meta)
+            .unwrap(),
     }
 }
 
 impl TryFrom<ResolvedEvent> for MediaEvent {
     type Error = EventConvertError;
 
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        EventEnvelope::try_from(value).map(|e| e.event)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for EventEnvelope<MediaEvent> {
+    type Error = EventConvertError;
+
     fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
         let event = value.link.or(value.event).ok_or(EventConvertError)?;
-        match event.event_type.borrow() {
-            "MediaCreated" => Ok(MediaEvent::MediaCreated {
+        let metadata = metadata(&event);
+        let event = match event.event_type.borrow() {
+            "MediaCreated" => MediaEvent::MediaCreated {
                 id: entity_id(&event.stream_id).ok_or(EventConvertError)?,
                 mime: serde_json::from_slice::<Value>(&event.custom_metadata)?
                     .as_object()
@@ -120,13 +133,14 @@ impl TryFrom<ResolvedEvent> for MediaEvent {
                     .filter_map(Value::as_str)
                     .find_map(|s| s.parse().ok())
                     .ok_or(EventConvertError)?,
-                data: event.data,
-            }),
-            "MediaDeleted" => Ok(MediaEvent::MediaDeleted {
+                data: event.data.clone(),
+            },
+            "MediaDeleted" => MediaEvent::MediaDeleted {
                 id: entity_id(&event.stream_id).ok_or(EventConvertError)?,
-            }),
-            _ => Err(EventConvertError),
-        }
+            },
+            _ => return Err(EventConvertError),
+        };
+        Ok(EventEnvelope { event, metadata })
     }
 }
 
@@ -138,7 +152,7 @@ mod tests {
     use crate::{
         domain::{
             core::{Media, MediaRepository},
-            ID_GENERATOR,
+            Metadata, ID_GENERATOR,
         },
         DelyConfig,
     };
@@ -151,6 +165,7 @@ mod tests {
         let config = DelyConfig::load().unwrap();
         let client = Client::new(config.eventstore.url.parse().unwrap()).unwrap();
         let mut repo = EventStoreMediaRepository::new(client.clone());
+        let metadata = Metadata::system();
 
         // エンティティ生成
         let id = ID_GENERATOR.generate().await;
@@ -161,7 +176,7 @@ mod tests {
         .unwrap();
 
         // エンティティ登録確認
-        assert_eq!(repo.save(&mut entity).await.unwrap(), true);
+        assert_eq!(repo.save(&mut entity, &metadata).await.unwrap(), true);
         assert_eq!(
             repo.find_by_id(id).await.unwrap(),
             Media::create(
@@ -171,7 +186,7 @@ mod tests {
             .ok()
         );
         // エンティティ削除確認
-        assert_eq!(repo.delete(&mut entity).await.unwrap(), true);
+        assert_eq!(repo.delete(&mut entity, &metadata).await.unwrap(), true);
         assert_eq!(repo.find_by_id(id).await.unwrap(), None);
     }
 }
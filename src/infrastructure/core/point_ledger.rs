@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eventstore::{AppendToStreamOptions, Client, ExpectedRevision, ResolvedEvent};
+
+use crate::domain::core::{PointLedger, PointLedgerEvent, PointLedgerId, PointLedgerRepository};
+use crate::domain::{Aggregation, DataAccessError, Entity, EventEnvelope, Metadata};
+use crate::infrastructure::{
+    find_by_id_while, find_by_id_with_revision, from_event, try_from_resolved_event, Revisions,
+};
+use crate::infrastructure::{stream_name, EventConvertError};
+
+#[derive(Clone)]
+pub struct EventStorePointLedgerRepository {
+    client: Client,
+    revisions: Revisions,
+}
+
+impl EventStorePointLedgerRepository {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            revisions: Revisions::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl PointLedgerRepository for EventStorePointLedgerRepository {
+    async fn find_by_id(&self, id: PointLedgerId) -> Result<Option<PointLedger>, DataAccessError> {
+        let found = find_by_id_with_revision::<PointLedger>(&self.client, id).await?;
+        Ok(found.map(|(entity, revision)| {
+            self.revisions.set(stream_name::<PointLedger>(id), revision);
+            entity
+        }))
+    }
+
+    async fn find_by_id_at(
+        &self,
+        id: PointLedgerId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<PointLedger>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.created <= time).await
+    }
+
+    async fn find_by_id_at_revision(
+        &self,
+        id: PointLedgerId,
+        revision: u64,
+    ) -> Result<Option<PointLedger>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.revision <= revision).await
+    }
+
+    async fn save(
+        &mut self,
+        entity: &mut PointLedger,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
+        let stream_name = stream_name::<PointLedger>(entity.id());
+        // お客様のポイント台帳が既に作成されている場合や、読み込んだ後に他で残高が変わった場合は失敗する
+        let rev = match entity.peek() {
+            Some(PointLedgerEvent::PointLedgerOpened { .. }) => ExpectedRevision::NoStream,
+            Some(_) => self.revisions.expected(&stream_name),
+            None => return Ok(false),
+        };
+        let result = self
+            .client
+            .append_to_stream(
+                &stream_name,
+                &AppendToStreamOptions::default().expected_revision(rev),
+                entity
+                    .pop_all()
+                    .into_iter()
+                    .map(|e| from_event(e, metadata))
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+        self.revisions
+            .set(stream_name, result.next_expected_version);
+        Ok(true)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for PointLedgerEvent {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        EventEnvelope::try_from(value).map(|e| e.event)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for EventEnvelope<PointLedgerEvent> {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        try_from_resolved_event(value)
+    }
+}
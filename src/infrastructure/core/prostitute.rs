@@ -1,12 +1,14 @@
 use async_trait::async_trait;
-use eventstore::{AppendToStreamOptions, Client, EventData, ExpectedRevision, ResolvedEvent};
+use chrono::{DateTime, Utc};
+use eventstore::{AppendToStreamOptions, Client, ExpectedRevision, ResolvedEvent};
+use serde_json::Value;
 
 use crate::domain::core::{
     Prostitute, ProstituteEvent, ProstituteId, ProstituteRepository,
 };
-use crate::domain::{DataAccessError, Aggregation, Entity};
+use crate::domain::{Aggregation, DataAccessError, Entity, EventEnvelope, Metadata};
 use crate::infrastructure::{EventConvertError, stream_name};
-use crate::infrastructure::{from_event, try_from_resolved_event};
+use crate::infrastructure::{find_by_id_while, from_event, try_from_resolved_event};
 
 #[derive(Clone)]
 pub struct EventStoreProstituteRepository {
@@ -21,38 +23,31 @@ impl EventStoreProstituteRepository {
 
 #[async_trait]
 impl ProstituteRepository for EventStoreProstituteRepository {
-    async fn find_by_id(
+    async fn find_by_id(&self, id: ProstituteId) -> Result<Option<Prostitute>, DataAccessError> {
+        find_by_id_while(&self.client, id, |_| true).await
+    }
+
+    async fn find_by_id_at(
         &self,
         id: ProstituteId,
+        time: DateTime<Utc>,
     ) -> Result<Option<Prostitute>, DataAccessError> {
-        match self
-            .client
-            .read_stream(stream_name::<Prostitute>(id), &Default::default())
-            .await
-        {
-            Ok(mut stream) => {
-                let mut entity = Prostitute::default();
-                loop {
-                    match stream.next().await {
-                        Ok(Some(e)) => entity.apply(TryFrom::try_from(e)?),
-                        Ok(_) => break,
-                        Err(eventstore::Error::ResourceDeleted) => return Ok(None),
-                        Err(eventstore::Error::ResourceNotFound) => return Ok(None),
-                        Err(e) => return Err(e.into()),
-                    }
-                }
-                if let None = entity.peek() {
-                    Ok(None)
-                } else {
-                    entity.clear();
-                    Ok(Some(entity))
-                }
-            }
-            Err(e) => Err(e.into()),
-        }
+        find_by_id_while(&self.client, id, |e| e.created <= time).await
     }
 
-    async fn save(&mut self, entity: &mut Prostitute) -> Result<bool, DataAccessError> {
+    async fn find_by_id_at_revision(
+        &self,
+        id: ProstituteId,
+        revision: u64,
+    ) -> Result<Option<Prostitute>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.revision <= revision).await
+    }
+
+    async fn save(
+        &mut self,
+        entity: &mut Prostitute,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
         let stream_name = stream_name::<Prostitute>(entity.id());
         let rev = match entity.peek() {
             Some(ProstituteEvent::ProstituteJoined { .. }) => ExpectedRevision::NoStream,
@@ -66,19 +61,23 @@ impl ProstituteRepository for EventStoreProstituteRepository {
                 entity
                     .pop_all()
                     .into_iter()
-                    .map(EventData::from)
+                    .map(|e| from_event(e, metadata))
                     .collect::<Vec<_>>(),
             )
             .await?;
         Ok(true)
     }
 
-    async fn delete(&mut self, entity: &mut Prostitute) -> Result<bool, DataAccessError> {
+    async fn delete(
+        &mut self,
+        entity: &mut Prostitute,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
         let stream_name = stream_name::<Prostitute>(entity.id());
         self.client.append_to_stream(
             &stream_name,
             &AppendToStreamOptions::default().expected_revision(ExpectedRevision::StreamExists),
-            EventData::from(ProstituteEvent::ProstituteDeleted { id: entity.id() }),
+            from_event(ProstituteEvent::ProstituteDeleted { id: entity.id() }, metadata),
         ).await?;
         self.client
             .delete_stream(&stream_name, &Default::default())
@@ -87,16 +86,122 @@ impl ProstituteRepository for EventStoreProstituteRepository {
     }
 }
 
-impl From<ProstituteEvent> for EventData {
-    fn from(value: ProstituteEvent) -> Self {
-        from_event(value)
+impl TryFrom<ResolvedEvent> for ProstituteEvent {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        EventEnvelope::try_from(value).map(|e| e.event)
     }
 }
 
-impl TryFrom<ResolvedEvent> for ProstituteEvent {
+impl TryFrom<ResolvedEvent> for EventEnvelope<ProstituteEvent> {
     type Error = EventConvertError;
 
     fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
         try_from_resolved_event(value)
     }
 }
+
+/// スキーマバージョン1から2への変換
+pub(crate) fn upcast_prostitute_v1(event_type: String, data: Value) -> (String, Value) {
+    match event_type.as_str() {
+        "ProstituteExtraServiceNameChanged" => ("ProstituteNameChanged".to_owned(), data),
+        _ => (event_type, data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eventstore::{Position, RecordedEvent, ResolvedEvent};
+    use serde_json::{json, Value};
+
+    use crate::{
+        domain::{
+            core::{Prostitute, ProstituteEvent},
+            Aggregation, Entity, Event, EventEnvelope,
+        },
+        infrastructure::UPCASTERS,
+    };
+
+    fn fixture(json: &str) -> Vec<ResolvedEvent> {
+        serde_json::from_str::<Vec<Value>>(json)
+            .unwrap()
+            .into_iter()
+            .map(|v| ResolvedEvent {
+                event: Some(RecordedEvent {
+                    stream_id: v["stream_id"].as_str().unwrap().to_owned(),
+                    id: Default::default(),
+                    revision: v["revision"].as_u64().unwrap(),
+                    event_type: v["event_type"].as_str().unwrap().to_owned(),
+                    data: serde_json::to_vec(&v["data"]).unwrap().into(),
+                    metadata: Default::default(),
+                    custom_metadata: match &v["custom_metadata"] {
+                        Value::Null => Default::default(),
+                        m => serde_json::to_vec(m).unwrap().into(),
+                    },
+                    is_json: true,
+                    position: Position {
+                        commit: Default::default(),
+                        prepare: Default::default(),
+                    },
+                    created: Default::default(),
+                }),
+                link: None,
+                commit_position: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_schema_version() {
+        assert_eq!(
+            ProstituteEvent::SCHEMA_VERSION,
+            UPCASTERS.current_version(Prostitute::ENTITY_NAME)
+        );
+    }
+
+    #[test]
+    fn test_replay_v1_fixture() {
+        let mut entity = Prostitute::default();
+        for e in fixture(include_str!("fixtures/prostitute_v1.json")) {
+            entity.apply(ProstituteEvent::try_from(e).unwrap());
+        }
+        let events = entity.pop_all();
+        assert_eq!(
+            events.last(),
+            Some(&ProstituteEvent::ProstituteNameChanged {
+                id: 100.into(),
+                name: "さくらこ".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_current_version_is_not_upcast() {
+        let mut events = fixture(
+            &json!([{
+                "stream_id": "prostitute-100",
+                "revision": 2,
+                "event_type": "ProstituteNameChanged",
+                "data": { "name": "さくら" },
+                "custom_metadata": {
+                    "actorId": 1,
+                    "role": "Manager",
+                    "timestamp": "2023-05-01T12:00:00Z",
+                    "$correlationId": "0a9b3a4e-6f7c-4d2b-9d1e-6b1f3c2a4e5d",
+                    "schemaVersion": 2,
+                },
+            }])
+            .to_string(),
+        );
+        let envelope = EventEnvelope::<ProstituteEvent>::try_from(events.remove(0)).unwrap();
+        assert_eq!(
+            envelope.event,
+            ProstituteEvent::ProstituteNameChanged {
+                id: 100.into(),
+                name: "さくら".to_owned(),
+            }
+        );
+        assert!(envelope.metadata.is_some());
+    }
+}
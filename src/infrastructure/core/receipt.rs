@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eventstore::{AppendToStreamOptions, Client, ExpectedRevision, ResolvedEvent};
+
+use crate::domain::core::{ReceiptBook, ReceiptBookEvent, ReceiptBookId, ReceiptBookRepository};
+use crate::domain::{Aggregation, DataAccessError, Entity, EventEnvelope, Metadata};
+use crate::infrastructure::{
+    find_by_id_while, find_by_id_with_revision, from_event, try_from_resolved_event, Revisions,
+};
+use crate::infrastructure::{stream_name, EventConvertError};
+
+#[derive(Clone)]
+pub struct EventStoreReceiptBookRepository {
+    client: Client,
+    revisions: Revisions,
+}
+
+impl EventStoreReceiptBookRepository {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            revisions: Revisions::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl ReceiptBookRepository for EventStoreReceiptBookRepository {
+    async fn find_by_id(&self, id: ReceiptBookId) -> Result<Option<ReceiptBook>, DataAccessError> {
+        let found = find_by_id_with_revision::<ReceiptBook>(&self.client, id).await?;
+        Ok(found.map(|(entity, revision)| {
+            self.revisions.set(stream_name::<ReceiptBook>(id), revision);
+            entity
+        }))
+    }
+
+    async fn find_by_id_at(
+        &self,
+        id: ReceiptBookId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<ReceiptBook>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.created <= time).await
+    }
+
+    async fn find_by_id_at_revision(
+        &self,
+        id: ReceiptBookId,
+        revision: u64,
+    ) -> Result<Option<ReceiptBook>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.revision <= revision).await
+    }
+
+    async fn save(
+        &mut self,
+        entity: &mut ReceiptBook,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
+        let stream_name = stream_name::<ReceiptBook>(entity.id());
+        // 同じ年の領収書控えが既に作成されている場合や、読み込んだ後に他で発行された場合は失敗する
+        let rev = match entity.peek() {
+            Some(ReceiptBookEvent::ReceiptBookOpened { .. }) => ExpectedRevision::NoStream,
+            Some(_) => self.revisions.expected(&stream_name),
+            None => return Ok(false),
+        };
+        let result = self
+            .client
+            .append_to_stream(
+                &stream_name,
+                &AppendToStreamOptions::default().expected_revision(rev),
+                entity
+                    .pop_all()
+                    .into_iter()
+                    .map(|e| from_event(e, metadata))
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+        self.revisions
+            .set(stream_name, result.next_expected_version);
+        Ok(true)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for ReceiptBookEvent {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        EventEnvelope::try_from(value).map(|e| e.event)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for EventEnvelope<ReceiptBookEvent> {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        try_from_resolved_event(value)
+    }
+}
@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eventstore::{AppendToStreamOptions, Client, ExpectedRevision, ResolvedEvent};
+
+use crate::domain::core::{Reservation, ReservationEvent, ReservationId, ReservationRepository};
+use crate::domain::{Aggregation, DataAccessError, Entity, EventEnvelope, Metadata};
+use crate::infrastructure::{
+    find_by_id_while, find_by_id_with_revision, from_event, try_from_resolved_event, Revisions,
+};
+use crate::infrastructure::{stream_name, EventConvertError};
+
+#[derive(Clone)]
+pub struct EventStoreReservationRepository {
+    client: Client,
+    revisions: Revisions,
+}
+
+impl EventStoreReservationRepository {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            revisions: Revisions::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl ReservationRepository for EventStoreReservationRepository {
+    async fn find_by_id(&self, id: ReservationId) -> Result<Option<Reservation>, DataAccessError> {
+        let found = find_by_id_with_revision::<Reservation>(&self.client, id).await?;
+        Ok(found.map(|(entity, revision)| {
+            self.revisions.set(stream_name::<Reservation>(id), revision);
+            entity
+        }))
+    }
+
+    async fn find_by_id_at(
+        &self,
+        id: ReservationId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Reservation>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.created <= time).await
+    }
+
+    async fn find_by_id_at_revision(
+        &self,
+        id: ReservationId,
+        revision: u64,
+    ) -> Result<Option<Reservation>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.revision <= revision).await
+    }
+
+    async fn save(
+        &mut self,
+        entity: &mut Reservation,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
+        let stream_name = stream_name::<Reservation>(entity.id());
+        // 読み込んだ後に他で支払いや予約詳細が記録された場合は読み込み直すために失敗する
+        let rev = match entity.peek() {
+            Some(ReservationEvent::ReservationCreated { .. }) => ExpectedRevision::NoStream,
+            Some(_) => self.revisions.expected(&stream_name),
+            None => return Ok(false),
+        };
+        let result = self
+            .client
+            .append_to_stream(
+                &stream_name,
+                &AppendToStreamOptions::default().expected_revision(rev),
+                entity
+                    .pop_all()
+                    .into_iter()
+                    .map(|e| from_event(e, metadata))
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+        self.revisions
+            .set(stream_name, result.next_expected_version);
+        Ok(true)
+    }
+
+    async fn delete(
+        &mut self,
+        entity: &mut Reservation,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
+        let stream_name = stream_name::<Reservation>(entity.id());
+        self.client
+            .append_to_stream(
+                &stream_name,
+                &AppendToStreamOptions::default()
+                    .expected_revision(self.revisions.expected(&stream_name)),
+                from_event(
+                    ReservationEvent::ReservationDeleted { id: entity.id() },
+                    metadata,
+                ),
+            )
+            .await?;
+        self.client
+            .delete_stream(&stream_name, &Default::default())
+            .await?;
+        Ok(true)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for ReservationEvent {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        EventEnvelope::try_from(value).map(|e| e.event)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for EventEnvelope<ReservationEvent> {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        try_from_resolved_event(value)
+    }
+}
@@ -1,12 +1,13 @@
 use async_trait::async_trait;
-use eventstore::{AppendToStreamOptions, Client, EventData, ExpectedRevision, ResolvedEvent};
+use chrono::{DateTime, Utc};
+use eventstore::{AppendToStreamOptions, Client, ExpectedRevision, ResolvedEvent};
 
 use crate::domain::core::{
     Schedule, ScheduleEvent, ScheduleId, ScheduleRepository,
 };
-use crate::domain::{DataAccessError, Aggregation, Entity};
+use crate::domain::{Aggregation, DataAccessError, Entity, EventEnvelope, Metadata};
 use crate::infrastructure::{EventConvertError, stream_name};
-use crate::infrastructure::{from_event, try_from_resolved_event};
+use crate::infrastructure::{find_by_id_while, from_event, try_from_resolved_event};
 
 #[derive(Clone)]
 pub struct EventStoreScheduleRepository {
@@ -21,38 +22,31 @@ impl EventStoreScheduleRepository {
 
 #[async_trait]
 impl ScheduleRepository for EventStoreScheduleRepository {
-    async fn find_by_id(
+    async fn find_by_id(&self, id: ScheduleId) -> Result<Option<Schedule>, DataAccessError> {
+        find_by_id_while(&self.client, id, |_| true).await
+    }
+
+    async fn find_by_id_at(
+        &self,
+        id: ScheduleId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Schedule>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.created <= time).await
+    }
+
+    async fn find_by_id_at_revision(
         &self,
         id: ScheduleId,
+        revision: u64,
     ) -> Result<Option<Schedule>, DataAccessError> {
-        match self
-            .client
-            .read_stream(stream_name::<Schedule>(id), &Default::default())
-            .await
-        {
-            Ok(mut stream) => {
-                let mut entity = Schedule::default();
-                loop {
-                    match stream.next().await {
-                        Ok(Some(e)) => entity.apply(TryFrom::try_from(e)?),
-                        Ok(_) => break,
-                        Err(eventstore::Error::ResourceDeleted) => return Ok(None),
-                        Err(eventstore::Error::ResourceNotFound) => return Ok(None),
-                        Err(e) => return Err(e.into()),
-                    }
-                }
-                if let None = entity.peek() {
-                    Ok(None)
-                } else {
-                    entity.clear();
-                    Ok(Some(entity))
-                }
-            }
-            Err(e) => Err(e.into()),
-        }
+        find_by_id_while(&self.client, id, |e| e.revision <= revision).await
     }
 
-    async fn save(&mut self, entity: &mut Schedule) -> Result<bool, DataAccessError> {
+    async fn save(
+        &mut self,
+        entity: &mut Schedule,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
         let stream_name = stream_name::<Schedule>(entity.id());
         let rev = match entity.peek() {
             Some(ScheduleEvent::ScheduleCreated { .. }) => ExpectedRevision::NoStream,
@@ -66,19 +60,23 @@ impl ScheduleRepository for EventStoreScheduleRepository {
                 entity
                     .pop_all()
                     .into_iter()
-                    .map(EventData::from)
+                    .map(|e| from_event(e, metadata))
                     .collect::<Vec<_>>(),
             )
             .await?;
         Ok(true)
     }
 
-    async fn delete(&mut self, entity: &mut Schedule) -> Result<bool, DataAccessError> {
+    async fn delete(
+        &mut self,
+        entity: &mut Schedule,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
         let stream_name = stream_name::<Schedule>(entity.id());
         self.client.append_to_stream(
             &stream_name,
             &AppendToStreamOptions::default().expected_revision(ExpectedRevision::StreamExists),
-            EventData::from(ScheduleEvent::ScheduleDeleted { id: entity.id() }),
+            from_event(ScheduleEvent::ScheduleDeleted { id: entity.id() }, metadata),
         ).await?;
         self.client
             .delete_stream(&stream_name, &Default::default())
@@ -87,13 +85,15 @@ impl ScheduleRepository for EventStoreScheduleRepository {
     }
 }
 
-impl From<ScheduleEvent> for EventData {
-    fn from(value: ScheduleEvent) -> Self {
-        from_event(value)
+impl TryFrom<ResolvedEvent> for ScheduleEvent {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        EventEnvelope::try_from(value).map(|e| e.event)
     }
 }
 
-impl TryFrom<ResolvedEvent> for ScheduleEvent {
+impl TryFrom<ResolvedEvent> for EventEnvelope<ScheduleEvent> {
     type Error = EventConvertError;
 
     fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eventstore::{AppendToStreamOptions, Client, ExpectedRevision, ResolvedEvent};
+
+use crate::domain::core::{Tag, TagEvent, TagId, TagRepository};
+use crate::domain::{Aggregation, DataAccessError, Entity, EventEnvelope, Metadata};
+use crate::infrastructure::{find_by_id_while, from_event, try_from_resolved_event};
+use crate::infrastructure::{stream_name, EventConvertError};
+
+#[derive(Clone)]
+pub struct EventStoreTagRepository {
+    client: Client,
+}
+
+impl EventStoreTagRepository {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl TagRepository for EventStoreTagRepository {
+    async fn find_by_id(&self, id: TagId) -> Result<Option<Tag>, DataAccessError> {
+        find_by_id_while(&self.client, id, |_| true).await
+    }
+
+    async fn find_by_id_at(
+        &self,
+        id: TagId,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Tag>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.created <= time).await
+    }
+
+    async fn find_by_id_at_revision(
+        &self,
+        id: TagId,
+        revision: u64,
+    ) -> Result<Option<Tag>, DataAccessError> {
+        find_by_id_while(&self.client, id, |e| e.revision <= revision).await
+    }
+
+    async fn save(
+        &mut self,
+        entity: &mut Tag,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
+        let stream_name = stream_name::<Tag>(entity.id());
+        let rev = match entity.peek() {
+            Some(TagEvent::TagCreated { .. }) => ExpectedRevision::NoStream,
+            Some(_) => ExpectedRevision::StreamExists,
+            None => return Ok(false),
+        };
+        self.client
+            .append_to_stream(
+                &stream_name,
+                &AppendToStreamOptions::default().expected_revision(rev),
+                entity
+                    .pop_all()
+                    .into_iter()
+                    .map(|e| from_event(e, metadata))
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+        Ok(true)
+    }
+
+    async fn delete(
+        &mut self,
+        entity: &mut Tag,
+        metadata: &Metadata,
+    ) -> Result<bool, DataAccessError> {
+        let stream_name = stream_name::<Tag>(entity.id());
+        self.client
+            .append_to_stream(
+                &stream_name,
+                &AppendToStreamOptions::default().expected_revision(ExpectedRevision::StreamExists),
+                from_event(TagEvent::TagDeleted { id: entity.id() }, metadata),
+            )
+            .await?;
+        self.client
+            .delete_stream(&stream_name, &Default::default())
+            .await?;
+        Ok(true)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for TagEvent {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        EventEnvelope::try_from(value).map(|e| e.event)
+    }
+}
+
+impl TryFrom<ResolvedEvent> for EventEnvelope<TagEvent> {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        try_from_resolved_event(value)
+    }
+}
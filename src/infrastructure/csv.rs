@@ -0,0 +1,88 @@
+use crate::domain::core::{Commission, PayoutLine};
+
+/// CSVの1行として出力できるレコード
+pub trait CsvRecord {
+    /// ヘッダー
+    fn header() -> &'static [&'static str];
+    /// 各列の値
+    fn fields(&self) -> Vec<String>;
+}
+
+/// レコードをヘッダー付きのCSV（RFC 4180）に変換する
+pub fn to_csv<T: CsvRecord>(records: &[T]) -> String {
+    let mut csv = line(T::header().iter().map(|h| h.to_string()));
+    for record in records {
+        csv += &line(record.fields().into_iter());
+    }
+    csv
+}
+
+fn line<I: Iterator<Item = String>>(fields: I) -> String {
+    let mut line = fields.map(|f| escape(&f)).collect::<Vec<_>>().join(",");
+    line += "\r\n";
+    line
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+impl CsvRecord for PayoutLine {
+    fn header() -> &'static [&'static str] {
+        &[
+            "date",
+            "reservation_id",
+            "description",
+            "quantity",
+            "sales",
+            "commission",
+            "amount",
+        ]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.date.to_rfc3339(),
+            self.reservation_id
+                .map_or(String::new(), |id| id.to_string()),
+            self.description.clone(),
+            self.quantity.to_string(),
+            self.sales.amount().to_string(),
+            match &self.commission {
+                Commission::Rate(rate) => format!("{}%", rate),
+                Commission::Fixed(amount) => amount.amount().to_string(),
+            },
+            self.amount.amount().to_string(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_csv, CsvRecord};
+
+    struct Row(&'static str, i64);
+
+    impl CsvRecord for Row {
+        fn header() -> &'static [&'static str] {
+            &["name", "amount"]
+        }
+
+        fn fields(&self) -> Vec<String> {
+            vec![self.0.to_owned(), self.1.to_string()]
+        }
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let csv = to_csv(&[Row("60分コース", 15000), Row("延長, \"30分\"", 8000)]);
+        assert_eq!(
+            csv,
+            "name,amount\r\n60分コース,15000\r\n\"延長, \"\"30分\"\"\",8000\r\n"
+        );
+    }
+}
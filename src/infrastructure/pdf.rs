@@ -0,0 +1,208 @@
+use chrono::FixedOffset;
+use derive_more::{Display, Error};
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+
+use crate::domain::core::{Commission, MoneyError, PayoutStatement, Receipt};
+
+use super::text;
+
+const PAGE_WIDTH: Mm = Mm(210.0);
+const PAGE_HEIGHT: Mm = Mm(297.0);
+const MARGIN: f32 = 20.0;
+const LINE_HEIGHT: f32 = 7.0;
+/// 明細の列（日時、内容、数量、売上、歩合、支払額）の左端
+const COLUMNS: [f32; 6] = [20.0, 50.0, 110.0, 125.0, 150.0, 170.0];
+
+/// 支払明細書をPDFに変換する
+///
+/// 日時は`offset`のタイムゾーンで表示する。`font`にTrueTypeフォントを指定しない場合は組み込みフォントを使うため、日本語は表示されない。
+pub fn payout_statement(
+    statement: &PayoutStatement,
+    name: &str,
+    offset: FixedOffset,
+    font: Option<&[u8]>,
+) -> Result<Vec<u8>, PdfError> {
+    let (doc, page, layer) = PdfDocument::new("支払明細書", PAGE_WIDTH, PAGE_HEIGHT, "明細");
+    let font = match font {
+        Some(font) => doc.add_external_font(font)?,
+        None => doc.add_builtin_font(BuiltinFont::Helvetica)?,
+    };
+    let mut writer = Writer {
+        layer: doc.get_page(page).get_layer(layer),
+        font,
+        y: PAGE_HEIGHT.0 - MARGIN,
+    };
+
+    writer.text(MARGIN, "支払明細書", 16.0);
+    writer.y -= LINE_HEIGHT;
+    writer.text(MARGIN, &format!("{} 様", name), 12.0);
+    let period = &statement.period;
+    writer.text(
+        MARGIN,
+        &format!(
+            "対象期間: {} 〜 {}",
+            period.start.with_timezone(&offset).format("%Y/%m/%d %H:%M"),
+            period.end.with_timezone(&offset).format("%Y/%m/%d %H:%M")
+        ),
+        10.0,
+    );
+    writer.y -= LINE_HEIGHT;
+    writer.row(&COLUMNS, ["日時", "内容", "数量", "売上", "歩合", "支払額"]);
+    for line in &statement.lines {
+        if writer.y < MARGIN + LINE_HEIGHT {
+            let (page, layer) = doc.add_page(PAGE_WIDTH, PAGE_HEIGHT, "明細");
+            writer.layer = doc.get_page(page).get_layer(layer);
+            writer.y = PAGE_HEIGHT.0 - MARGIN;
+        }
+        let commission = match &line.commission {
+            Commission::Rate(rate) => format!("{}%", rate),
+            Commission::Fixed(amount) => amount.to_string(),
+        };
+        writer.row(
+            &COLUMNS,
+            [
+                &line
+                    .date
+                    .with_timezone(&offset)
+                    .format("%m/%d %H:%M")
+                    .to_string(),
+                &line.description,
+                &line.quantity.to_string(),
+                &line.sales.to_string(),
+                &commission,
+                &line.amount.to_string(),
+            ],
+        );
+    }
+    writer.y -= LINE_HEIGHT;
+    writer.text(COLUMNS[4], "合計", 12.0);
+    writer.text(COLUMNS[5], &statement.total()?.to_string(), 12.0);
+    Ok(doc.save_to_bytes()?)
+}
+
+/// 領収書をPDFに変換する
+///
+/// 記載内容はプレーンテキストの領収書と同じにする。
+/// 組み込みフォントは日本語を表示できないため、日本語のTrueTypeフォントの`font`を指定しない場合は失敗する。
+pub fn receipt(
+    receipt: &Receipt,
+    offset: FixedOffset,
+    font: Option<&[u8]>,
+) -> Result<Vec<u8>, PdfError> {
+    let title = receipt.kind.title();
+    let (doc, page, layer) = PdfDocument::new(title, PAGE_WIDTH, PAGE_HEIGHT, title);
+    let font = doc.add_external_font(font.ok_or(PdfError::FontRequired)?)?;
+    let mut writer = Writer {
+        layer: doc.get_page(page).get_layer(layer),
+        font,
+        y: PAGE_HEIGHT.0 - MARGIN,
+    };
+    for (i, line) in text::receipt(receipt, offset).lines().enumerate() {
+        if writer.y < MARGIN {
+            let (page, layer) = doc.add_page(PAGE_WIDTH, PAGE_HEIGHT, title);
+            writer.layer = doc.get_page(page).get_layer(layer);
+            writer.y = PAGE_HEIGHT.0 - MARGIN;
+        }
+        writer.text(MARGIN, line, if i == 0 { 16.0 } else { 10.0 });
+    }
+    Ok(doc.save_to_bytes()?)
+}
+
+/// PDF変換エラー
+#[derive(Error, Display, Debug)]
+pub enum PdfError {
+    /// PDFの生成エラー
+    #[display(fmt = "PDF error: {}", _0)]
+    Pdf(#[error(source)] printpdf::Error),
+    /// 金額の計算エラー
+    #[display(fmt = "Money error: {}", _0)]
+    MoneyError(#[error(source)] MoneyError),
+    /// 日本語のフォントが指定されていません
+    #[display(fmt = "A Japanese TrueType font is required (set [invoice] font_path)")]
+    FontRequired,
+}
+
+impl From<printpdf::Error> for PdfError {
+    fn from(value: printpdf::Error) -> Self {
+        PdfError::Pdf(value)
+    }
+}
+
+impl From<MoneyError> for PdfError {
+    fn from(value: MoneyError) -> Self {
+        PdfError::MoneyError(value)
+    }
+}
+
+struct Writer {
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    y: f32,
+}
+
+impl Writer {
+    fn text(&mut self, x: f32, text: &str, size: f32) {
+        self.layer
+            .use_text(text, size, Mm(x), Mm(self.y), &self.font);
+        self.y -= LINE_HEIGHT;
+    }
+
+    fn row<const N: usize>(&mut self, columns: &[f32; N], cells: [&str; N]) {
+        for (x, cell) in columns.iter().zip(cells) {
+            self.layer
+                .use_text(cell, 9.0, Mm(*x), Mm(self.y), &self.font);
+        }
+        self.y -= LINE_HEIGHT;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, TimeZone, Utc};
+
+    use crate::domain::core::{Commission, Currency, Money, PayoutLine, PayoutStatement};
+
+    use super::{payout_statement, receipt, PdfError};
+    use crate::infrastructure::text::tests::reissued_receipt;
+
+    #[test]
+    fn test_payout_statement() {
+        let start = Utc.with_ymd_and_hms(2023, 4, 1, 0, 0, 0).unwrap();
+        let statement = PayoutStatement {
+            prostitute_id: 1.into(),
+            period: start..Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap(),
+            lines: (0..100)
+                .map(|i| PayoutLine {
+                    date: start,
+                    reservation_id: Some(i.into()),
+                    description: "60 min".to_owned(),
+                    quantity: 1,
+                    sales: Money::new(20000, Currency::JPY),
+                    commission: Commission::Rate(50),
+                    amount: Money::new(10000, Currency::JPY),
+                })
+                .collect(),
+            currency: Currency::JPY,
+        };
+        let pdf = payout_statement(
+            &statement,
+            "Ai",
+            FixedOffset::east_opt(9 * 3600).unwrap(),
+            None,
+        )
+        .unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+
+    #[test]
+    fn test_receipt() {
+        assert!(matches!(
+            receipt(
+                &reissued_receipt(),
+                FixedOffset::east_opt(9 * 3600).unwrap(),
+                None,
+            ),
+            Err(PdfError::FontRequired)
+        ));
+    }
+}
@@ -0,0 +1,489 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eventstore::{Client, Position, ReadAllOptions, ResolvedEvent, StreamPosition};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::error;
+
+use crate::domain::core::CoreEvent;
+
+use super::EventConvertError;
+
+pub type ProjectionError = Box<dyn Error + Send + Sync>;
+
+/// 追い上げ中にチェックポイントを保存する間隔（イベント数）
+const CATCH_UP_FLUSH_EVERY: u64 = 1000;
+
+/// 投影するイベント
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectedEvent {
+    /// `$all`の位置
+    pub position: Position,
+    /// ストリーム名
+    pub stream_id: String,
+    /// リビジョン
+    pub revision: u64,
+    /// 書き込み日時
+    pub created: DateTime<Utc>,
+    /// イベント
+    pub event: CoreEvent,
+}
+
+impl TryFrom<ResolvedEvent> for ProjectedEvent {
+    type Error = EventConvertError;
+
+    fn try_from(value: ResolvedEvent) -> Result<Self, Self::Error> {
+        let recorded = value.get_original_event();
+        let position = recorded.position;
+        let stream_id = recorded.stream_id.clone();
+        let revision = recorded.revision;
+        let created = recorded.created;
+        Ok(Self {
+            position,
+            stream_id,
+            revision,
+            created,
+            event: CoreEvent::try_from(value)?,
+        })
+    }
+}
+
+/// `a`が`b`より後の位置であれば`true`を返す
+pub fn is_after(a: &Position, b: &Position) -> bool {
+    (a.commit, a.prepare) > (b.commit, b.prepare)
+}
+
+/// 投影
+#[async_trait]
+pub trait Projection: Send {
+    /// 投影名（チェックポイントの識別に使用する）
+    fn name(&self) -> &str;
+    /// イベントを投影する
+    async fn project(&mut self, event: &ProjectedEvent) -> Result<(), ProjectionError>;
+    /// 溜めている書き込みを確定する
+    async fn flush(&mut self) -> Result<(), ProjectionError> {
+        Ok(())
+    }
+}
+
+/// 投影ごとのチェックポイントストア
+#[async_trait]
+pub trait ProjectionCheckpointStore: Send + Sync {
+    /// チェックポイントを読み込む（未保存の場合は`None`）
+    async fn load(&self, name: &str) -> Result<Option<Position>, ProjectionError>;
+    /// チェックポイントを保存する
+    async fn save(&mut self, name: &str, position: Position) -> Result<(), ProjectionError>;
+}
+
+/// メモリ上のチェックポイントストア
+#[derive(Default)]
+pub struct InMemoryCheckpointStore {
+    positions: HashMap<String, Position>,
+}
+
+#[async_trait]
+impl ProjectionCheckpointStore for InMemoryCheckpointStore {
+    async fn load(&self, name: &str) -> Result<Option<Position>, ProjectionError> {
+        Ok(self.positions.get(name).copied())
+    }
+
+    async fn save(&mut self, name: &str, position: Position) -> Result<(), ProjectionError> {
+        self.positions.insert(name.to_owned(), position);
+        Ok(())
+    }
+}
+
+/// JSONファイルのチェックポイントストア（投影名をキーとしたオブジェクトで保存する）
+pub struct JsonFileCheckpointStore {
+    path: PathBuf,
+}
+
+impl JsonFileCheckpointStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    async fn read(&self) -> Result<HashMap<String, Position>, ProjectionError> {
+        match fs::read(&self.path).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl ProjectionCheckpointStore for JsonFileCheckpointStore {
+    async fn load(&self, name: &str) -> Result<Option<Position>, ProjectionError> {
+        Ok(self.read().await?.get(name).copied())
+    }
+
+    async fn save(&mut self, name: &str, position: Position) -> Result<(), ProjectionError> {
+        let mut positions = self.read().await?;
+        positions.insert(name.to_owned(), position);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&positions)?).await?;
+        fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+struct Registered {
+    projection: Box<dyn Projection>,
+    /// 投影済みの位置
+    position: Option<Position>,
+    /// 保存済みの位置
+    saved: Option<Position>,
+    /// 失敗した場合は再起動するまで停止する（チェックポイント以降を投影し直すため）
+    failed: bool,
+}
+
+/// 複数の投影にイベントを配り、投影ごとのチェックポイントを管理する
+pub struct Projector {
+    projections: Vec<Registered>,
+    store: Box<dyn ProjectionCheckpointStore>,
+}
+
+impl Projector {
+    pub fn new<S: ProjectionCheckpointStore + 'static>(store: S) -> Self {
+        Self {
+            projections: Vec::new(),
+            store: Box::new(store),
+        }
+    }
+
+    /// 投影を追加する
+    pub fn with<P: Projection + 'static>(mut self, projection: P) -> Self {
+        self.projections.push(Registered {
+            projection: Box::new(projection),
+            position: None,
+            saved: None,
+            failed: false,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.projections.is_empty()
+    }
+
+    /// 各投影のチェックポイントを読み込む
+    pub async fn load(&mut self) -> Result<(), ProjectionError> {
+        for p in self.projections.iter_mut() {
+            p.position = self.store.load(p.projection.name()).await?;
+            p.saved = p.position;
+            p.failed = false;
+        }
+        Ok(())
+    }
+
+    /// 最も遅れている投影の位置を返す（投影が1つもなければ`None`、最初から投影する投影があれば`Some(None)`）
+    pub fn position(&self) -> Option<Option<Position>> {
+        self.projections
+            .iter()
+            .map(|p| p.position)
+            .reduce(|a, b| match (a, b) {
+                (Some(a), Some(b)) => Some(if is_after(&a, &b) { b } else { a }),
+                _ => None,
+            })
+    }
+
+    /// イベントを各投影に配る（チェックポイント以前のイベントは投影済みとして読み飛ばす）
+    ///
+    /// 失敗した投影は他の投影を止めずに停止し、失敗した投影名とエラーを返す。
+    pub async fn project(&mut self, event: &ProjectedEvent) -> Vec<(String, ProjectionError)> {
+        let mut errors = Vec::new();
+        for p in self.projections.iter_mut() {
+            if p.failed
+                || p.position
+                    .is_some_and(|pos| !is_after(&event.position, &pos))
+            {
+                continue;
+            }
+            match p.projection.project(event).await {
+                Ok(()) => p.position = Some(event.position),
+                Err(e) => {
+                    error!("投影エラー（{}）: {}", p.projection.name(), e);
+                    p.failed = true;
+                    errors.push((p.projection.name().to_owned(), e));
+                }
+            }
+        }
+        errors
+    }
+
+    /// 最も遅れている投影の位置から`$all`の末尾まで投影する
+    pub async fn catch_up(&mut self, client: &Client) -> Result<(), ProjectionError> {
+        let from = match self.position() {
+            Some(from) => from,
+            None => return Ok(()),
+        };
+        let options = ReadAllOptions::default().position(match from {
+            Some(position) => StreamPosition::Position(position),
+            None => StreamPosition::Start,
+        });
+        let mut stream = client.read_all(&options).await?;
+        let mut count = 0;
+        while let Some(resolved) = stream.next().await? {
+            // 投影対象外のイベントは変換できないため読み飛ばす
+            if let Ok(event) = ProjectedEvent::try_from(resolved) {
+                self.project(&event).await;
+                count += 1;
+                if count % CATCH_UP_FLUSH_EVERY == 0 {
+                    self.flush().await?;
+                }
+            }
+        }
+        self.flush().await
+    }
+
+    /// 各投影の書き込みを確定してチェックポイントを保存する
+    pub async fn flush(&mut self) -> Result<(), ProjectionError> {
+        for p in self.projections.iter_mut() {
+            if p.position == p.saved {
+                continue;
+            }
+            p.projection.flush().await?;
+            if let Some(position) = p.position {
+                self.store.save(p.projection.name(), position).await?;
+            }
+            p.saved = p.position;
+        }
+        Ok(())
+    }
+}
+
+/// メモリ上の読み取りモデル
+///
+/// イベントを関数で畳み込んだ状態を保持する。テストや小規模な集計に使用する。
+pub struct InMemoryProjection<S> {
+    name: String,
+    state: Arc<Mutex<S>>,
+    reducer: fn(&mut S, &ProjectedEvent),
+}
+
+impl<S> InMemoryProjection<S> {
+    pub fn new(name: &str, state: S, reducer: fn(&mut S, &ProjectedEvent)) -> Self {
+        Self {
+            name: name.to_owned(),
+            state: Arc::new(Mutex::new(state)),
+            reducer,
+        }
+    }
+
+    /// 状態を取得する（`Projector`に追加した後も参照できるよう共有する）
+    pub fn state(&self) -> Arc<Mutex<S>> {
+        self.state.clone()
+    }
+}
+
+#[async_trait]
+impl<S: Send + 'static> Projection for InMemoryProjection<S> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn project(&mut self, event: &ProjectedEvent) -> Result<(), ProjectionError> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        (self.reducer)(&mut state, event);
+        Ok(())
+    }
+}
+
+/// イベントをJSON Lines形式のファイルに書き出す投影
+pub struct JsonFileProjection {
+    name: String,
+    path: PathBuf,
+    buffer: String,
+}
+
+impl JsonFileProjection {
+    pub fn new<P: Into<PathBuf>>(name: &str, path: P) -> Self {
+        Self {
+            name: name.to_owned(),
+            path: path.into(),
+            buffer: String::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Projection for JsonFileProjection {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn project(&mut self, event: &ProjectedEvent) -> Result<(), ProjectionError> {
+        self.buffer += &serde_json::to_string(event)?;
+        self.buffer.push('\n');
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ProjectionError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(self.buffer.as_bytes()).await?;
+        file.flush().await?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use eventstore::Position;
+    use uuid::Uuid;
+
+    use crate::domain::core::{CoreEvent, ExtraServiceEvent};
+
+    use super::{
+        InMemoryCheckpointStore, InMemoryProjection, JsonFileCheckpointStore, JsonFileProjection,
+        ProjectedEvent, Projection, ProjectionCheckpointStore, ProjectionError, Projector,
+    };
+
+    fn events() -> Vec<ProjectedEvent> {
+        (1..=3)
+            .map(|i| ProjectedEvent {
+                position: Position {
+                    commit: i * 100,
+                    prepare: i * 100,
+                },
+                stream_id: "extra_service-1".to_owned(),
+                revision: i - 1,
+                created: Utc::now(),
+                event: CoreEvent::ExtraServiceEvent(ExtraServiceEvent::ExtraServiceNameChanged {
+                    id: 1.into(),
+                    name: format!("延長{}", i),
+                }),
+            })
+            .collect()
+    }
+
+    fn count(state: &mut HashMap<String, u64>, event: &ProjectedEvent) {
+        *state.entry(event.stream_id.clone()).or_default() += 1;
+    }
+
+    struct Failing;
+
+    #[async_trait]
+    impl Projection for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn project(&mut self, event: &ProjectedEvent) -> Result<(), ProjectionError> {
+            match event.revision {
+                1 => Err("失敗".into()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_projector() {
+        let mut store = InMemoryCheckpointStore::default();
+        store
+            .save(
+                "behind",
+                Position {
+                    commit: 100,
+                    prepare: 100,
+                },
+            )
+            .await
+            .unwrap();
+        let fresh = InMemoryProjection::new("fresh", HashMap::new(), count);
+        let behind = InMemoryProjection::new("behind", HashMap::new(), count);
+        let (fresh_state, behind_state) = (fresh.state(), behind.state());
+        let mut projector = Projector::new(store).with(fresh).with(behind).with(Failing);
+        projector.load().await.unwrap();
+        assert_eq!(projector.position(), Some(None));
+
+        let mut errors = Vec::new();
+        for event in events() {
+            errors.extend(projector.project(&event).await);
+        }
+        projector.flush().await.unwrap();
+
+        // チェックポイント以前のイベントは読み飛ばし、失敗した投影は他の投影を止めない
+        assert_eq!(fresh_state.lock().unwrap()["extra_service-1"], 3);
+        assert_eq!(behind_state.lock().unwrap()["extra_service-1"], 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "failing");
+
+        // 失敗した投影は最後に成功した位置から再開する
+        projector.load().await.unwrap();
+        assert_eq!(
+            projector.position(),
+            Some(Some(Position {
+                commit: 100,
+                prepare: 100
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_file() {
+        let dir = std::env::temp_dir().join(format!("dely-{}", Uuid::new_v4()));
+        let mut projector =
+            Projector::new(JsonFileCheckpointStore::new(dir.join("checkpoints.json")))
+                .with(JsonFileProjection::new("export", dir.join("events.jsonl")));
+        projector.load().await.unwrap();
+        for event in events() {
+            assert!(projector.project(&event).await.is_empty());
+        }
+        projector.flush().await.unwrap();
+
+        let text = tokio::fs::read_to_string(dir.join("events.jsonl"))
+            .await
+            .unwrap();
+        let exported = text
+            .lines()
+            .map(|l| serde_json::from_str::<ProjectedEvent>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            exported,
+            events()
+                .into_iter()
+                .map(|mut e| {
+                    e.created = exported[e.revision as usize].created;
+                    e
+                })
+                .collect::<Vec<_>>()
+        );
+
+        let store = JsonFileCheckpointStore::new(dir.join("checkpoints.json"));
+        assert_eq!(
+            store.load("export").await.unwrap(),
+            Some(Position {
+                commit: 300,
+                prepare: 300
+            })
+        );
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,1854 @@
+use std::{fs, ops::Range, path::Path};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use rusqlite::{
+    params,
+    types::{FromSql, FromSqlError, FromSqlResult, ValueRef},
+    Connection, OptionalExtension,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    core::{
+        CoreEvent, CouponEvent, CouponId, Currency, ExtraServiceEvent, Money, ProstituteEvent,
+        ProstituteId, ReservationCustomer, ReservationEvent, ReservationId, ReservationItem,
+        ScheduleEvent, ScheduleId, ShiftStatus,
+    },
+    Entity,
+};
+
+use super::{
+    csv::CsvRecord,
+    projection::{ProjectedEvent, Projection, ProjectionError},
+};
+
+/// スキーマのマイグレーション（`user_version`に適用済みの数を記録する）
+const MIGRATIONS: &[&str] = &[
+    "
+CREATE TABLE IF NOT EXISTS cast_members (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    leaved INTEGER NOT NULL DEFAULT 0,
+    deleted INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS schedules (
+    id INTEGER PRIMARY KEY,
+    prostitute_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS shifts (
+    id INTEGER PRIMARY KEY,
+    schedule_id INTEGER NOT NULL,
+    start_at TEXT NOT NULL,
+    end_at TEXT NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS extra_services (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    price INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    deleted INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS reservations (
+    id INTEGER PRIMARY KEY,
+    start_at TEXT NOT NULL,
+    end_at TEXT NOT NULL,
+    customer_type TEXT NOT NULL,
+    customer_id INTEGER,
+    customer_name TEXT,
+    customer_phone TEXT,
+    deleted INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS reservation_cast_members (
+    reservation_id INTEGER NOT NULL,
+    prostitute_id INTEGER NOT NULL,
+    PRIMARY KEY (reservation_id, prostitute_id)
+);
+CREATE TABLE IF NOT EXISTS reservation_details (
+    reservation_id INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    quantity INTEGER NOT NULL,
+    price INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    PRIMARY KEY (reservation_id, id)
+);
+CREATE INDEX IF NOT EXISTS reservations_start_at ON reservations (start_at);
+",
+    "
+ALTER TABLE reservation_details ADD COLUMN item_type TEXT;
+ALTER TABLE reservation_details ADD COLUMN item_id INTEGER;
+",
+    "
+ALTER TABLE reservations ADD COLUMN completed INTEGER NOT NULL DEFAULT 0;
+CREATE TABLE IF NOT EXISTS reservation_payments (
+    reservation_id INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    method TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    refunded INTEGER NOT NULL DEFAULT 0,
+    currency TEXT NOT NULL,
+    PRIMARY KEY (reservation_id, id)
+);
+",
+    "
+ALTER TABLE extra_services ADD COLUMN tax_category TEXT NOT NULL DEFAULT 'Standard';
+ALTER TABLE extra_services ADD COLUMN tax_inclusion TEXT NOT NULL DEFAULT 'Inclusive';
+ALTER TABLE reservation_details ADD COLUMN tax_category TEXT NOT NULL DEFAULT 'Standard';
+ALTER TABLE reservation_details ADD COLUMN tax_rate INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE reservation_details ADD COLUMN tax_inclusion TEXT NOT NULL DEFAULT 'Inclusive';
+",
+    "
+CREATE TABLE IF NOT EXISTS coupons (
+    id INTEGER PRIMARY KEY,
+    code TEXT NOT NULL,
+    name TEXT NOT NULL,
+    disabled INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS coupons_code ON coupons (code);
+CREATE TABLE IF NOT EXISTS coupon_redemptions (
+    coupon_id INTEGER NOT NULL,
+    reservation_id INTEGER NOT NULL,
+    customer_id INTEGER,
+    discount INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    redeemed_at TEXT NOT NULL,
+    canceled INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (coupon_id, reservation_id)
+);
+",
+    "
+ALTER TABLE reservation_cast_members ADD COLUMN designation TEXT NOT NULL DEFAULT 'Free';
+",
+    "
+ALTER TABLE cast_members ADD COLUMN joined_at TEXT;
+",
+    "
+DROP INDEX IF EXISTS coupons_code;
+CREATE UNIQUE INDEX IF NOT EXISTS coupons_active_code ON coupons (code) WHERE disabled = 0;
+",
+];
+
+/// SQLiteのデータベースを開き、読み取りモデルのテーブルを作成する
+pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Connection> {
+    if let Some(dir) = path.as_ref().parent() {
+        // ディレクトリが作成できない場合は`Connection::open`のエラーとして報告する
+        let _ = fs::create_dir_all(dir);
+    }
+    migrate(Connection::open(path)?)
+}
+
+/// メモリ上のSQLiteのデータベースを開き、読み取りモデルのテーブルを作成する
+pub fn open_in_memory() -> rusqlite::Result<Connection> {
+    migrate(Connection::open_in_memory()?)
+}
+
+fn migrate(mut connection: Connection) -> rusqlite::Result<Connection> {
+    let version = connection.query_row("PRAGMA user_version", [], |row| row.get::<_, usize>(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = connection.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(connection)
+}
+
+/// レポート用の正規化したテーブルをSQLiteに保持する投影
+pub struct SqliteProjection {
+    connection: Connection,
+    in_transaction: bool,
+}
+
+impl SqliteProjection {
+    pub fn new(connection: Connection) -> Self {
+        Self {
+            connection,
+            in_transaction: false,
+        }
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    fn apply(&self, event: &ProjectedEvent) -> rusqlite::Result<()> {
+        match &event.event {
+            CoreEvent::CashClosingEvent(_) => Ok(()),
+            CoreEvent::CouponEvent(event) => self.apply_coupon(event),
+            CoreEvent::ExtraServiceEvent(event) => self.apply_extra_service(event),
+            CoreEvent::MediaEvent(_) => Ok(()),
+            CoreEvent::PointLedgerEvent(_) => Ok(()),
+            CoreEvent::ProstituteEvent(e) => self.apply_prostitute(e, event.created),
+            CoreEvent::ReceiptBookEvent(_) => Ok(()),
+            CoreEvent::ReservationEvent(event) => self.apply_reservation(event),
+            CoreEvent::ScheduleEvent(event) => self.apply_schedule(event),
+            CoreEvent::TagEvent(_) => Ok(()),
+        }
+    }
+
+    fn apply_coupon(&self, event: &CouponEvent) -> rusqlite::Result<()> {
+        let c = &self.connection;
+        match event {
+            // 有効なクーポンのコードは一意にする（重複する場合は投影に失敗する）
+            CouponEvent::CouponCreated { id, code, name, .. } => c.execute(
+                "INSERT INTO coupons (id, code, name) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET code = excluded.code, name = excluded.name",
+                params![**id as i64, code, name],
+            ),
+            CouponEvent::CouponDisabled { id } => c.execute(
+                "UPDATE coupons SET disabled = 1 WHERE id = ?1",
+                params![**id as i64],
+            ),
+            CouponEvent::CouponRedeemed { id, redemption } => c.execute(
+                "INSERT OR REPLACE INTO coupon_redemptions
+                 (coupon_id, reservation_id, customer_id, discount, currency, redeemed_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    **id as i64,
+                    *redemption.reservation_id as i64,
+                    redemption.customer_id.map(|id| *id as i64),
+                    redemption.discount.amount(),
+                    currency(&redemption.discount),
+                    redemption.redeemed_at
+                ],
+            ),
+            CouponEvent::CouponRedemptionCanceled { id, reservation_id } => c.execute(
+                "UPDATE coupon_redemptions SET canceled = 1
+                 WHERE coupon_id = ?1 AND reservation_id = ?2",
+                params![**id as i64, **reservation_id as i64],
+            ),
+        }
+        .map(|_| ())
+    }
+
+    fn apply_extra_service(&self, event: &ExtraServiceEvent) -> rusqlite::Result<()> {
+        let c = &self.connection;
+        match event {
+            ExtraServiceEvent::ExtraServiceCreated {
+                id,
+                name,
+                description,
+                price,
+            } => c.execute(
+                "INSERT OR REPLACE INTO extra_services (id, name, description, price, currency)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    **id as i64,
+                    name,
+                    description,
+                    price.amount(),
+                    currency(price)
+                ],
+            ),
+            ExtraServiceEvent::ExtraServiceNameChanged { id, name } => c.execute(
+                "UPDATE extra_services SET name = ?2 WHERE id = ?1",
+                params![**id as i64, name],
+            ),
+            ExtraServiceEvent::ExtraServiceDescriptionChanged { id, description } => c.execute(
+                "UPDATE extra_services SET description = ?2 WHERE id = ?1",
+                params![**id as i64, description],
+            ),
+            ExtraServiceEvent::ExtraServicePriceChanged { id, price } => c.execute(
+                "UPDATE extra_services SET price = ?2, currency = ?3 WHERE id = ?1",
+                params![**id as i64, price.amount(), currency(price)],
+            ),
+            ExtraServiceEvent::ExtraServiceTaxChanged {
+                id,
+                tax_category,
+                tax_inclusion,
+            } => c.execute(
+                "UPDATE extra_services SET tax_category = ?2, tax_inclusion = ?3 WHERE id = ?1",
+                params![
+                    **id as i64,
+                    format!("{:?}", tax_category),
+                    format!("{:?}", tax_inclusion)
+                ],
+            ),
+            ExtraServiceEvent::ExtraServiceDeleted { id } => c.execute(
+                "UPDATE extra_services SET deleted = 1 WHERE id = ?1",
+                params![**id as i64],
+            ),
+        }
+        .map(|_| ())
+    }
+
+    fn apply_prostitute(
+        &self,
+        event: &ProstituteEvent,
+        created: DateTime<Utc>,
+    ) -> rusqlite::Result<()> {
+        let c = &self.connection;
+        match event {
+            // 登録イベントの書き込み日時を登録日時とする
+            ProstituteEvent::ProstituteJoined { id, name, .. } => c.execute(
+                "INSERT OR REPLACE INTO cast_members (id, name, joined_at) VALUES (?1, ?2, ?3)",
+                params![**id as i64, name, created],
+            ),
+            ProstituteEvent::ProstituteNameChanged { id, name } => c.execute(
+                "UPDATE cast_members SET name = ?2 WHERE id = ?1",
+                params![**id as i64, name],
+            ),
+            ProstituteEvent::ProstituteLeaved { id } => c.execute(
+                "UPDATE cast_members SET leaved = 1 WHERE id = ?1",
+                params![**id as i64],
+            ),
+            ProstituteEvent::ProstituteRejoined { id } => c.execute(
+                "UPDATE cast_members SET leaved = 0 WHERE id = ?1",
+                params![**id as i64],
+            ),
+            // 過去の売上を集計できるよう削除済みとして残す
+            ProstituteEvent::ProstituteDeleted { id } => c.execute(
+                "UPDATE cast_members SET deleted = 1 WHERE id = ?1",
+                params![**id as i64],
+            ),
+            _ => Ok(0),
+        }
+        .map(|_| ())
+    }
+
+    fn apply_schedule(&self, event: &ScheduleEvent) -> rusqlite::Result<()> {
+        let c = &self.connection;
+        match event {
+            ScheduleEvent::ScheduleCreated { id, prostitute_id } => c.execute(
+                "INSERT OR REPLACE INTO schedules (id, prostitute_id) VALUES (?1, ?2)",
+                params![**id as i64, **prostitute_id as i64],
+            ),
+            ScheduleEvent::ScheduleDeleted { id } => {
+                c.execute(
+                    "DELETE FROM shifts WHERE schedule_id = ?1",
+                    params![**id as i64],
+                )?;
+                c.execute("DELETE FROM schedules WHERE id = ?1", params![**id as i64])
+            }
+            ScheduleEvent::ScheduleShiftAdded { id, shift } => c.execute(
+                "INSERT OR REPLACE INTO shifts (id, schedule_id, start_at, end_at, status)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    *shift.id() as i64,
+                    **id as i64,
+                    shift.time().start,
+                    shift.time().end,
+                    status(shift.status())
+                ],
+            ),
+            ScheduleEvent::ScheduleShiftTimeChanged { shift_id, time } => c.execute(
+                "UPDATE shifts SET start_at = ?2, end_at = ?3 WHERE id = ?1",
+                params![**shift_id as i64, time.start, time.end],
+            ),
+            ScheduleEvent::ScheduleShiftStatusChanged {
+                shift_id,
+                status: s,
+            } => c.execute(
+                "UPDATE shifts SET status = ?2 WHERE id = ?1",
+                params![**shift_id as i64, status(*s)],
+            ),
+            ScheduleEvent::ScheduleShiftsDeleted { shift_ids } => {
+                for shift_id in shift_ids {
+                    c.execute(
+                        "DELETE FROM shifts WHERE id = ?1",
+                        params![**shift_id as i64],
+                    )?;
+                }
+                Ok(0)
+            }
+        }
+        .map(|_| ())
+    }
+
+    fn apply_reservation(&self, event: &ReservationEvent) -> rusqlite::Result<()> {
+        let c = &self.connection;
+        match event {
+            ReservationEvent::ReservationCreated {
+                id,
+                prostitute_ids,
+                time,
+                customer,
+            } => {
+                let (customer_type, customer_id, customer_name, customer_phone) = match customer {
+                    ReservationCustomer::Anonymous => ("Anonymous", None, None, None),
+                    ReservationCustomer::Registered { id } => {
+                        ("Registered", Some(**id as i64), None, None)
+                    }
+                    ReservationCustomer::Unregistered { name, phone } => {
+                        ("Unregistered", None, Some(name), Some(phone))
+                    }
+                };
+                c.execute(
+                    "INSERT OR REPLACE INTO reservations
+                     (id, start_at, end_at, customer_type, customer_id, customer_name, customer_phone)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        **id as i64,
+                        time.start,
+                        time.end,
+                        customer_type,
+                        customer_id,
+                        customer_name,
+                        customer_phone
+                    ],
+                )?;
+                for prostitute_id in prostitute_ids {
+                    c.execute(
+                        "INSERT OR IGNORE INTO reservation_cast_members (reservation_id, prostitute_id)
+                         VALUES (?1, ?2)",
+                        params![**id as i64, **prostitute_id as i64],
+                    )?;
+                }
+                Ok(())
+            }
+            ReservationEvent::ReservationTimeExtended { id, second } => {
+                let end = c
+                    .query_row(
+                        "SELECT end_at FROM reservations WHERE id = ?1",
+                        params![**id as i64],
+                        |row| row.get::<_, DateTime<Utc>>(0),
+                    )
+                    .optional()?;
+                if let Some(end) = end {
+                    c.execute(
+                        "UPDATE reservations SET end_at = ?2 WHERE id = ?1",
+                        params![**id as i64, end + Duration::seconds(*second)],
+                    )?;
+                }
+                Ok(())
+            }
+            ReservationEvent::ReservationDetailAdded { id, detail } => c
+                .execute(
+                    "INSERT OR REPLACE INTO reservation_details
+                     (reservation_id, id, name, quantity, price, currency, item_type, item_id,
+                      tax_category, tax_rate, tax_inclusion)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        **id as i64,
+                        *detail.id() as i64,
+                        detail.name(),
+                        detail.quantity(),
+                        detail.price().amount(),
+                        currency(detail.price()),
+                        detail.item().map(|item| match item {
+                            ReservationItem::Service(_) => "Service",
+                            ReservationItem::ExtraService(_) => "ExtraService",
+                            ReservationItem::Points(_) => "Points",
+                            ReservationItem::Designation(_) => "Designation",
+                            ReservationItem::Coupon(_) => "Coupon",
+                        }),
+                        detail.item().and_then(|item| match item {
+                            ReservationItem::Service(id) => Some(*id as i64),
+                            ReservationItem::ExtraService(id) => Some(*id as i64),
+                            ReservationItem::Points(id) => Some(*id as i64),
+                            ReservationItem::Designation(_) => None,
+                            ReservationItem::Coupon(id) => Some(*id as i64),
+                        }),
+                        format!("{:?}", detail.tax_category()),
+                        detail.tax_rate(),
+                        format!("{:?}", detail.tax_inclusion())
+                    ],
+                )
+                .map(|_| ()),
+            ReservationEvent::ReservationDetailDeleted { id, detail_id } => c
+                .execute(
+                    "DELETE FROM reservation_details WHERE reservation_id = ?1 AND id = ?2",
+                    params![**id as i64, **detail_id as i64],
+                )
+                .map(|_| ()),
+            ReservationEvent::ReservationDesignated {
+                id,
+                prostitute_id,
+                designation,
+                ..
+            } => c
+                .execute(
+                    "UPDATE reservation_cast_members SET designation = ?3
+                     WHERE reservation_id = ?1 AND prostitute_id = ?2",
+                    params![
+                        **id as i64,
+                        **prostitute_id as i64,
+                        format!("{:?}", designation)
+                    ],
+                )
+                .map(|_| ()),
+            ReservationEvent::ReservationPaymentReceived { id, payment } => c
+                .execute(
+                    "INSERT OR REPLACE INTO reservation_payments
+                     (reservation_id, id, method, amount, refunded, currency)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        **id as i64,
+                        *payment.id() as i64,
+                        format!("{:?}", payment.method()),
+                        payment.amount().amount(),
+                        payment.refunded().amount(),
+                        currency(payment.amount())
+                    ],
+                )
+                .map(|_| ()),
+            ReservationEvent::ReservationPaymentRefunded {
+                id,
+                payment_id,
+                amount,
+            } => c
+                .execute(
+                    "UPDATE reservation_payments SET refunded = refunded + ?3
+                     WHERE reservation_id = ?1 AND id = ?2",
+                    params![**id as i64, **payment_id as i64, amount.amount()],
+                )
+                .map(|_| ()),
+            ReservationEvent::ReservationCompleted { id, .. } => c
+                .execute(
+                    "UPDATE reservations SET completed = 1 WHERE id = ?1",
+                    params![**id as i64],
+                )
+                .map(|_| ()),
+            ReservationEvent::ReservationDeleted { id } => c
+                .execute(
+                    "UPDATE reservations SET deleted = 1 WHERE id = ?1",
+                    params![**id as i64],
+                )
+                .map(|_| ()),
+        }
+    }
+}
+
+#[async_trait]
+impl Projection for SqliteProjection {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    async fn project(&mut self, event: &ProjectedEvent) -> Result<(), ProjectionError> {
+        if !self.in_transaction {
+            self.connection.execute_batch("BEGIN")?;
+            self.in_transaction = true;
+        }
+        // 失敗したイベントの途中までの変更を残さない
+        self.connection.execute_batch("SAVEPOINT event")?;
+        match self.apply(event) {
+            Ok(()) => {
+                self.connection.execute_batch("RELEASE event")?;
+                Ok(())
+            }
+            Err(e) => {
+                self.connection
+                    .execute_batch("ROLLBACK TO event; RELEASE event")?;
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), ProjectionError> {
+        if self.in_transaction {
+            self.connection.execute_batch("COMMIT")?;
+            self.in_transaction = false;
+        }
+        Ok(())
+    }
+}
+
+fn currency(money: &Money) -> String {
+    format!("{:?}", money.currency())
+}
+
+/// 予約詳細がない予約などを集計する通貨
+fn default_currency() -> String {
+    format!("{:?}", Currency::default())
+}
+
+impl FromSql for Currency {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        serde_json::from_value(serde_json::Value::String(value.as_str()?.to_owned()))
+            .map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+fn status(status: ShiftStatus) -> String {
+    format!("{:?}", status)
+}
+
+/// 女の子ごとの月間売上
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CastMemberRevenue {
+    /// 年月（`YYYY-MM`）
+    pub month: String,
+    pub prostitute_id: u64,
+    pub name: String,
+    /// 予約数
+    pub reservations: u64,
+    /// 売上
+    pub amount: i64,
+    /// 売上の通貨
+    pub currency: Currency,
+}
+
+/// 予約ごとの合計金額
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReservationTotal {
+    pub reservation_id: u64,
+    pub start_at: DateTime<Utc>,
+    pub customer_type: String,
+    pub amount: i64,
+    pub currency: Currency,
+}
+
+/// 集計期間
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportPeriod {
+    /// 日次（`YYYY-MM-DD`）
+    Daily,
+    /// 週次（月曜日の`YYYY-MM-DD`）
+    Weekly,
+    /// 月次（`YYYY-MM`）
+    Monthly,
+}
+
+impl ReportPeriod {
+    fn expression(&self) -> &'static str {
+        match self {
+            ReportPeriod::Daily => "strftime('%Y-%m-%d', r.start_at, ?3)",
+            ReportPeriod::Weekly => "date(r.start_at, ?3, 'weekday 0', '-6 days')",
+            ReportPeriod::Monthly => "strftime('%Y-%m', r.start_at, ?3)",
+        }
+    }
+}
+
+/// 売上の内訳
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportDimension {
+    /// 合計
+    Total,
+    /// 女の子
+    CastMember,
+    /// サービスコース
+    ServiceCourse,
+    /// オプションサービス
+    ExtraService,
+    /// お客様の種類（登録済み・未登録）
+    CustomerType,
+}
+
+/// 売上
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SalesRow {
+    /// 集計期間
+    pub period: String,
+    /// 内訳のキー（女の子ID、サービスID、お客様の種類など）
+    pub key: String,
+    /// 内訳の表示名
+    pub label: String,
+    /// 予約数
+    pub reservations: u64,
+    /// 売上
+    pub amount: i64,
+    /// 売上の通貨
+    pub currency: Currency,
+}
+
+impl CsvRecord for SalesRow {
+    fn header() -> &'static [&'static str] {
+        &[
+            "period",
+            "key",
+            "label",
+            "reservations",
+            "amount",
+            "currency",
+        ]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.period.clone(),
+            self.key.clone(),
+            self.label.clone(),
+            self.reservations.to_string(),
+            self.amount.to_string(),
+            format!("{:?}", self.currency),
+        ]
+    }
+}
+
+/// クーポンごとの利用状況
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CouponRedemptionRow {
+    pub coupon_id: u64,
+    pub code: String,
+    pub name: String,
+    /// 利用回数（取り消しを除く）
+    pub redemptions: u64,
+    /// 利用したお客様の数（未登録のお客様を除く）
+    pub customers: u64,
+    /// 取り消された回数
+    pub canceled: u64,
+    /// 割引額の合計（取り消しを除く）
+    pub discount: i64,
+    /// 割引額の通貨
+    pub currency: Currency,
+}
+
+impl CsvRecord for CouponRedemptionRow {
+    fn header() -> &'static [&'static str] {
+        &[
+            "coupon_id",
+            "code",
+            "name",
+            "redemptions",
+            "customers",
+            "canceled",
+            "discount",
+            "currency",
+        ]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.coupon_id.to_string(),
+            self.code.clone(),
+            self.name.clone(),
+            self.redemptions.to_string(),
+            self.customers.to_string(),
+            self.canceled.to_string(),
+            self.discount.to_string(),
+            format!("{:?}", self.currency),
+        ]
+    }
+}
+
+/// 女の子ごとの指名の種類別の予約数
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DesignationCount {
+    pub prostitute_id: u64,
+    pub name: String,
+    /// フリーの予約数
+    pub free: u64,
+    /// 写真指名の予約数
+    pub photo: u64,
+    /// 本指名の予約数
+    pub regular: u64,
+}
+
+impl CsvRecord for DesignationCount {
+    fn header() -> &'static [&'static str] {
+        &["prostitute_id", "name", "free", "photo", "regular"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.prostitute_id.to_string(),
+            self.name.clone(),
+            self.free.to_string(),
+            self.photo.to_string(),
+            self.regular.to_string(),
+        ]
+    }
+}
+
+/// 女の子ごとのランキング用の統計
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CastStatistics {
+    pub prostitute_id: u64,
+    pub name: String,
+    /// 予約数
+    pub reservations: u64,
+    /// 指名数（写真指名と本指名）
+    pub designations: u64,
+    /// 予約した登録済みのお客様の数
+    pub customers: u64,
+    /// 期間の終了までに2回以上予約した登録済みのお客様の数
+    pub repeat_customers: u64,
+    /// リピート率（リピートしたお客様 / 予約したお客様、お客様がいない場合は0）
+    pub repeat_ratio: f64,
+    /// 売上（複数の女の子が担当した予約はそれぞれに全額計上する）
+    pub revenue: i64,
+    /// 売上の通貨
+    pub currency: Currency,
+}
+
+impl CsvRecord for CastStatistics {
+    fn header() -> &'static [&'static str] {
+        &[
+            "prostitute_id",
+            "name",
+            "reservations",
+            "designations",
+            "customers",
+            "repeat_customers",
+            "repeat_ratio",
+            "revenue",
+            "currency",
+        ]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.prostitute_id.to_string(),
+            self.name.clone(),
+            self.reservations.to_string(),
+            self.designations.to_string(),
+            self.customers.to_string(),
+            self.repeat_customers.to_string(),
+            format!("{:.4}", self.repeat_ratio),
+            self.revenue.to_string(),
+            format!("{:?}", self.currency),
+        ]
+    }
+}
+
+/// 女の子の出勤状況
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CastAvailability {
+    pub prostitute_id: u64,
+    pub leaved: bool,
+    /// 登録日時（登録イベントの書き込み日時、記録される前に登録した場合は`None`）
+    pub joined_at: Option<DateTime<Utc>>,
+    /// 期間と重なる確定したシフトの時間（開始日時順）
+    pub shifts: Vec<Range<DateTime<Utc>>>,
+    /// 期間と重なる予約の時間（開始日時順）
+    pub reservations: Vec<Range<DateTime<Utc>>>,
+}
+
+/// 読み取りモデルに対するレポートクエリ
+pub struct Reports<'a> {
+    connection: &'a Connection,
+}
+
+impl<'a> Reports<'a> {
+    pub fn new(connection: &'a Connection) -> Self {
+        Self { connection }
+    }
+
+    /// 期間内に開始した予約の合計金額を開始日時順に通貨ごとに取得する
+    pub fn reservation_totals(
+        &self,
+        range: Range<DateTime<Utc>>,
+    ) -> rusqlite::Result<Vec<ReservationTotal>> {
+        let mut statement = self.connection.prepare(
+            "SELECT r.id, r.start_at, r.customer_type,
+                    COALESCE(SUM(d.quantity * d.price), 0), COALESCE(d.currency, ?3)
+             FROM reservations r
+             LEFT JOIN reservation_details d ON d.reservation_id = r.id
+             WHERE r.deleted = 0 AND r.start_at >= ?1 AND r.start_at < ?2
+             GROUP BY r.id, COALESCE(d.currency, ?3)
+             ORDER BY r.start_at, r.id, COALESCE(d.currency, ?3)",
+        )?;
+        let rows =
+            statement.query_map(params![range.start, range.end, default_currency()], |row| {
+                Ok(ReservationTotal {
+                    reservation_id: row.get::<_, i64>(0)? as u64,
+                    start_at: row.get(1)?,
+                    customer_type: row.get(2)?,
+                    amount: row.get(3)?,
+                    currency: row.get(4)?,
+                })
+            })?;
+        rows.collect()
+    }
+
+    /// 期間内の総売上を集計期間と内訳と通貨ごとに取得する
+    ///
+    /// 集計期間は`offset`のタイムゾーンで区切る。女の子別では、複数の女の子が担当した予約をそれぞれの女の子の売上に全額計上する。
+    /// サービスコース別とオプションサービス別は、対象が指定された予約詳細だけを集計する。
+    pub fn sales(
+        &self,
+        range: Range<DateTime<Utc>>,
+        offset: FixedOffset,
+        period: ReportPeriod,
+        dimension: ReportDimension,
+    ) -> rusqlite::Result<Vec<SalesRow>> {
+        let (columns, from, group) = match dimension {
+            ReportDimension::Total => (
+                "'total', '合計', COUNT(DISTINCT r.id), COALESCE(SUM(d.quantity * d.price), 0),
+                 COALESCE(d.currency, ?4)",
+                "reservations r LEFT JOIN reservation_details d ON d.reservation_id = r.id",
+                "period, COALESCE(d.currency, ?4)",
+            ),
+            ReportDimension::CastMember => (
+                "CAST(c.id AS TEXT), c.name, COUNT(DISTINCT r.id), COALESCE(SUM(t.amount), 0),
+                 COALESCE(t.currency, ?4)",
+                "reservations r
+                 JOIN reservation_cast_members rc ON rc.reservation_id = r.id
+                 JOIN cast_members c ON c.id = rc.prostitute_id
+                 LEFT JOIN (
+                     SELECT reservation_id, currency, SUM(quantity * price) AS amount
+                     FROM reservation_details GROUP BY reservation_id, currency
+                 ) t ON t.reservation_id = r.id",
+                "period, c.id, COALESCE(t.currency, ?4)",
+            ),
+            ReportDimension::ServiceCourse => (
+                "CAST(d.item_id AS TEXT), MAX(d.name), COUNT(DISTINCT r.id),
+                 SUM(d.quantity * d.price), COALESCE(d.currency, ?4)",
+                "reservations r
+                 JOIN reservation_details d
+                   ON d.reservation_id = r.id AND d.item_type = 'Service'",
+                "period, d.item_id, COALESCE(d.currency, ?4)",
+            ),
+            ReportDimension::ExtraService => (
+                "CAST(d.item_id AS TEXT), COALESCE(MAX(e.name), MAX(d.name)),
+                 COUNT(DISTINCT r.id), SUM(d.quantity * d.price), COALESCE(d.currency, ?4)",
+                "reservations r
+                 JOIN reservation_details d
+                   ON d.reservation_id = r.id AND d.item_type = 'ExtraService'
+                 LEFT JOIN extra_services e ON e.id = d.item_id",
+                "period, d.item_id, COALESCE(d.currency, ?4)",
+            ),
+            ReportDimension::CustomerType => (
+                "r.customer_type, r.customer_type, COUNT(DISTINCT r.id),
+                 COALESCE(SUM(d.quantity * d.price), 0), COALESCE(d.currency, ?4)",
+                "reservations r LEFT JOIN reservation_details d ON d.reservation_id = r.id",
+                "period, r.customer_type, COALESCE(d.currency, ?4)",
+            ),
+        };
+        let sql = format!(
+            "SELECT {} AS period, {}
+             FROM {}
+             WHERE r.deleted = 0 AND r.start_at >= ?1 AND r.start_at < ?2
+             GROUP BY {}
+             ORDER BY {}",
+            period.expression(),
+            columns,
+            from,
+            group,
+            group
+        );
+        let mut statement = self.connection.prepare(&sql)?;
+        let modifier = format!("{:+} seconds", offset.local_minus_utc());
+        let rows = statement.query_map(
+            params![range.start, range.end, modifier, default_currency()],
+            |row| {
+                Ok(SalesRow {
+                    period: row.get(0)?,
+                    key: row.get(1)?,
+                    label: row.get(2)?,
+                    reservations: row.get::<_, i64>(3)? as u64,
+                    amount: row.get(4)?,
+                    currency: row.get(5)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    /// 女の子の名前を取得する
+    pub fn cast_member_name(
+        &self,
+        prostitute_id: ProstituteId,
+    ) -> rusqlite::Result<Option<String>> {
+        self.connection
+            .query_row(
+                "SELECT name FROM cast_members WHERE id = ?1 AND deleted = 0",
+                params![*prostitute_id as i64],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// 女の子が担当した予約のうち、期間内に開始したもののIDを開始日時順に取得する
+    pub fn reservation_ids(
+        &self,
+        prostitute_id: ProstituteId,
+        range: Range<DateTime<Utc>>,
+    ) -> rusqlite::Result<Vec<ReservationId>> {
+        let mut statement = self.connection.prepare(
+            "SELECT r.id
+             FROM reservations r
+             JOIN reservation_cast_members rc ON rc.reservation_id = r.id
+             WHERE rc.prostitute_id = ?1 AND r.deleted = 0
+               AND r.start_at >= ?2 AND r.start_at < ?3
+             ORDER BY r.start_at, r.id",
+        )?;
+        let rows = statement.query_map(
+            params![*prostitute_id as i64, range.start, range.end],
+            |row| Ok(ReservationId::from(row.get::<_, i64>(0)? as u64)),
+        )?;
+        rows.collect()
+    }
+
+    /// コードに一致する有効なクーポンのIDを取得する
+    pub fn coupon_id(&self, code: &str) -> rusqlite::Result<Option<CouponId>> {
+        self.connection
+            .query_row(
+                "SELECT id FROM coupons WHERE code = ?1 AND disabled = 0",
+                params![code],
+                |row| Ok(CouponId::from(row.get::<_, i64>(0)? as u64)),
+            )
+            .optional()
+    }
+
+    /// 期間内に利用されたクーポンの利用状況をクーポンと割引額の通貨ごとに取得する
+    pub fn coupon_redemptions(
+        &self,
+        range: Range<DateTime<Utc>>,
+    ) -> rusqlite::Result<Vec<CouponRedemptionRow>> {
+        let mut statement = self.connection.prepare(
+            "SELECT c.id, c.code, c.name,
+                    COUNT(*) FILTER (WHERE cr.canceled = 0),
+                    COUNT(DISTINCT cr.customer_id) FILTER (WHERE cr.canceled = 0),
+                    COUNT(*) FILTER (WHERE cr.canceled = 1),
+                    COALESCE(SUM(cr.discount) FILTER (WHERE cr.canceled = 0), 0),
+                    cr.currency
+             FROM coupon_redemptions cr
+             JOIN coupons c ON c.id = cr.coupon_id
+             WHERE cr.redeemed_at >= ?1 AND cr.redeemed_at < ?2
+             GROUP BY c.id, cr.currency
+             ORDER BY c.id, cr.currency",
+        )?;
+        let rows = statement.query_map(params![range.start, range.end], |row| {
+            Ok(CouponRedemptionRow {
+                coupon_id: row.get::<_, i64>(0)? as u64,
+                code: row.get(1)?,
+                name: row.get(2)?,
+                redemptions: row.get::<_, i64>(3)? as u64,
+                customers: row.get::<_, i64>(4)? as u64,
+                canceled: row.get::<_, i64>(5)? as u64,
+                discount: row.get(6)?,
+                currency: row.get(7)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// 期間内に開始した予約の数を女の子ごとに指名の種類別に取得する
+    pub fn designation_counts(
+        &self,
+        range: Range<DateTime<Utc>>,
+    ) -> rusqlite::Result<Vec<DesignationCount>> {
+        let mut statement = self.connection.prepare(
+            "SELECT c.id, c.name,
+                    COUNT(*) FILTER (WHERE rc.designation = 'Free'),
+                    COUNT(*) FILTER (WHERE rc.designation = 'Photo'),
+                    COUNT(*) FILTER (WHERE rc.designation = 'Regular')
+             FROM reservations r
+             JOIN reservation_cast_members rc ON rc.reservation_id = r.id
+             JOIN cast_members c ON c.id = rc.prostitute_id
+             WHERE r.deleted = 0 AND r.start_at >= ?1 AND r.start_at < ?2
+             GROUP BY c.id
+             ORDER BY c.id",
+        )?;
+        let rows = statement.query_map(params![range.start, range.end], |row| {
+            Ok(DesignationCount {
+                prostitute_id: row.get::<_, i64>(0)? as u64,
+                name: row.get(1)?,
+                free: row.get::<_, i64>(2)? as u64,
+                photo: row.get::<_, i64>(3)? as u64,
+                regular: row.get::<_, i64>(4)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// 期間内に開始した予約から、在籍中の女の子ごとのランキング用の統計を売上の通貨ごとに取得する
+    ///
+    /// 期間内に予約がない女の子も既定の通貨で0件として含める。リピートは期間より前の予約も含めて、同じ女の子を2回以上予約したお客様を数える。
+    pub fn cast_statistics(
+        &self,
+        range: Range<DateTime<Utc>>,
+    ) -> rusqlite::Result<Vec<CastStatistics>> {
+        let mut statement = self.connection.prepare(
+            "WITH visits AS (
+                 SELECT rc.prostitute_id, r.customer_id, COUNT(*) AS visits
+                 FROM reservations r
+                 JOIN reservation_cast_members rc ON rc.reservation_id = r.id
+                 WHERE r.deleted = 0 AND r.customer_id IS NOT NULL AND r.start_at < ?2
+                 GROUP BY rc.prostitute_id, r.customer_id
+             ),
+             period AS (
+                 SELECT rc.prostitute_id, r.id, r.customer_id, rc.designation
+                 FROM reservations r
+                 JOIN reservation_cast_members rc ON rc.reservation_id = r.id
+                 WHERE r.deleted = 0 AND r.start_at >= ?1 AND r.start_at < ?2
+             )
+             SELECT c.id, c.name,
+                    COUNT(p.id),
+                    COUNT(p.id) FILTER (WHERE p.designation <> 'Free'),
+                    COUNT(DISTINCT p.customer_id),
+                    COUNT(DISTINCT p.customer_id) FILTER (WHERE v.visits > 1),
+                    COALESCE(SUM(t.amount), 0),
+                    COALESCE(t.currency, ?3)
+             FROM cast_members c
+             LEFT JOIN period p ON p.prostitute_id = c.id
+             LEFT JOIN visits v ON v.prostitute_id = c.id AND v.customer_id = p.customer_id
+             LEFT JOIN (
+                 SELECT reservation_id, currency, SUM(quantity * price) AS amount
+                 FROM reservation_details GROUP BY reservation_id, currency
+             ) t ON t.reservation_id = p.id
+             WHERE c.deleted = 0
+             GROUP BY c.id, COALESCE(t.currency, ?3)
+             ORDER BY c.id, COALESCE(t.currency, ?3)",
+        )?;
+        let params = params![range.start, range.end, default_currency()];
+        let rows = statement.query_map(params, |row| {
+            let customers = row.get::<_, i64>(4)? as u64;
+            let repeat_customers = row.get::<_, i64>(5)? as u64;
+            Ok(CastStatistics {
+                prostitute_id: row.get::<_, i64>(0)? as u64,
+                name: row.get(1)?,
+                reservations: row.get::<_, i64>(2)? as u64,
+                designations: row.get::<_, i64>(3)? as u64,
+                customers,
+                repeat_customers,
+                repeat_ratio: match customers {
+                    0 => 0.0,
+                    _ => repeat_customers as f64 / customers as f64,
+                },
+                revenue: row.get(6)?,
+                currency: row.get(7)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// 削除されていない女の子ごとに、期間と重なる確定したシフトと予約を取得する
+    pub fn cast_availability(
+        &self,
+        range: Range<DateTime<Utc>>,
+    ) -> rusqlite::Result<Vec<CastAvailability>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, leaved, joined_at FROM cast_members WHERE deleted = 0 ORDER BY id",
+        )?;
+        let mut casts = statement
+            .query_map([], |row| {
+                Ok(CastAvailability {
+                    prostitute_id: row.get::<_, i64>(0)? as u64,
+                    leaved: row.get(1)?,
+                    joined_at: row.get(2)?,
+                    shifts: Vec::new(),
+                    reservations: Vec::new(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut statement = self.connection.prepare(
+            "SELECT s.prostitute_id, f.start_at, f.end_at
+             FROM shifts f
+             JOIN schedules s ON s.id = f.schedule_id
+             WHERE f.status = 'Confirmed' AND f.start_at < ?2 AND f.end_at > ?1
+             ORDER BY f.start_at",
+        )?;
+        let shifts = statement.query_map(params![range.start, range.end], |row| {
+            Ok((row.get::<_, i64>(0)? as u64, row.get(1)?..row.get(2)?))
+        })?;
+        for shift in shifts {
+            let (prostitute_id, time) = shift?;
+            if let Some(cast) = casts.iter_mut().find(|c| c.prostitute_id == prostitute_id) {
+                cast.shifts.push(time);
+            }
+        }
+        let mut statement = self.connection.prepare(
+            "SELECT rc.prostitute_id, r.start_at, r.end_at
+             FROM reservations r
+             JOIN reservation_cast_members rc ON rc.reservation_id = r.id
+             WHERE r.deleted = 0 AND r.start_at < ?2 AND r.end_at > ?1
+             ORDER BY r.start_at",
+        )?;
+        let reservations = statement.query_map(params![range.start, range.end], |row| {
+            Ok((row.get::<_, i64>(0)? as u64, row.get(1)?..row.get(2)?))
+        })?;
+        for reservation in reservations {
+            let (prostitute_id, time) = reservation?;
+            if let Some(cast) = casts.iter_mut().find(|c| c.prostitute_id == prostitute_id) {
+                cast.reservations.push(time);
+            }
+        }
+        Ok(casts)
+    }
+
+    /// 女の子のスケジュールのIDを取得する
+    pub fn schedule_ids(&self, prostitute_id: ProstituteId) -> rusqlite::Result<Vec<ScheduleId>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id FROM schedules WHERE prostitute_id = ?1 ORDER BY id")?;
+        let rows = statement.query_map(params![*prostitute_id as i64], |row| {
+            Ok(ScheduleId::from(row.get::<_, i64>(0)? as u64))
+        })?;
+        rows.collect()
+    }
+
+    /// 期間内の女の子ごとの月間売上を売上の通貨ごとに取得する
+    ///
+    /// 月は`offset`のタイムゾーンで区切る。複数の女の子が担当した予約は、それぞれの女の子の売上に全額を計上する。
+    pub fn monthly_revenue_by_cast_member(
+        &self,
+        range: Range<DateTime<Utc>>,
+        offset: FixedOffset,
+    ) -> rusqlite::Result<Vec<CastMemberRevenue>> {
+        let mut statement = self.connection.prepare(
+            "SELECT strftime('%Y-%m', r.start_at, ?3) AS month, c.id, c.name,
+                    COUNT(DISTINCT r.id),
+                    COALESCE(SUM(t.amount), 0),
+                    COALESCE(t.currency, ?4)
+             FROM reservations r
+             JOIN reservation_cast_members rc ON rc.reservation_id = r.id
+             JOIN cast_members c ON c.id = rc.prostitute_id
+             LEFT JOIN (
+                 SELECT reservation_id, currency, SUM(quantity * price) AS amount
+                 FROM reservation_details GROUP BY reservation_id, currency
+             ) t ON t.reservation_id = r.id
+             WHERE r.deleted = 0 AND r.start_at >= ?1 AND r.start_at < ?2
+             GROUP BY month, c.id, COALESCE(t.currency, ?4)
+             ORDER BY month, c.id, COALESCE(t.currency, ?4)",
+        )?;
+        let modifier = format!("{:+} seconds", offset.local_minus_utc());
+        let rows = statement.query_map(
+            params![range.start, range.end, modifier, default_currency()],
+            |row| {
+                Ok(CastMemberRevenue {
+                    month: row.get(0)?,
+                    prostitute_id: row.get::<_, i64>(1)? as u64,
+                    name: row.get(2)?,
+                    reservations: row.get::<_, i64>(3)? as u64,
+                    amount: row.get(4)?,
+                    currency: row.get(5)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+    use eventstore::Position;
+
+    use crate::domain::core::{
+        CoreEvent, CouponEvent, CouponRedemption, CouponRules, Currency, CustomerId, Designation,
+        DiscountType, ExtraServiceEvent, Figure, Money, Payment, PaymentMethod, ProstituteEvent,
+        ReservationCustomer, ReservationDetail, ReservationEvent, ReservationItem, ScheduleEvent,
+        Shift, ShiftStatus,
+    };
+    use crate::infrastructure::projection::{ProjectedEvent, Projection};
+
+    use super::{open_in_memory, ReportDimension, ReportPeriod, Reports, SqliteProjection};
+
+    fn prostitute_joined(id: u64, name: &str) -> CoreEvent {
+        ProstituteEvent::ProstituteJoined {
+            id: id.into(),
+            name: name.to_owned(),
+            catchphrase: String::new(),
+            profile: String::new(),
+            message: String::new(),
+            figure: Figure::default(),
+            blood: None,
+            birthday: None,
+            questions: Vec::new(),
+            images: Vec::new(),
+            video: None,
+        }
+        .into()
+    }
+
+    fn events() -> Vec<CoreEvent> {
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        let detail = |id: u64, amount: i64| {
+            ReservationDetail::create(
+                id.into(),
+                "60分コース".to_owned(),
+                1,
+                Money::new(amount, Currency::JPY),
+            )
+            .unwrap()
+        };
+        vec![
+            prostitute_joined(1, "あい"),
+            prostitute_joined(2, "いろは"),
+            // 日本時間では4月1日の予約
+            ReservationEvent::ReservationCreated {
+                id: 10.into(),
+                prostitute_ids: vec![1.into()],
+                time: jst
+                    .with_ymd_and_hms(2023, 4, 1, 1, 0, 0)
+                    .unwrap()
+                    .with_timezone(&Utc)
+                    ..jst
+                        .with_ymd_and_hms(2023, 4, 1, 2, 0, 0)
+                        .unwrap()
+                        .with_timezone(&Utc),
+                customer: ReservationCustomer::Registered {
+                    id: CustomerId::from(5),
+                },
+            }
+            .into(),
+            ReservationEvent::ReservationDetailAdded {
+                id: 10.into(),
+                detail: detail(1, 15000),
+            }
+            .into(),
+            ReservationEvent::ReservationTimeExtended {
+                id: 10.into(),
+                second: 1800,
+            }
+            .into(),
+            ReservationEvent::ReservationPaymentReceived {
+                id: 10.into(),
+                payment: Payment::create(
+                    1.into(),
+                    PaymentMethod::Cash,
+                    Money::new(15000, Currency::JPY),
+                    None,
+                )
+                .unwrap(),
+            }
+            .into(),
+            ReservationEvent::ReservationPaymentRefunded {
+                id: 10.into(),
+                payment_id: 1.into(),
+                amount: Money::new(1000, Currency::JPY),
+            }
+            .into(),
+            ReservationEvent::ReservationCompleted {
+                id: 10.into(),
+                approved_by: Some(1.into()),
+            }
+            .into(),
+            ReservationEvent::ReservationDesignated {
+                id: 10.into(),
+                prostitute_id: 1.into(),
+                designation: Designation::Photo,
+                fee_detail_id: None,
+            }
+            .into(),
+            ReservationEvent::ReservationCreated {
+                id: 11.into(),
+                prostitute_ids: vec![1.into(), 2.into()],
+                time: Utc.with_ymd_and_hms(2023, 4, 10, 10, 0, 0).unwrap()
+                    ..Utc.with_ymd_and_hms(2023, 4, 10, 11, 0, 0).unwrap(),
+                customer: ReservationCustomer::Unregistered {
+                    name: "山田".to_owned(),
+                    phone: "09000000000".to_owned(),
+                },
+            }
+            .into(),
+            ReservationEvent::ReservationDetailAdded {
+                id: 11.into(),
+                detail: detail(1, 20000),
+            }
+            .into(),
+            ReservationEvent::ReservationDetailAdded {
+                id: 11.into(),
+                detail: detail(2, 3000),
+            }
+            .into(),
+            ReservationEvent::ReservationDetailDeleted {
+                id: 11.into(),
+                detail_id: 2.into(),
+            }
+            .into(),
+            ReservationEvent::ReservationDesignated {
+                id: 11.into(),
+                prostitute_id: 1.into(),
+                designation: Designation::Regular,
+                fee_detail_id: None,
+            }
+            .into(),
+            CouponEvent::CouponCreated {
+                id: 3.into(),
+                code: "SPRING".to_owned(),
+                name: "春の割引".to_owned(),
+                discount: DiscountType::Percentage { percentage: 10 },
+                period: range(),
+                rules: CouponRules::default(),
+            }
+            .into(),
+            coupon_redeemed(10, Some(5), 1500),
+            coupon_redeemed(11, None, 2200),
+            coupon_redeemed(12, Some(5), 1000),
+            CouponEvent::CouponRedemptionCanceled {
+                id: 3.into(),
+                reservation_id: 12.into(),
+            }
+            .into(),
+        ]
+    }
+
+    fn coupon_redeemed(reservation_id: u64, customer_id: Option<u64>, discount: i64) -> CoreEvent {
+        CouponEvent::CouponRedeemed {
+            id: 3.into(),
+            redemption: CouponRedemption {
+                reservation_id: reservation_id.into(),
+                customer_id: customer_id.map(CustomerId::from),
+                discount: Money::new(discount, Currency::JPY),
+                redeemed_at: Utc.with_ymd_and_hms(2023, 4, 1, 0, 0, 0).unwrap(),
+            },
+        }
+        .into()
+    }
+
+    async fn projection() -> SqliteProjection {
+        let mut projection = SqliteProjection::new(open_in_memory().unwrap());
+        for (i, event) in events().into_iter().enumerate() {
+            let event = ProjectedEvent {
+                position: Position {
+                    commit: i as u64,
+                    prepare: i as u64,
+                },
+                stream_id: String::new(),
+                revision: 0,
+                created: Utc::now(),
+                event,
+            };
+            projection.project(&event).await.unwrap();
+        }
+        projection.flush().await.unwrap();
+        projection
+    }
+
+    fn range() -> Range<DateTime<Utc>> {
+        Utc.with_ymd_and_hms(2023, 3, 1, 0, 0, 0).unwrap()
+            ..Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_projection() {
+        let projection = projection().await;
+        let reports = Reports::new(projection.connection());
+        let totals = reports.reservation_totals(range()).unwrap();
+        assert_eq!(
+            totals
+                .iter()
+                .map(|t| (t.reservation_id, t.amount))
+                .collect::<Vec<_>>(),
+            vec![(10, 15000), (11, 20000)]
+        );
+        assert_eq!(totals[0].customer_type, "Registered");
+        assert_eq!(
+            reports.reservation_ids(2.into(), range()).unwrap(),
+            vec![11.into()]
+        );
+        assert_eq!(
+            reports.cast_member_name(2.into()).unwrap(),
+            Some("いろは".to_owned())
+        );
+        let end = projection
+            .connection()
+            .query_row("SELECT end_at FROM reservations WHERE id = 10", [], |row| {
+                row.get::<_, chrono::DateTime<Utc>>(0)
+            })
+            .unwrap();
+        assert_eq!(end, Utc.with_ymd_and_hms(2023, 3, 31, 17, 30, 0).unwrap());
+        let payment = projection
+            .connection()
+            .query_row(
+                "SELECT p.method, p.amount - p.refunded, r.completed
+                 FROM reservation_payments p JOIN reservations r ON r.id = p.reservation_id",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, bool>(2)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(payment, ("Cash".to_owned(), 14000, true));
+
+        // 日本時間で月を区切るため、3月31日（UTC）の予約も4月に計上される
+        let revenue = reports
+            .monthly_revenue_by_cast_member(range(), FixedOffset::east_opt(9 * 3600).unwrap())
+            .unwrap();
+        assert_eq!(
+            revenue
+                .iter()
+                .map(|r| (r.month.as_str(), r.name.as_str(), r.reservations, r.amount))
+                .collect::<Vec<_>>(),
+            vec![
+                ("2023-04", "あい", 2, 35000),
+                ("2023-04", "いろは", 1, 20000)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reports_by_currency() {
+        let mut projection = projection().await;
+        let events: Vec<CoreEvent> = vec![
+            ReservationEvent::ReservationCreated {
+                id: 12.into(),
+                prostitute_ids: vec![1.into()],
+                time: Utc.with_ymd_and_hms(2023, 4, 20, 10, 0, 0).unwrap()
+                    ..Utc.with_ymd_and_hms(2023, 4, 20, 11, 0, 0).unwrap(),
+                customer: ReservationCustomer::Registered { id: 6.into() },
+            }
+            .into(),
+            ReservationEvent::ReservationDetailAdded {
+                id: 12.into(),
+                detail: ReservationDetail::create(
+                    1.into(),
+                    "60分コース".to_owned(),
+                    1,
+                    Money::new(10000, Currency::USD),
+                )
+                .unwrap(),
+            }
+            .into(),
+        ];
+        for (i, event) in events.into_iter().enumerate() {
+            let event = ProjectedEvent {
+                position: Position {
+                    commit: 100 + i as u64,
+                    prepare: 100 + i as u64,
+                },
+                stream_id: String::new(),
+                revision: 0,
+                created: Utc::now(),
+                event,
+            };
+            projection.project(&event).await.unwrap();
+        }
+        projection.flush().await.unwrap();
+        let reports = Reports::new(projection.connection());
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+
+        assert_eq!(
+            reports
+                .reservation_totals(range())
+                .unwrap()
+                .iter()
+                .map(|t| (t.reservation_id, t.amount, t.currency))
+                .collect::<Vec<_>>(),
+            vec![
+                (10, 15000, Currency::JPY),
+                (11, 20000, Currency::JPY),
+                (12, 10000, Currency::USD)
+            ]
+        );
+        // 異なる通貨の売上は合算しない
+        assert_eq!(
+            reports
+                .sales(range(), jst, ReportPeriod::Monthly, ReportDimension::Total)
+                .unwrap()
+                .iter()
+                .map(|r| (r.reservations, r.amount, r.currency))
+                .collect::<Vec<_>>(),
+            vec![(2, 35000, Currency::JPY), (1, 10000, Currency::USD)]
+        );
+        assert_eq!(
+            reports
+                .monthly_revenue_by_cast_member(range(), jst)
+                .unwrap()
+                .iter()
+                .map(|r| (r.name.as_str(), r.amount, r.currency))
+                .collect::<Vec<_>>(),
+            vec![
+                ("あい", 35000, Currency::JPY),
+                ("あい", 10000, Currency::USD),
+                ("いろは", 20000, Currency::JPY)
+            ]
+        );
+        assert_eq!(
+            reports
+                .cast_statistics(range())
+                .unwrap()
+                .iter()
+                .map(|r| (r.name.as_str(), r.reservations, r.revenue, r.currency))
+                .collect::<Vec<_>>(),
+            vec![
+                ("あい", 2, 35000, Currency::JPY),
+                ("あい", 1, 10000, Currency::USD),
+                ("いろは", 1, 20000, Currency::JPY)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_designation_counts() {
+        let projection = projection().await;
+        let rows = Reports::new(projection.connection())
+            .designation_counts(range())
+            .unwrap();
+        assert_eq!(
+            rows.iter()
+                .map(|r| (r.name.as_str(), r.free, r.photo, r.regular))
+                .collect::<Vec<_>>(),
+            vec![("あい", 0, 1, 1), ("いろは", 1, 0, 0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cast_statistics() {
+        let mut projection = projection().await;
+        // 同じお客様が同じ女の子を再度予約する
+        let event = ProjectedEvent {
+            position: Position {
+                commit: 100,
+                prepare: 100,
+            },
+            stream_id: String::new(),
+            revision: 0,
+            created: Utc::now(),
+            event: ReservationEvent::ReservationCreated {
+                id: 12.into(),
+                prostitute_ids: vec![1.into()],
+                time: Utc.with_ymd_and_hms(2023, 4, 20, 10, 0, 0).unwrap()
+                    ..Utc.with_ymd_and_hms(2023, 4, 20, 11, 0, 0).unwrap(),
+                customer: ReservationCustomer::Registered { id: 5.into() },
+            }
+            .into(),
+        };
+        projection.project(&event).await.unwrap();
+        projection.flush().await.unwrap();
+        let rows = Reports::new(projection.connection())
+            .cast_statistics(range())
+            .unwrap();
+        assert_eq!(
+            rows.iter()
+                .map(|r| (
+                    r.name.as_str(),
+                    r.reservations,
+                    r.designations,
+                    r.customers,
+                    r.repeat_customers,
+                    r.revenue
+                ))
+                .collect::<Vec<_>>(),
+            vec![("あい", 3, 2, 1, 1, 35000), ("いろは", 1, 0, 0, 0, 20000)]
+        );
+        assert_eq!(rows[0].repeat_ratio, 1.0);
+        assert_eq!(rows[1].repeat_ratio, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_cast_availability() {
+        let mut projection = projection().await;
+        let time = |h: u32| Utc.with_ymd_and_hms(2023, 4, 20, h, 0, 0).unwrap();
+        let shift = |id: u64, status: ShiftStatus| {
+            Shift::create(id.into(), time(9)..time(17), status).unwrap()
+        };
+        let events: Vec<CoreEvent> = vec![
+            ScheduleEvent::ScheduleCreated {
+                id: 1.into(),
+                prostitute_id: 1.into(),
+            }
+            .into(),
+            ScheduleEvent::ScheduleShiftAdded {
+                id: 1.into(),
+                shift: shift(1, ShiftStatus::Confirmed),
+            }
+            .into(),
+            ScheduleEvent::ScheduleCreated {
+                id: 2.into(),
+                prostitute_id: 2.into(),
+            }
+            .into(),
+            ScheduleEvent::ScheduleShiftAdded {
+                id: 2.into(),
+                shift: shift(2, ShiftStatus::Reviewing),
+            }
+            .into(),
+            ReservationEvent::ReservationCreated {
+                id: 12.into(),
+                prostitute_ids: vec![1.into()],
+                time: time(10)..time(11),
+                customer: ReservationCustomer::Anonymous,
+            }
+            .into(),
+            ProstituteEvent::ProstituteLeaved { id: 2.into() }.into(),
+        ];
+        for (i, event) in events.into_iter().enumerate() {
+            let event = ProjectedEvent {
+                position: Position {
+                    commit: 100 + i as u64,
+                    prepare: 100 + i as u64,
+                },
+                stream_id: String::new(),
+                revision: 0,
+                created: Utc::now(),
+                event,
+            };
+            projection.project(&event).await.unwrap();
+        }
+        projection.flush().await.unwrap();
+        let casts = Reports::new(projection.connection())
+            .cast_availability(time(0)..Utc.with_ymd_and_hms(2023, 4, 21, 0, 0, 0).unwrap())
+            .unwrap();
+        assert_eq!(casts.len(), 2);
+        assert!(casts.iter().all(|c| c.joined_at.is_some()));
+        assert_eq!(
+            (casts[0].leaved, &casts[0].shifts, &casts[0].reservations),
+            (false, &vec![time(9)..time(17)], &vec![time(10)..time(11)])
+        );
+        // 確定していないシフトは含めない
+        assert_eq!(
+            (casts[1].leaved, &casts[1].shifts, &casts[1].reservations),
+            (true, &vec![], &vec![])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_coupon_redemptions() {
+        let projection = projection().await;
+        let reports = Reports::new(projection.connection());
+        assert_eq!(reports.coupon_id("SPRING").unwrap(), Some(3.into()));
+        assert_eq!(reports.coupon_id("SUMMER").unwrap(), None);
+        let rows = reports.coupon_redemptions(range()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            (
+                rows[0].redemptions,
+                rows[0].customers,
+                rows[0].canceled,
+                rows[0].discount
+            ),
+            (2, 1, 1, 3700)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_coupon_code_unique() {
+        let mut projection = projection().await;
+        let created = |id: u64| -> CoreEvent {
+            CouponEvent::CouponCreated {
+                id: id.into(),
+                code: "SPRING".to_owned(),
+                name: "春の割引".to_owned(),
+                discount: DiscountType::Percentage { percentage: 10 },
+                period: range(),
+                rules: CouponRules::default(),
+            }
+            .into()
+        };
+        let events = [
+            (created(4), false),
+            (CouponEvent::CouponDisabled { id: 3.into() }.into(), true),
+            // 無効にしたクーポンのコードは再利用できる
+            (created(4), true),
+            // 同じイベントを再び投影しても重複にならない
+            (created(4), true),
+        ];
+        for (i, (event, ok)) in events.into_iter().enumerate() {
+            let event = ProjectedEvent {
+                position: Position {
+                    commit: 100 + i as u64,
+                    prepare: 100 + i as u64,
+                },
+                stream_id: String::new(),
+                revision: 0,
+                created: Utc::now(),
+                event,
+            };
+            assert_eq!(projection.project(&event).await.is_ok(), ok);
+        }
+        projection.flush().await.unwrap();
+        let reports = Reports::new(projection.connection());
+        assert_eq!(reports.coupon_id("SPRING").unwrap(), Some(4.into()));
+    }
+
+    #[tokio::test]
+    async fn test_sales() {
+        let mut projection = projection().await;
+        let detail = |id: u64, name: &str, amount: i64, item: ReservationItem| {
+            ReservationDetail::create(
+                id.into(),
+                name.to_owned(),
+                1,
+                Money::new(amount, Currency::JPY),
+            )
+            .unwrap()
+            .with_item(item)
+        };
+        let events: Vec<CoreEvent> = vec![
+            ExtraServiceEvent::ExtraServiceCreated {
+                id: 7.into(),
+                name: "ローション".to_owned(),
+                description: String::new(),
+                price: Money::new(2000, Currency::JPY),
+            }
+            .into(),
+            ReservationEvent::ReservationCreated {
+                id: 12.into(),
+                prostitute_ids: vec![2.into()],
+                time: Utc.with_ymd_and_hms(2023, 4, 20, 10, 0, 0).unwrap()
+                    ..Utc.with_ymd_and_hms(2023, 4, 20, 11, 0, 0).unwrap(),
+                customer: ReservationCustomer::Registered { id: 6.into() },
+            }
+            .into(),
+            ReservationEvent::ReservationDetailAdded {
+                id: 12.into(),
+                detail: detail(1, "60分コース", 20000, ReservationItem::Service(1.into())),
+            }
+            .into(),
+            ReservationEvent::ReservationDetailAdded {
+                id: 12.into(),
+                detail: detail(
+                    2,
+                    "オプション",
+                    2000,
+                    ReservationItem::ExtraService(7.into()),
+                ),
+            }
+            .into(),
+        ];
+        for (i, event) in events.into_iter().enumerate() {
+            let event = ProjectedEvent {
+                position: Position {
+                    commit: 100 + i as u64,
+                    prepare: 100 + i as u64,
+                },
+                stream_id: String::new(),
+                revision: 0,
+                created: Utc::now(),
+                event,
+            };
+            projection.project(&event).await.unwrap();
+        }
+        projection.flush().await.unwrap();
+        let reports = Reports::new(projection.connection());
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        let sales = |period, dimension| {
+            reports
+                .sales(range(), jst, period, dimension)
+                .unwrap()
+                .into_iter()
+                .map(|r| (r.period, r.label, r.reservations, r.amount))
+                .collect::<Vec<_>>()
+        };
+        let row = |period: &str, label: &str, reservations, amount| {
+            (period.to_owned(), label.to_owned(), reservations, amount)
+        };
+
+        assert_eq!(
+            sales(ReportPeriod::Daily, ReportDimension::Total),
+            vec![
+                row("2023-04-01", "合計", 1, 15000),
+                row("2023-04-10", "合計", 1, 20000),
+                row("2023-04-20", "合計", 1, 22000)
+            ]
+        );
+        // 4月1日は土曜日のため、3月27日（月曜日）の週に計上される
+        assert_eq!(
+            sales(ReportPeriod::Weekly, ReportDimension::Total),
+            vec![
+                row("2023-03-27", "合計", 1, 15000),
+                row("2023-04-10", "合計", 1, 20000),
+                row("2023-04-17", "合計", 1, 22000)
+            ]
+        );
+        // サービスコースやオプションサービスと紐付いていない明細は含めない
+        assert_eq!(
+            sales(ReportPeriod::Monthly, ReportDimension::ServiceCourse),
+            vec![row("2023-04", "60分コース", 1, 20000)]
+        );
+        assert_eq!(
+            sales(ReportPeriod::Monthly, ReportDimension::ExtraService),
+            vec![row("2023-04", "ローション", 1, 2000)]
+        );
+        assert_eq!(
+            sales(ReportPeriod::Monthly, ReportDimension::CustomerType),
+            vec![
+                row("2023-04", "Registered", 2, 37000),
+                row("2023-04", "Unregistered", 1, 20000)
+            ]
+        );
+        assert_eq!(
+            sales(ReportPeriod::Monthly, ReportDimension::CastMember),
+            vec![
+                row("2023-04", "あい", 2, 35000),
+                row("2023-04", "いろは", 2, 42000)
+            ]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt::Write;
+
+use chrono::FixedOffset;
+
+use crate::domain::core::{Receipt, ReceiptKind, TaxCategory, TaxInclusion};
+
+/// 領収書をプレーンテキストに変換する
+///
+/// 日時は`offset`のタイムゾーンで表示する。軽減税率の対象の明細には`※`を付ける。
+pub fn receipt(receipt: &Receipt, offset: FixedOffset) -> String {
+    let mut text = String::new();
+    // `String`への書き込みは失敗しない
+    let _ = write_receipt(&mut text, receipt, offset);
+    text
+}
+
+fn write_receipt(text: &mut String, receipt: &Receipt, offset: FixedOffset) -> std::fmt::Result {
+    match receipt.is_reissued() {
+        true => writeln!(text, "{}（再発行）", receipt.kind.title())?,
+        false => writeln!(text, "{}", receipt.kind.title())?,
+    }
+    writeln!(text, "No. {}", receipt.display_number())?;
+    writeln!(text, "{} 様", receipt.addressee)?;
+    writeln!(text)?;
+    writeln!(text, "合計 {}（税込）", receipt.total)?;
+    match receipt.kind {
+        ReceiptKind::Receipt => writeln!(text, "上記正に領収いたしました。")?,
+        ReceiptKind::Invoice => writeln!(text, "下記の通りご請求申し上げます。")?,
+    }
+    writeln!(
+        text,
+        "取引日 {}",
+        receipt
+            .transaction_date
+            .with_timezone(&offset)
+            .format("%Y/%m/%d")
+    )?;
+    writeln!(text)?;
+    for line in &receipt.lines {
+        let reduced = match line.tax_category {
+            TaxCategory::Reduced => "※",
+            TaxCategory::Standard | TaxCategory::Exempt => "",
+        };
+        let inclusion = match line.tax_inclusion {
+            TaxInclusion::Inclusive => "税込",
+            TaxInclusion::Exclusive => "税抜",
+        };
+        writeln!(
+            text,
+            "{}{} x{} {}（{}）",
+            line.name, reduced, line.quantity, line.amount, inclusion
+        )?;
+    }
+    if receipt
+        .lines
+        .iter()
+        .any(|l| l.tax_category == TaxCategory::Reduced)
+    {
+        writeln!(text, "※は軽減税率対象")?;
+    }
+    writeln!(text)?;
+    for breakdown in &receipt.tax_breakdown {
+        let label = match breakdown.category {
+            TaxCategory::Standard => format!("{}%対象", breakdown.rate),
+            TaxCategory::Reduced => format!("{}%対象※", breakdown.rate),
+            TaxCategory::Exempt => "非課税".to_owned(),
+        };
+        writeln!(
+            text,
+            "{} {}（税抜 {} 消費税 {}）",
+            label,
+            breakdown
+                .total()
+                .map_or_else(|e| e.to_string(), |total| total.to_string()),
+            breakdown.taxable,
+            breakdown.tax
+        )?;
+    }
+    writeln!(text)?;
+    writeln!(
+        text,
+        "発行日 {}",
+        receipt.issued_at.with_timezone(&offset).format("%Y/%m/%d")
+    )?;
+    writeln!(text, "{}", receipt.issuer.name)?;
+    writeln!(text, "登録番号 {}", receipt.issuer.registration_number)?;
+    if let Some(address) = &receipt.issuer.address {
+        writeln!(text, "{}", address)?;
+    }
+    if let Some(phone) = &receipt.issuer.phone {
+        writeln!(text, "TEL {}", phone)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::{Duration, FixedOffset, TimeZone, Utc};
+
+    use crate::domain::core::{
+        Currency, Issuer, Money, Receipt, ReceiptBook, ReceiptKind, Reservation,
+        ReservationCustomer, ReservationDetail, TaxCategory, TaxInclusion, TaxTable,
+    };
+
+    use super::receipt;
+
+    /// 標準税率と軽減税率の明細を含む再発行済みの領収書
+    pub(crate) fn reissued_receipt() -> Receipt {
+        let start = Utc.with_ymd_and_hms(2023, 11, 1, 10, 0, 0).unwrap();
+        let mut reservation = Reservation::create(
+            1.into(),
+            vec![1.into()],
+            start..start + Duration::hours(1),
+            ReservationCustomer::Registered { id: 1.into() },
+        )
+        .unwrap();
+        for (id, name, price, category, rate) in [
+            (1, "60分コース", 22000, TaxCategory::Standard, 10),
+            (2, "ドリンク", 540, TaxCategory::Reduced, 8),
+        ] {
+            reservation
+                .add_detail(
+                    ReservationDetail::create(
+                        id.into(),
+                        name.to_owned(),
+                        1,
+                        Money::new(price, Currency::JPY),
+                    )
+                    .unwrap()
+                    .with_tax(category, rate, TaxInclusion::Inclusive),
+                    &TaxTable::default(),
+                )
+                .unwrap();
+        }
+        let mut book = ReceiptBook::open(2023.into());
+        book.issue(
+            ReceiptKind::Receipt,
+            &reservation,
+            Issuer {
+                name: "デリヘル".to_owned(),
+                registration_number: "T9234567890123".to_owned(),
+                address: None,
+                phone: Some("03-0000-0000".to_owned()),
+            },
+            "株式会社テスト".to_owned(),
+            start + Duration::days(1),
+        )
+        .unwrap();
+        book.reissue(1, start + Duration::days(2)).unwrap().clone()
+    }
+
+    #[test]
+    fn test_receipt() {
+        let text = receipt(
+            &reissued_receipt(),
+            FixedOffset::east_opt(9 * 3600).unwrap(),
+        );
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "領収書（再発行）");
+        assert_eq!(lines[1], "No. 2023-000001");
+        assert!(lines.contains(&"合計 ¥22,540（税込）"));
+        assert!(lines.contains(&"ドリンク※ x1 ¥540（税込）"));
+        assert!(lines.contains(&"10%対象 ¥22,000（税抜 ¥20,000 消費税 ¥2,000）"));
+        assert!(lines.contains(&"8%対象※ ¥540（税抜 ¥500 消費税 ¥40）"));
+        assert!(lines.contains(&"登録番号 T9234567890123"));
+    }
+}
@@ -1,6 +1,11 @@
+use chrono::FixedOffset;
 use config::{Config, ConfigError};
 use serde::Deserialize;
 
+use crate::domain::core::{
+    Billing, Currency, DesignationFees, Issuer, PayrollRules, PointPolicy, ServiceCatalog, TaxTable,
+};
+
 pub mod domain;
 pub mod infrastructure;
 
@@ -9,15 +14,58 @@ pub struct DelyConfig {
     pub eventstore: EventStore,
     pub meilisearch: MeiliSearch,
     pub logger: Logger,
+    #[serde(default)]
+    pub sync: Synchronizer,
+    #[serde(default)]
+    pub payroll: Payroll,
+    /// 消費税率表（未指定の場合は日本の消費税率）
+    #[serde(default)]
+    pub tax: TaxTable,
+    #[serde(default)]
+    pub invoice: Invoice,
+    /// 時間単位の価格（延長料金など）の課金方法
+    #[serde(default)]
+    pub billing: Billing,
+    /// ポイントの付与率と有効期限
+    #[serde(default)]
+    pub points: PointPolicy,
+    /// 写真指名・本指名の指名料
+    #[serde(default)]
+    pub designation_fees: DesignationFees,
+    /// コースなどのサービスと延長価格
+    #[serde(default)]
+    pub services: ServiceCatalog,
+    /// 一覧に表示する女の子のバッジの条件
+    #[serde(default)]
+    pub badges: Badges,
 }
 
 impl DelyConfig {
     pub fn load() -> Result<Self, ConfigError> {
-        Config::builder()
-            .add_source(config::File::with_name("dely.toml"))
-            .add_source(config::Environment::with_prefix("DELY").separator("_"))
-            .build()?
-            .try_deserialize::<DelyConfig>()
+        Self::from_config(
+            Config::builder()
+                .add_source(config::File::with_name("dely.toml"))
+                .add_source(config::Environment::with_prefix("DELY").separator("_"))
+                .build()?,
+        )
+    }
+
+    /// 以前の`[badges]`の`utc_offset_hours`は`[sync]`に指定がない場合に引き継ぐ
+    fn from_config(config: Config) -> Result<Self, ConfigError> {
+        let legacy_offset = config.get::<i32>("badges.utc_offset_hours").ok();
+        let offset = config.get::<i32>("sync.utc_offset_hours").ok();
+        let mut dely = config.try_deserialize::<DelyConfig>()?;
+        match (legacy_offset, offset) {
+            (Some(hours), None) => dely.sync.utc_offset_hours = hours,
+            (Some(_), Some(_)) => {
+                return Err(ConfigError::Message(
+                    "utc_offset_hours is set in both [badges] and [sync]; remove it from [badges]"
+                        .to_owned(),
+                ))
+            }
+            (None, _) => {}
+        }
+        Ok(dely)
     }
 }
 
@@ -32,6 +80,125 @@ pub struct MeiliSearch {
     pub api_key: String,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Synchronizer {
+    /// 失敗したイベントの最大リトライ回数
+    pub max_retries: u32,
+    /// 初回リトライまでの待機時間（ミリ秒）、以降は倍々に増やす
+    pub retry_backoff_ms: u64,
+    /// リトライ待機時間の上限（ミリ秒）
+    pub max_retry_backoff_ms: u64,
+    /// デッドレターの保存先
+    pub dead_letter_path: String,
+    /// チェックポイントの保存先の種類（未保存の場合は従来のMeilisearchのチェックポイントから引き継ぐ）
+    pub checkpoint_store: CheckpointStoreKind,
+    /// チェックポイントファイルのパス（`File`の場合）
+    pub checkpoint_path: String,
+    /// チェックポイントのストリーム名（`EventStore`の場合）
+    pub checkpoint_stream: String,
+    /// 何イベントごとにチェックポイントを保存するか
+    pub checkpoint_every: u32,
+    /// チェックポイントを保存する最大間隔（ミリ秒）
+    pub checkpoint_interval_ms: u64,
+    /// 追い上げ時に1回の更新にまとめる最大ドキュメント数
+    pub catch_up_batch_size: usize,
+    /// メトリクスを公開するアドレス
+    pub metrics_addr: String,
+    /// 投影ごとのチェックポイントファイルのパス（検索用インデックス以外）
+    pub projection_checkpoint_path: String,
+    /// イベントをJSON Lines形式で書き出すファイル（未指定の場合は書き出さない）
+    pub export_path: Option<String>,
+    /// レポート用の読み取りモデルを保持するSQLiteファイル（未指定の場合は保持しない）
+    pub sqlite_path: Option<String>,
+    /// 女の子のランキングを集計する直近の日数（SQLiteの読み取りモデルを保持する場合のみ集計する）
+    pub ranking_days: u32,
+    /// 女の子のランキングで売上を比べる通貨（他の通貨の売上は集計しない）
+    pub ranking_currency: Currency,
+    /// 日付を区切るタイムゾーンのUTCからの時差（時間）（本日のバッジや年齢の判定に使う）
+    pub utc_offset_hours: i32,
+}
+
+impl Default for Synchronizer {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            retry_backoff_ms: 200,
+            max_retry_backoff_ms: 10_000,
+            dead_letter_path: "data/sync/dead_letters.jsonl".to_owned(),
+            checkpoint_store: CheckpointStoreKind::File,
+            checkpoint_path: "data/sync/checkpoint.json".to_owned(),
+            checkpoint_stream: "dely_sync-checkpoint".to_owned(),
+            checkpoint_every: 1,
+            checkpoint_interval_ms: 1000,
+            catch_up_batch_size: 1000,
+            metrics_addr: "127.0.0.1:9464".to_owned(),
+            projection_checkpoint_path: "data/sync/projections.json".to_owned(),
+            export_path: None,
+            sqlite_path: Some("data/reports.sqlite3".to_owned()),
+            ranking_days: 30,
+            ranking_currency: Currency::default(),
+            utc_offset_hours: 9,
+        }
+    }
+}
+
+impl Synchronizer {
+    /// 日付を区切るタイムゾーン（時差が不正な場合はUTC）
+    pub fn offset(&self) -> FixedOffset {
+        FixedOffset::east_opt(self.utc_offset_hours * 3600)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap())
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Payroll {
+    /// 歩合と手当のルール
+    #[serde(flatten)]
+    pub rules: PayrollRules,
+    /// 支払明細のPDFに埋め込むTrueTypeフォント（未指定の場合は日本語を表示できない組み込みフォントを使う）
+    pub font_path: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Invoice {
+    /// 領収書に記載する発行事業者
+    #[serde(flatten)]
+    pub issuer: Issuer,
+    /// 領収書のPDFに埋め込むTrueTypeフォント
+    pub font_path: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Badges {
+    /// 「新人」を表示する登録からの日数
+    pub newcomer_days: u32,
+    /// 「残りわずか」を表示する本日の空き時間の上限（分）
+    pub few_slots_minutes: u32,
+    /// 時間の経過によるバッジの変化を反映する間隔（秒）
+    pub refresh_interval_secs: u64,
+}
+
+impl Default for Badges {
+    fn default() -> Self {
+        Self {
+            newcomer_days: 30,
+            few_slots_minutes: 120,
+            refresh_interval_secs: 300,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum CheckpointStoreKind {
+    File,
+    MeiliSearch,
+    EventStore,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Logger {
     pub level: Level,
@@ -57,3 +224,80 @@ impl From<&Level> for tracing::Level {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use config::{Config, File, FileFormat};
+
+    use super::DelyConfig;
+
+    fn load(toml: &str) -> Result<DelyConfig, config::ConfigError> {
+        let base = "
+[eventstore]
+url = \"esdb://localhost:2113?tls=false\"
+[meilisearch]
+url = \"http://localhost:7700\"
+api_key = \"\"
+[logger]
+level = \"INFO\"
+";
+        DelyConfig::from_config(
+            Config::builder()
+                .add_source(File::from_str(
+                    &format!("{}{}", base, toml),
+                    FileFormat::Toml,
+                ))
+                .build()?,
+        )
+    }
+
+    #[test]
+    fn test_utc_offset_hours() {
+        assert_eq!(load("").unwrap().sync.utc_offset_hours, 9);
+        assert_eq!(
+            load("[sync]\nutc_offset_hours = 8")
+                .unwrap()
+                .sync
+                .utc_offset_hours,
+            8
+        );
+        // 以前の`[badges]`の設定も読み込む
+        assert_eq!(
+            load("[badges]\nutc_offset_hours = 7")
+                .unwrap()
+                .sync
+                .utc_offset_hours,
+            7
+        );
+        assert!(load("[sync]\nutc_offset_hours = 8\n[badges]\nutc_offset_hours = 7").is_err());
+    }
+
+    #[test]
+    fn test_services() {
+        let config = load(
+            "
+[[services]]
+id = 1
+name = \"60分コース\"
+default_price = { amount = { amount = 20000, currency = \"JPY\" }, unit = \"OneTime\" }
+extension_price = { amount = { amount = 6000, currency = \"JPY\" }, unit = \"Hourly\" }
+
+[[services]]
+id = 2
+name = \"イベントコース\"
+default_price = { amount = { amount = 15000, currency = \"JPY\" }, unit = \"OneTime\" }
+",
+        )
+        .unwrap();
+        let service = config.services.find_by_id(1.into()).unwrap().unwrap();
+        assert_eq!(service.name(), "60分コース");
+        assert_eq!(
+            service.extension_price().map(|p| p.amount().amount()),
+            Some(6000)
+        );
+        // 延長価格がないサービスは延長できない
+        let service = config.services.find_by_id(2.into()).unwrap().unwrap();
+        assert_eq!(service.extension_price(), None);
+        assert!(config.services.find_by_id(3.into()).unwrap().is_none());
+    }
+}